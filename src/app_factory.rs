@@ -1,12 +1,13 @@
 use std::sync::Arc;
 
+use actix_cors::Cors;
 use actix_files::Files;
 use actix_session::{config::{PersistentSession, SessionLifecycle}, storage::CookieSessionStore, SessionMiddleware};
-use actix_web::{body::MessageBody, cookie::Key, dev::{ServiceFactory, ServiceRequest, ServiceResponse}, get, web::{self, Data}, App, Error, HttpResponse, Responder};
+use actix_web::{body::MessageBody, cookie::Key, dev::{ServiceFactory, ServiceRequest, ServiceResponse}, get, http::header::{HeaderName, AUTHORIZATION, CONTENT_TYPE}, web::{self, Data, JsonConfig}, App, Error, HttpResponse, Responder};
 use authfix::{multifactor::{config::MfaConfig, factor_impl::authenticator::AuthenticatorFactor}, session::{app_builder::SessionLoginAppBuilder, config::Routes}};
 use serde::Serialize;
 
-use crate::{config::db::DbConfig, controller::{activity_controller, mfa_controller, root_controller}, domain::{user_api::UserApi}, service::{auth_service::{AuthenticationService, HandleMfaRequestImpl}, user_service::UserService}};
+use crate::{config::{config::{Config, CorsConfig}, db::DbConfig}, controller::{account_controller, activity_controller, admin_controller, api_token_controller, calendar_controller, category_controller, exchange_controller, goal_controller, mfa_controller, oidc_controller, report_controller, root_controller, time_entry_controller, trusted_device_controller, webauthn_controller}, domain::{activity_api::ActivityApi, api_token_api::ApiTokenApi, audit_api::AuditApi, auth_api::AuthenticationApi, calendar_api::CalendarApi, category_api::CategoryApi, password_reset_api::PasswordResetApi, email_change_api::EmailChangeApi, email_code_api::EmailCodeApi, exchange_api::ExchangeApi, goal_api::GoalApi, mail_api::MailSender, mfa_policy_api::MfaPolicyApi, notification_api::NotificationApi, report_api::ReportApi, tag_api::TagApi, time_entry_api::TimeEntryApi, trusted_device_api::TrustedDeviceApi, user_api::UserApi, webauthn_api::WebauthnApi}, error::errors::ApiError, middleware::{bearer_auth::BearerTokenAuth, csrf::{CsrfProtection, CSRF_HEADER}, locked_account::LockedAccountGuard, login_failure::LoginFailureSlot, logout_audit::LogoutAudit, mfa_enrollment::MfaEnrollmentGuard, security_headers::SecurityHeaders}, service::{activity_service::ActivityService, api_token_service::ApiTokenService, audit_service::AuditService, auth_service::{AuthenticationService, ExternalLogin, HandleMfaRequestImpl}, calendar_service::CalendarService, category_service::CategoryService, email_change_service::EmailChangeService, email_code_factor::EmailCodeFactor, email_code_service::EmailCodeService, exchange_service::ExchangeService, goal_service::GoalService, mail_service::{FileMailSender, LogMailSender}, mfa_policy_service::{MfaEnrollmentCheck, MfaPolicyService}, notification_service::NotificationService, oidc_service::OidcService, password_policy::PasswordPolicy, password_reset_service::PasswordResetService, report_service::ReportService, tag_service::TagService, time_entry_service::TimeEntryService, trusted_device_service::{TrustedDeviceCookie, TrustedDeviceService}, user_service::UserService, webauthn_factor::WebauthnFactor, webauthn_service::WebauthnService}};
 
 
 pub fn create_test_session_middleware (key: Key) -> SessionMiddleware<CookieSessionStore> {
@@ -32,7 +33,29 @@ async fn test_endpoint() -> impl Responder {
     HttpResponse::Ok().json(TestResponse { test: 42, title: "MyActivities".to_owned() })
 }
 
-pub fn create_app(cookie_key: Key, db_config: DbConfig) -> App<
+/// Malformed JSON bodies are answered with the same problem+json format as all other API errors
+fn json_config() -> JsonConfig {
+    JsonConfig::default()
+        .error_handler(|err, _req| ApiError::Validation(err.to_string()).into())
+}
+
+/// Requests from origins that are not allowed are still processed, browsers block them based on the missing headers
+fn cors(config: &CorsConfig) -> Cors {
+    let cors = config.allowed_origins.iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        .allowed_headers([CONTENT_TYPE, AUTHORIZATION, HeaderName::from_static(CSRF_HEADER)])
+        .expose_headers([HeaderName::from_static(CSRF_HEADER)])
+        .max_age(config.max_age_secs);
+
+    if config.allow_credentials {
+        cors.supports_credentials()
+    } else {
+        cors
+    }
+}
+
+pub fn create_app(cookie_key: Key, db_config: DbConfig, config: Arc<Config>) -> App<
 impl ServiceFactory<
     ServiceRequest,
     Response = ServiceResponse<impl MessageBody>,
@@ -41,27 +64,133 @@ impl ServiceFactory<
     Error = Error,
 >> {
     
-    let user_service= Arc::new(UserService::new(Arc::new(db_config)));
+    let cors = cors(&config.cors);
+    let security_headers = SecurityHeaders::new(&config.content_security_policy);
+    let db_config = Arc::new(db_config);
+    let user_service= Arc::new(UserService::new(Arc::clone(&db_config)).with_password_hash_config(&config.password_hash));
     let user_api: Arc<dyn UserApi> = Arc::clone(&user_service) as Arc<dyn UserApi>;
-    let user_api_data = Data::from(user_api);
+    let user_api_data = Data::from(Arc::clone(&user_api));
+
+    let token_api: Arc<dyn ApiTokenApi> = Arc::new(ApiTokenService::new(Arc::clone(&db_config)));
+    let token_api_data = Data::from(Arc::clone(&token_api));
+
+    let webauthn_api: Arc<dyn WebauthnApi> = Arc::new(WebauthnService::new(Arc::clone(&db_config), config.webauthn.clone()));
+    let webauthn_api_data = Data::from(Arc::clone(&webauthn_api));
+
+    let trusted_device_api: Arc<dyn TrustedDeviceApi> = Arc::new(TrustedDeviceService::new(Arc::clone(&db_config)));
+    let trusted_device_api_data = Data::from(Arc::clone(&trusted_device_api));
+
+    let activity_api: Arc<dyn ActivityApi> = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+    let category_api: Arc<dyn CategoryApi> = Arc::new(CategoryService::new(Arc::clone(&db_config)));
+    let tag_api: Arc<dyn TagApi> = Arc::new(TagService::new(Arc::clone(&db_config)));
+    let time_entry_api: Arc<dyn TimeEntryApi> = Arc::new(TimeEntryService::new(Arc::clone(&db_config)));
+    let calendar_api: Arc<dyn CalendarApi> = Arc::new(CalendarService::new(Arc::clone(&db_config), Arc::clone(&activity_api)));
+    let goal_api: Arc<dyn GoalApi> = Arc::new(GoalService::new(Arc::clone(&db_config), Arc::clone(&activity_api)));
+    let report_api: Arc<dyn ReportApi> = Arc::new(ReportService::new(Arc::clone(&db_config), Arc::clone(&activity_api)));
+    let exchange_api: Arc<dyn ExchangeApi> = Arc::new(ExchangeService::new(Arc::clone(&db_config), Arc::clone(&activity_api)));
+
+    let audit_api: Arc<dyn AuditApi> = Arc::new(AuditService::new(Arc::clone(&db_config)));
+    let audit_api_data = Data::from(Arc::clone(&audit_api));
+
+    let mfa_policy_api: Arc<dyn MfaPolicyApi> = Arc::new(MfaPolicyService::new(Arc::clone(&db_config)));
+    let mfa_policy_api_data = Data::from(Arc::clone(&mfa_policy_api));
+
+    let mail_sender: Arc<dyn MailSender> = match &config.mail.file_dir {
+        Some(dir) => Arc::new(FileMailSender::new(dir, &config.mail)),
+        None => Arc::new(LogMailSender::new(&config.mail)),
+    };
+    let notification_api: Arc<dyn NotificationApi> = Arc::new(NotificationService::new(Arc::clone(&mail_sender), &config.mail));
+    let notification_api_data = Data::from(Arc::clone(&notification_api));
+    let email_code_api: Arc<dyn EmailCodeApi> = Arc::new(EmailCodeService::new(Arc::clone(&db_config), Arc::clone(&mail_sender)));
+    let mail_sender_data = Data::from(Arc::clone(&mail_sender));
+
+    let password_reset_api: Arc<dyn PasswordResetApi> = Arc::new(PasswordResetService::new(Arc::clone(&db_config)));
+    let email_change_api: Arc<dyn EmailChangeApi> = Arc::new(EmailChangeService::new(Arc::clone(&db_config)));
+    let auth_api: Arc<dyn AuthenticationApi> = Arc::new(AuthenticationService::new(Arc::clone(&user_service)));
+    let password_policy = Data::new(PasswordPolicy::new(&config.password_policy));
+
+    let oidc_service = config.oidc.clone()
+        .map(|oidc| Data::new(OidcService::new(oidc, Arc::clone(&user_api))));
 
     let routes = Routes::new("/api", "/login", "/login/mfa", "/logout");
-    let login_handler = AuthenticationService::new(Arc::clone(&user_service));
-    let handle_mfa = HandleMfaRequestImpl::new(Arc::clone(&user_service));
+    let login_handler = AuthenticationService::new(Arc::clone(&user_service))
+        .with_audit_api(Arc::clone(&audit_api))
+        .with_notification_api(notification_api);
+    let login_failure_handler = login_handler.failure_handler();
+    let login_success_handler = login_handler.success_handler(MfaEnrollmentCheck::new(Arc::clone(&mfa_policy_api)));
+    let trusted_device = (config.trusted_device_days > 0)
+        .then(|| TrustedDeviceCookie::new(Arc::clone(&trusted_device_api), &cookie_key, config.trusted_device_days));
+    let handle_mfa = HandleMfaRequestImpl::new(Arc::clone(&user_service), trusted_device)
+        .with_audit_api(Arc::clone(&audit_api));
 
-    let mfa_config = MfaConfig::new(vec![Box::new(AuthenticatorFactor::new(Arc::clone(&user_service)))], handle_mfa);
+    let mfa_config = MfaConfig::new(vec![
+        Box::new(AuthenticatorFactor::new(Arc::clone(&user_service))),
+        Box::new(WebauthnFactor::new(webauthn_api)),
+        Box::new(EmailCodeFactor::new(email_code_api)),
+    ], handle_mfa);
+    let external_login = Data::new(ExternalLogin::new(mfa_config.clone(), login_handler.success_handler(MfaEnrollmentCheck::new(Arc::clone(&mfa_policy_api)))));
     
     SessionLoginAppBuilder::create_with_session_middleware(login_handler, create_test_session_middleware(cookie_key))
-        .set_login_routes_and_public_paths(routes, vec!["/api/test", "/web/index.html"])
+        .set_login_routes_and_public_paths(routes, vec!["/api/test", "/web/index.html", "/api/oidc/login", "/api/oidc/callback", "/api/webauthn/login-options",
+            "/api/register", "/api/password-reset/request", "/api/password-reset/confirm", "/api/calendar/feed/*.ics"])
         .set_mfa(mfa_config)
+        .set_login_failure_handler(login_failure_handler)
+        .set_login_success_handler(login_success_handler)
         .build()
     .service(
         web::scope("/api")
+            .wrap(MfaEnrollmentGuard)
+            .wrap(LockedAccountGuard::new(Arc::clone(&user_api)))
             .service(test_endpoint)
+            .configure(calendar_controller::config)
+            .configure(exchange_controller::config)
             .configure(activity_controller::config)
+            .configure(category_controller::config)
+            .configure(time_entry_controller::config)
+            .configure(report_controller::config)
+            .configure(goal_controller::config)
             .configure(root_controller::config)
             .configure(mfa_controller::config)
+            .configure(admin_controller::config)
+            .configure(api_token_controller::config)
+            .configure(webauthn_controller::config)
+            .configure(trusted_device_controller::config)
+            .configure(account_controller::config)
+            .configure(|cfg| {
+                if let Some(oidc_service) = oidc_service {
+                    cfg.app_data(oidc_service)
+                        .app_data(external_login)
+                        .configure(oidc_controller::config);
+                }
+            })
     )
     .service(Files::new("/web", "./static"))
     .app_data(user_api_data.clone())
+    .app_data(token_api_data)
+    .app_data(Data::from(activity_api))
+    .app_data(Data::from(category_api))
+    .app_data(Data::from(tag_api))
+    .app_data(Data::from(time_entry_api))
+    .app_data(Data::from(calendar_api))
+    .app_data(Data::from(exchange_api))
+    .app_data(Data::from(report_api))
+    .app_data(Data::from(goal_api))
+    .app_data(webauthn_api_data)
+    .app_data(trusted_device_api_data)
+    .app_data(mfa_policy_api_data)
+    .app_data(audit_api_data)
+    .app_data(notification_api_data)
+    .app_data(Data::from(password_reset_api))
+    .app_data(Data::from(email_change_api))
+    .app_data(Data::from(auth_api))
+    .app_data(mail_sender_data)
+    .app_data(password_policy)
+    .app_data(Data::from(config))
+    .app_data(json_config())
+    .wrap(LogoutAudit::new(audit_api))
+    .wrap(LoginFailureSlot)
+    .wrap(CsrfProtection)
+    .wrap(BearerTokenAuth::new(token_api, user_api, mfa_policy_api))
+    .wrap(security_headers)
+    .wrap(cors)
 }
\ No newline at end of file
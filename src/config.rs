@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod config;
 pub mod db;
\ No newline at end of file
@@ -1,9 +1,260 @@
 const DEFAULT_HOST: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 5665;
+const DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+const DEFAULT_OIDC_POST_LOGIN_REDIRECT: &str = "/";
+const DEFAULT_OIDC_MFA_REDIRECT: &str = "/login";
+const DEFAULT_WEBAUTHN_RP_ID: &str = "localhost";
+const DEFAULT_WEBAUTHN_RP_NAME: &str = "MyActivities";
+const DEFAULT_TRUSTED_DEVICE_DAYS: u32 = 30;
+const DEFAULT_PASSWORD_MIN_LENGTH: usize = 10;
+const DEFAULT_PUBLIC_URL: &str = "http://localhost:5665";
+const DEFAULT_MAIL_FROM: &str = "MyActivities <no-reply@localhost>";
+const DEFAULT_CORS_ALLOWED_METHODS: &str = "GET,POST,PUT,DELETE";
+const DEFAULT_CORS_MAX_AGE_SECS: usize = 3600;
+const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; object-src 'none'; base-uri 'self'; frame-ancestors 'none'";
 
+#[derive(Clone)]
 pub struct Config {
     pub host: String,
     pub port: u16,
+    pub oidc: Option<OidcConfig>,
+    pub webauthn: WebauthnConfig,
+    pub mail: MailConfig,
+    /// Days a browser skips the second factor after a successful MFA login, 0 disables trusted devices
+    pub trusted_device_days: u32,
+    pub password_policy: PasswordPolicyConfig,
+    pub password_hash: PasswordHashConfig,
+    pub cors: CorsConfig,
+    /// Sent with every response, `frame-ancestors` also prevents clickjacking
+    pub content_security_policy: String,
+}
+
+/// Login with an external OpenID Connect provider. Only active if `MA_OIDC_ISSUER` is set.
+#[derive(Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+    /// Where the browser is sent after a successful login
+    pub post_login_redirect: String,
+    /// Where the browser is sent if a second factor is required, `mfa_id` is appended as query parameter
+    pub mfa_redirect: String,
+}
+
+impl OidcConfig {
+    fn from_env() -> Option<Self> {
+        let issuer = std::env::var("MA_OIDC_ISSUER").ok()?;
+
+        Some(OidcConfig {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            client_id: std::env::var("MA_OIDC_CLIENT_ID").expect("MA_OIDC_CLIENT_ID must be set if MA_OIDC_ISSUER is set"),
+            client_secret: std::env::var("MA_OIDC_CLIENT_SECRET").ok(),
+            redirect_uri: std::env::var("MA_OIDC_REDIRECT_URI").expect("MA_OIDC_REDIRECT_URI must be set if MA_OIDC_ISSUER is set"),
+            scopes: std::env::var("MA_OIDC_SCOPES").unwrap_or_else(|_| DEFAULT_OIDC_SCOPES.to_owned()),
+            post_login_redirect: std::env::var("MA_OIDC_POST_LOGIN_REDIRECT").unwrap_or_else(|_| DEFAULT_OIDC_POST_LOGIN_REDIRECT.to_owned()),
+            mfa_redirect: std::env::var("MA_OIDC_MFA_REDIRECT").unwrap_or_else(|_| DEFAULT_OIDC_MFA_REDIRECT.to_owned()),
+        })
+    }
+}
+
+/// Relying party settings for WebAuthn. Passkeys are bound to `rp_id`, so changing it invalidates all registered keys.
+#[derive(Clone)]
+pub struct WebauthnConfig {
+    pub rp_id: String,
+    pub rp_name: String,
+    /// Origin the browser reports in the client data, e.g. `https://activities.example.org`
+    pub origin: String,
+}
+
+impl WebauthnConfig {
+    fn new(rp_id: &str, origin: &str) -> Self {
+        WebauthnConfig {
+            rp_id: rp_id.to_owned(),
+            rp_name: DEFAULT_WEBAUTHN_RP_NAME.to_owned(),
+            origin: origin.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    fn from_env(port: u16) -> Self {
+        let rp_id = std::env::var("MA_WEBAUTHN_RP_ID").unwrap_or_else(|_| DEFAULT_WEBAUTHN_RP_ID.to_owned());
+        let origin = std::env::var("MA_WEBAUTHN_ORIGIN").unwrap_or_else(|_| format!("http://{}:{}", rp_id, port));
+
+        WebauthnConfig::new(&rp_id, &origin)
+    }
+}
+
+#[derive(Clone)]
+pub struct MailConfig {
+    /// Sender address of all mails
+    pub from: String,
+    /// Base of links in mails, e.g. `https://activities.example.org`
+    pub public_url: String,
+    /// Writes mails as `.eml` files into this directory instead of the log, for local testing
+    pub file_dir: Option<String>,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            from: DEFAULT_MAIL_FROM.to_owned(),
+            public_url: DEFAULT_PUBLIC_URL.to_owned(),
+            file_dir: None,
+        }
+    }
+}
+
+impl MailConfig {
+    fn from_env() -> Self {
+        MailConfig {
+            from: std::env::var("MA_MAIL_FROM").unwrap_or_else(|_| DEFAULT_MAIL_FROM.to_owned()),
+            public_url: std::env::var("MA_PUBLIC_URL").unwrap_or_else(|_| DEFAULT_PUBLIC_URL.to_owned()).trim_end_matches('/').to_owned(),
+            file_dir: std::env::var("MA_MAIL_FILE_DIR").ok(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PasswordPolicyConfig {
+    pub min_length: usize,
+    /// Rejects passwords that contain the email address or the name of the user
+    pub disallow_personal_info: bool,
+    /// File with one blocked password per line, used in addition to the bundled list
+    pub blocklist_file: Option<String>,
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        PasswordPolicyConfig {
+            min_length: DEFAULT_PASSWORD_MIN_LENGTH,
+            disallow_personal_info: true,
+            blocklist_file: None,
+        }
+    }
+}
+
+impl PasswordPolicyConfig {
+    fn from_env() -> Self {
+        PasswordPolicyConfig {
+            min_length: match std::env::var("MA_PASSWORD_MIN_LENGTH") {
+                Ok(l) => l.parse().expect("MA_PASSWORD_MIN_LENGTH must be of type usize"),
+                Err(_) => DEFAULT_PASSWORD_MIN_LENGTH
+            },
+            disallow_personal_info: match std::env::var("MA_PASSWORD_DISALLOW_PERSONAL_INFO") {
+                Ok(d) => d.parse().expect("MA_PASSWORD_DISALLOW_PERSONAL_INFO must be true or false"),
+                Err(_) => true
+            },
+            blocklist_file: std::env::var("MA_PASSWORD_BLOCKLIST").ok(),
+        }
+    }
+}
+
+/// Argon2id cost parameters for new password hashes.
+/// Hashes with weaker parameters are upgraded on the next successful login.
+#[derive(Clone)]
+pub struct PasswordHashConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashConfig {
+    fn default() -> Self {
+        PasswordHashConfig {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl PasswordHashConfig {
+    fn from_env() -> Self {
+        let defaults = PasswordHashConfig::default();
+
+        PasswordHashConfig {
+            memory_kib: match std::env::var("MA_ARGON2_MEMORY_KIB") {
+                Ok(m) => m.parse().expect("MA_ARGON2_MEMORY_KIB must be of type u32"),
+                Err(_) => defaults.memory_kib
+            },
+            iterations: match std::env::var("MA_ARGON2_ITERATIONS") {
+                Ok(i) => i.parse().expect("MA_ARGON2_ITERATIONS must be of type u32"),
+                Err(_) => defaults.iterations
+            },
+            parallelism: match std::env::var("MA_ARGON2_PARALLELISM") {
+                Ok(p) => p.parse().expect("MA_ARGON2_PARALLELISM must be of type u32"),
+                Err(_) => defaults.parallelism
+            },
+        }
+    }
+}
+
+/// Cross-origin access for frontends that are not served by this app. Without allowed origins no CORS headers are sent.
+#[derive(Clone)]
+pub struct CorsConfig {
+    /// Full origins like `https://app.example.org`
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Allows the session cookie in cross-origin requests
+    pub allow_credentials: bool,
+    pub max_age_secs: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec![],
+            allowed_methods: split_list(DEFAULT_CORS_ALLOWED_METHODS),
+            allow_credentials: false,
+            max_age_secs: DEFAULT_CORS_MAX_AGE_SECS,
+        }
+    }
+}
+
+impl CorsConfig {
+    fn from_env() -> Self {
+        CorsConfig {
+            allowed_origins: std::env::var("MA_CORS_ALLOWED_ORIGINS")
+                .map(|origins| split_list(&origins).iter().map(|o| o.trim_end_matches('/').to_owned()).collect())
+                .unwrap_or_default(),
+            allowed_methods: split_list(&std::env::var("MA_CORS_ALLOWED_METHODS").unwrap_or_else(|_| DEFAULT_CORS_ALLOWED_METHODS.to_owned())),
+            allow_credentials: match std::env::var("MA_CORS_ALLOW_CREDENTIALS") {
+                Ok(c) => c.parse().expect("MA_CORS_ALLOW_CREDENTIALS must be true or false"),
+                Err(_) => false
+            },
+            max_age_secs: match std::env::var("MA_CORS_MAX_AGE") {
+                Ok(m) => m.parse().expect("MA_CORS_MAX_AGE must be of type usize"),
+                Err(_) => DEFAULT_CORS_MAX_AGE_SECS
+            },
+        }
+    }
+}
+
+/// Splits a comma separated environment value
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: DEFAULT_HOST.to_string(),
+            port: DEFAULT_PORT,
+            oidc: None,
+            webauthn: WebauthnConfig::new(DEFAULT_WEBAUTHN_RP_ID, &format!("http://{}:{}", DEFAULT_WEBAUTHN_RP_ID, DEFAULT_PORT)),
+            mail: MailConfig::default(),
+            trusted_device_days: DEFAULT_TRUSTED_DEVICE_DAYS,
+            password_policy: PasswordPolicyConfig::default(),
+            password_hash: PasswordHashConfig::default(),
+            cors: CorsConfig::default(),
+            content_security_policy: DEFAULT_CONTENT_SECURITY_POLICY.to_owned(),
+        }
+    }
 }
 
 impl Config {
@@ -18,9 +269,22 @@ impl Config {
             Err(_) => DEFAULT_PORT
         };
 
+        let trusted_device_days: u32 = match std::env::var("MA_TRUSTED_DEVICE_DAYS") {
+            Ok(d) => d.parse().expect("MA_TRUSTED_DEVICE_DAYS must be of type u32"),
+            Err(_) => DEFAULT_TRUSTED_DEVICE_DAYS
+        };
+
         Config {
             host,
             port,
+            oidc: OidcConfig::from_env(),
+            webauthn: WebauthnConfig::from_env(port),
+            mail: MailConfig::from_env(),
+            trusted_device_days,
+            password_policy: PasswordPolicyConfig::from_env(),
+            password_hash: PasswordHashConfig::from_env(),
+            cors: CorsConfig::from_env(),
+            content_security_policy: std::env::var("MA_CONTENT_SECURITY_POLICY").unwrap_or_else(|_| DEFAULT_CONTENT_SECURITY_POLICY.to_owned()),
         }
     }
 }
@@ -35,7 +299,11 @@ mod tests {
 
         assert_eq!(c.host, "127.0.0.1".to_string());
         assert_eq!(c.port, 5665);
+        assert!(c.oidc.is_none());
+        assert_eq!(c.webauthn.rp_id, "localhost".to_string());
+        assert_eq!(c.webauthn.origin, "http://localhost:5665".to_string());
+        assert!(c.cors.allowed_origins.is_empty());
+        assert_eq!(c.cors.allowed_methods, vec!["GET", "POST", "PUT", "DELETE"]);
     }
 
 }
-
@@ -1,3 +1,18 @@
 pub mod activity_controller;
 pub mod root_controller;
-pub mod mfa_controller;
\ No newline at end of file
+pub mod mfa_controller;
+pub mod admin_controller;
+pub mod role_guard;
+pub mod api_token_controller;
+pub mod oidc_controller;
+pub mod webauthn_controller;
+pub mod trusted_device_controller;
+pub mod account_controller;
+pub mod security_events;
+pub mod category_controller;
+pub mod time_entry_controller;
+pub mod calendar_controller;
+pub mod exchange_controller;
+pub mod pagination;
+pub mod report_controller;
+pub mod goal_controller;
\ No newline at end of file
@@ -0,0 +1,392 @@
+use actix_session::Session;
+use actix_web::{get, post, put, web::{Data, Json, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+
+use crate::{config::config::Config, controller::security_events::SecurityEvents, domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditFilter, AuditOutcome, NewAuditEvent}, auth_api::AuthenticationApi, email_change_api::EmailChangeApi, mail::Mail, mail_api::MailSender, password_reset_api::PasswordResetApi, security_notification::SecurityNotification, user::User, user_api::UserApi}, error::errors::{ApiError, FieldError, QueryUserError}, service::{auth_service::AUTHFIX_SESSION_KEY_USER, password_policy::PasswordPolicy}};
+
+const PASSWORD_RESET_VALIDITY_MINUTES: i64 = 60;
+const EMAIL_CHANGE_VALIDITY_MINUTES: i64 = 60;
+const MAX_NAME_LENGTH: usize = 100;
+const SECURITY_EVENTS_LIMIT: u32 = 100;
+
+#[derive(Deserialize)]
+struct RegisterRequest {
+    email: String,
+    name: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+struct ChangeEmailRequest {
+    current_password: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct ConfirmEmailChangeRequest {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct PasswordResetRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct ConfirmPasswordResetRequest {
+    token: String,
+    new_password: String,
+}
+
+/// Public path: creates a user with the default role
+#[post("/register")]
+async fn register(body: Json<RegisterRequest>, policy: Data<PasswordPolicy>, user_api: Data<dyn UserApi>, events: SecurityEvents)
+    -> Result<impl Responder, ApiError>
+{
+    let body = body.into_inner();
+    let email = body.email.trim().to_lowercase();
+    let name = body.name.trim().to_owned();
+
+    let mut errors = Vec::new();
+    if !email.contains('@') {
+        errors.push(FieldError::new("email", "invalid", "Email address is invalid."));
+    }
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        errors.push(FieldError::new("name", "invalid_length", &format!("Name must have between 1 and {} characters.", MAX_NAME_LENGTH)));
+    }
+    if let Err(password_errors) = policy.validate("password", &body.password, &email, &name) {
+        errors.extend(password_errors);
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::InvalidFields(errors));
+    }
+
+    let user = user_api.save_user_with_credentials(User::new(0, email, name), &body.password).await?;
+    events.record(NewAuditEvent::new(AuditEventType::Registration, AuditOutcome::Success).user(user.id)).await;
+
+    Ok(HttpResponse::Created().json(user))
+}
+
+/// Revokes all API tokens and trusted devices of the user
+#[put("/account/password")]
+async fn change_password(body: Json<ChangePasswordRequest>, token: AuthToken<User>, policy: Data<PasswordPolicy>,
+    auth_api: Data<dyn AuthenticationApi>, user_api: Data<dyn UserApi>, events: SecurityEvents)
+    -> Result<impl Responder, ApiError>
+{
+    let user = token.authenticated_user();
+    events.confirm_password(auth_api.get_ref(), &user, &body.current_password, AuditEventType::PasswordChange).await?;
+
+    policy.validate("new_password", &body.new_password, &user.email, &user.name)
+        .map_err(ApiError::InvalidFields)?;
+    user_api.update_password(user.id, &body.new_password).await?;
+    events.record(NewAuditEvent::new(AuditEventType::PasswordChange, AuditOutcome::Success).user(user.id)).await;
+    events.notify(&user, SecurityNotification::PasswordChanged).await;
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Needs the password, a stolen session must not be enough to take over the account.
+/// The address is only changed once the link sent to it was opened, see [confirm_email_change].
+#[put("/account/email")]
+async fn change_email(body: Json<ChangeEmailRequest>, token: AuthToken<User>, config: Data<Config>, auth_api: Data<dyn AuthenticationApi>,
+    email_change_api: Data<dyn EmailChangeApi>, mail_sender: Data<dyn MailSender>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user = token.authenticated_user();
+    let email = body.email.trim().to_lowercase();
+    if !email.contains('@') {
+        return Err(ApiError::InvalidFields(vec![FieldError::new("email", "invalid", "Email address is invalid.")]));
+    }
+    events.confirm_password(auth_api.get_ref(), &user, &body.current_password, AuditEventType::EmailChange).await?;
+
+    let token = email_change_api.create_token(user.id, &email, Utc::now() + Duration::minutes(EMAIL_CHANGE_VALIDITY_MINUTES)).await?;
+    let body = format!(
+        "Hello {},\n\nopen {}/web/index.html#confirm-email?token={} while logged in to use this address for your account. The link is valid for {} minutes.\n\nIf you did not change your address, ignore this mail.",
+        user.name, config.mail.public_url, token, EMAIL_CHANGE_VALIDITY_MINUTES
+    );
+    mail_sender.send(Mail::new(&email, "Confirm your new MyActivities address", &body)).await
+        .map_err(|e| ApiError::Internal(format!("Cannot send confirmation mail: {}", e)))?;
+    events.record(NewAuditEvent::new(AuditEventType::EmailChangeRequest, AuditOutcome::Success).user(user.id).detail(&email)).await;
+
+    Ok(HttpResponse::Accepted())
+}
+
+/// Switches to the address of the link, only for the user who requested it. The previous address is notified.
+#[post("/account/email/confirm")]
+async fn confirm_email_change(body: Json<ConfirmEmailChangeRequest>, token: AuthToken<User>, session: Session,
+    email_change_api: Data<dyn EmailChangeApi>, user_api: Data<dyn UserApi>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user = token.authenticated_user();
+    let email = email_change_api.take_email(user.id, &body.token).await?;
+
+    let updated = user_api.update_email(user.id, &email).await?;
+    // authfix reads the user from the session, it has to see the new address
+    session.insert(AUTHFIX_SESSION_KEY_USER, &updated)?;
+    events.record(NewAuditEvent::new(AuditEventType::EmailChange, AuditOutcome::Success).user(user.id)).await;
+    events.notify(&user, SecurityNotification::EmailChanged { new_email: updated.email.clone() }).await;
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+/// Public path: always answers with 204, so the endpoint does not reveal which addresses have an account
+#[post("/password-reset/request")]
+async fn request_password_reset(body: Json<PasswordResetRequest>, config: Data<Config>, user_api: Data<dyn UserApi>,
+    reset_api: Data<dyn PasswordResetApi>, mail_sender: Data<dyn MailSender>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user = match user_api.find_by_email(&body.email.trim().to_lowercase()).await {
+        Ok(user) if !user.locked => user,
+        Ok(_) | Err(QueryUserError::NotFound) => return Ok(HttpResponse::NoContent()),
+        Err(e) => return Err(e.into()),
+    };
+
+    events.record(NewAuditEvent::new(AuditEventType::PasswordResetRequest, AuditOutcome::Success).user(user.id)).await;
+    let token = reset_api.create_token(user.id, Utc::now() + Duration::minutes(PASSWORD_RESET_VALIDITY_MINUTES)).await?;
+    let body = format!(
+        "Hello {},\n\nopen {}/web/index.html#reset-password?token={} to choose a new password. The link is valid for {} minutes.\n\nIf you did not request a new password, ignore this mail.",
+        user.name, config.mail.public_url, token, PASSWORD_RESET_VALIDITY_MINUTES
+    );
+    if let Err(e) = mail_sender.send(Mail::new(&user.email, "Reset your MyActivities password", &body)).await {
+        log::error!("Cannot send password reset mail to user {}: {}", user.id, e);
+    }
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Public path: sets the new password and invalidates all reset tokens, API tokens and trusted devices of the user
+#[post("/password-reset/confirm")]
+async fn confirm_password_reset(body: Json<ConfirmPasswordResetRequest>, policy: Data<PasswordPolicy>, user_api: Data<dyn UserApi>,
+    reset_api: Data<dyn PasswordResetApi>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user_id = reset_api.find_user_id(&body.token).await?;
+    let user = user_api.find_by_id(user_id).await?;
+
+    policy.validate("new_password", &body.new_password, &user.email, &user.name)
+        .map_err(ApiError::InvalidFields)?;
+    user_api.update_password(user.id, &body.new_password).await?;
+    reset_api.delete_tokens(user.id).await?;
+    events.record(NewAuditEvent::new(AuditEventType::PasswordReset, AuditOutcome::Success).user(user.id)).await;
+    events.notify(&user, SecurityNotification::PasswordChanged).await;
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// The latest security events of the logged in user
+#[get("/account/security-events")]
+async fn security_events(token: AuthToken<User>, audit_api: Data<dyn AuditApi>) -> Result<impl Responder, ApiError> {
+    let filter = AuditFilter {
+        user_id: Some(token.authenticated_user().id),
+        limit: Some(SECURITY_EVENTS_LIMIT),
+        ..Default::default()
+    };
+    let events = audit_api.find(&filter).await?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(register)
+    .service(change_password)
+    .service(change_email)
+    .service(confirm_email_change)
+    .service(request_password_reset)
+    .service(confirm_password_reset)
+    .service(security_events);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::Key, http::StatusCode, test};
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{api_token::TokenScope, api_token_api::ApiTokenApi, password_reset_api::PasswordResetApi, trusted_device_api::TrustedDeviceApi, user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::{api_token_service::ApiTokenService, password_reset_service::PasswordResetService, trusted_device_service::TrustedDeviceService, user_service::UserService}};
+
+    const DB: &str = "file:account_controller_test?mode=memory&cache=shared";
+    const AUDIT_DB: &str = "file:account_controller_audit_test?mode=memory&cache=shared";
+    const NOTIFICATION_DB: &str = "file:account_controller_notification_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_enforce_password_policy_on_register_change_and_reset() {
+        let _db = create_db(&DbConfig::new(DB));
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/register")
+            .set_json(json!({ "email": "hans@example.org", "name": "Hans", "password": "hans1234" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        let codes: Vec<&str> = problem["errors"].as_array().unwrap().iter().map(|e| e["code"].as_str().unwrap()).collect();
+        assert_eq!(codes, vec!["too_short", "contains_personal_info"]);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/register")
+            .set_json(json!({ "email": "hans@example.org", "name": "Hans", "password": "correct horse battery" }))
+            .to_request();
+        let user: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(user["email"], "hans@example.org");
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "hans@example.org", "password": "correct horse battery" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/account/password").cookie(session.clone())
+            .set_json(json!({ "current_password": "correct horse battery", "new_password": "password1" }))
+            .to_request();
+        let problem: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(problem["errors"][0]["field"], "new_password");
+        assert_eq!(problem["errors"][1]["code"], "common");
+
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let user_id = user_service.find_by_email("hans@example.org").await.unwrap().id;
+        let token_service = ApiTokenService::new(Arc::new(DbConfig::new(DB)));
+        let device_service = TrustedDeviceService::new(Arc::new(DbConfig::new(DB)));
+        token_service.create_token(user_id, "script", vec![TokenScope::Read], Utc::now() + Duration::days(1)).await.unwrap();
+        device_service.create(user_id, Utc::now() + Duration::days(1)).await.unwrap();
+
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/account/password").cookie(session)
+            .set_json(json!({ "current_password": "correct horse battery", "new_password": "staple in the stable" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        assert!(token_service.find_by_user_id(user_id).await.unwrap().is_empty());
+        assert!(device_service.find_by_user_id(user_id).await.unwrap().is_empty());
+
+        token_service.create_token(user_id, "script", vec![TokenScope::Read], Utc::now() + Duration::days(1)).await.unwrap();
+        device_service.create(user_id, Utc::now() + Duration::days(1)).await.unwrap();
+        // the mail is only logged, so the token is created directly
+        let token = PasswordResetService::new(Arc::new(DbConfig::new(DB))).create_token(user_id, Utc::now() + Duration::minutes(5)).await.unwrap();
+        let confirm = json!({ "token": token, "new_password": "a brand new passphrase" });
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/password-reset/confirm").set_json(&confirm).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+        assert!(token_service.find_by_user_id(user_id).await.unwrap().is_empty());
+        assert!(device_service.find_by_user_id(user_id).await.unwrap().is_empty());
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/password-reset/confirm").set_json(&confirm).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "Hans@Example.org", "password": "a brand new passphrase" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn should_list_own_security_events() {
+        let _db = create_db(&DbConfig::new(AUDIT_DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(AUDIT_DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(AUDIT_DB), Arc::new(Config::default()))).await;
+        let login = |password: &str| with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .insert_header(("User-Agent", "Firefox"))
+            .set_json(json!({ "email": "test@example.org", "password": password }))
+            .to_request();
+
+        assert_eq!(test::call_service(&app, login("wrong")).await.status(), StatusCode::UNAUTHORIZED);
+        let res = test::call_service(&app, login("test123")).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/logout").cookie(session).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let res = test::call_service(&app, login("test123")).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+        let req = test::TestRequest::get().uri("/api/account/security-events").cookie(session).to_request();
+        let events: Value = test::call_and_read_body_json(&app, req).await;
+
+        let events: Vec<(&str, &str)> = events.as_array().unwrap().iter()
+            .map(|e| (e["event_type"].as_str().unwrap(), e["outcome"].as_str().unwrap()))
+            .collect();
+        assert_eq!(events, vec![("login", "success"), ("logout", "success"), ("login", "success"), ("login", "failure")]);
+    }
+
+    #[actix_web::test]
+    async fn should_notify_about_new_device_and_password_change() {
+        let _db = create_db(&DbConfig::new(NOTIFICATION_DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(NOTIFICATION_DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("ma-notification-mails-{}", std::process::id()));
+        let mut config = Config::default();
+        config.mail.file_dir = Some(dir.to_str().unwrap().to_owned());
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(NOTIFICATION_DB), Arc::new(config))).await;
+        let login = |user_agent: &str| with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .insert_header(("User-Agent", user_agent.to_owned()))
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+
+        test::call_service(&app, login("Firefox")).await;
+        test::call_service(&app, login("Firefox")).await;
+        let res = test::call_service(&app, login("Chrome")).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/account/password").cookie(session)
+            .set_json(json!({ "current_password": "test123", "new_password": "staple in the stable" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+
+        let mut mails = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        mails.sort();
+        let mails = mails.iter().map(|path| std::fs::read_to_string(path).unwrap()).collect::<Vec<_>>();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mails.len(), 2);
+        assert!(mails[0].contains("Subject: New login to your MyActivities account") && mails[0].contains("Browser: Chrome"));
+        assert!(mails[1].contains("Subject: Your MyActivities password was changed"));
+    }
+
+    #[actix_web::test]
+    async fn should_change_email_only_after_confirmation() {
+        const DB: &str = "file:account_controller_email_test?mode=memory&cache=shared";
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("ma-email-change-mails-{}", std::process::id()));
+        let mut config = Config::default();
+        config.mail.file_dir = Some(dir.to_str().unwrap().to_owned());
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(config))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/account/email").cookie(session.clone())
+            .set_json(json!({ "current_password": "test123", "email": "New@Example.org" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::ACCEPTED);
+        assert_eq!(user_service.find_by_email("test@example.org").await.unwrap().name, "Hans");
+
+        let mail = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .find(|mail| mail.contains("To: new@example.org"))
+            .unwrap();
+        let token = mail.split("token=").nth(1).unwrap().split_whitespace().next().unwrap().to_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/account/email/confirm").cookie(session.clone())
+            .set_json(json!({ "token": "wrong" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/account/email/confirm").cookie(session)
+            .set_json(json!({ "token": token }))
+            .to_request();
+        let user: Value = test::call_and_read_body_json(&app, req).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(user["email"], "new@example.org");
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "new@example.org", "password": "test123" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+}
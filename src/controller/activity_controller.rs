@@ -1,13 +1,275 @@
-use actix_web::{get, web::ServiceConfig, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web::{Data, Json, Path, Query, ServiceConfig}, HttpResponse, Responder};
 use authfix::AuthToken;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 
-use crate::domain::user::User;
+use crate::{controller::pagination::ListQuery, domain::{activity::{ActivityFilter, ActivitySearch, ActivitySort, NewActivity, NewActivityException}, activity_api::ActivityApi, user::User}, error::errors::{ActivityError, ApiError}};
 
+/// Lists are comma separated, e.g. `?category=1,2&tag=morning,outdoor`.
+/// The range, order and page are given by the [ListQuery].
+#[derive(Deserialize)]
+struct ActivityQuery {
+    /// Any of these category ids
+    category: Option<String>,
+    /// All of these tags
+    tag: Option<String>,
+    /// At least one of these tags
+    any_tag: Option<String>,
+}
+
+fn split_list(value: &Option<String>) -> Vec<String> {
+    value.iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl TryFrom<ActivityQuery> for ActivityFilter {
+    type Error = ApiError;
+
+    fn try_from(query: ActivityQuery) -> Result<Self, Self::Error> {
+        let category_ids = split_list(&query.category).iter()
+            .map(|id| id.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ApiError::Validation("category must be a comma separated list of category ids".to_owned()))?;
+
+        Ok(ActivityFilter {
+            category_ids,
+            all_tags: split_list(&query.tag),
+            any_tags: split_list(&query.any_tag),
+            ..ActivityFilter::default()
+        })
+    }
+}
+
+/// Paged, e.g. `?sort=-start&limit=20&from=2025-03-01T00:00:00Z&to=2025-04-01T00:00:00Z`
 #[get("/activities")]
-pub async fn activities(token: AuthToken<User>) -> impl Responder {
-    HttpResponse::Ok().body(format!("my activities. Request from user: {}", token.authenticated_user().email))
+async fn list_activities(list: ListQuery<ActivitySort>, query: Query<ActivityQuery>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let filter = ActivityFilter { from: list.from, to: list.to, ..ActivityFilter::try_from(query.into_inner())? };
+    let activities = activity_api.find_page(token.authenticated_user().id, &filter, &list.page).await?;
+
+    Ok(list.response(activities))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Registered before the routes with an activity id
+#[get("/activities/search")]
+async fn search_activities(query: Query<SearchQuery>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    let search = ActivitySearch::new(&query.q, query.limit).map_err(ActivityError::Invalid)?;
+    let hits = activity_api.search(token.authenticated_user().id, &search).await?;
+
+    Ok(HttpResponse::Ok().json(hits))
+}
+
+#[post("/activities")]
+async fn create_activity(body: Json<NewActivity>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    let activity = activity_api.create(token.authenticated_user().id, body.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(activity))
+}
+
+#[get("/activities/{activity_id}")]
+async fn get_activity(activity_id: Path<i32>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    let activity = activity_api.find_by_id(token.authenticated_user().id, activity_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(activity))
+}
+
+#[put("/activities/{activity_id}")]
+async fn update_activity(activity_id: Path<i32>, body: Json<NewActivity>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let activity = activity_api.update(token.authenticated_user().id, activity_id.into_inner(), body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(activity))
+}
+
+#[delete("/activities/{activity_id}")]
+async fn delete_activity(activity_id: Path<i32>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    activity_api.delete(token.authenticated_user().id, activity_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+#[get("/activities/{activity_id}/exceptions")]
+async fn list_exceptions(activity_id: Path<i32>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    let exceptions = activity_api.find_exceptions(token.authenticated_user().id, activity_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(exceptions))
+}
+
+/// The occurrence is identified by its original start, e.g. `/activities/1/exceptions/2025-03-10T07:00:00Z`
+#[put("/activities/{activity_id}/exceptions/{occurrence}")]
+async fn save_exception(path: Path<(i32, DateTime<Utc>)>, body: Json<NewActivityException>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let (activity_id, occurrence) = path.into_inner();
+    let exception = activity_api.save_exception(token.authenticated_user().id, activity_id, occurrence, body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(exception))
+}
+
+#[delete("/activities/{activity_id}/exceptions/{occurrence}")]
+async fn delete_exception(path: Path<(i32, DateTime<Utc>)>, token: AuthToken<User>, activity_api: Data<dyn ActivityApi>) -> Result<impl Responder, ApiError> {
+    let (activity_id, occurrence) = path.into_inner();
+    activity_api.delete_exception(token.authenticated_user().id, activity_id, occurrence).await?;
+
+    Ok(HttpResponse::NoContent())
 }
 
 pub fn config(cfg: &mut ServiceConfig) {
-    cfg.service(activities);
-}
\ No newline at end of file
+    cfg.service(list_activities)
+    .service(search_activities)
+    .service(create_activity)
+    .service(get_activity)
+    .service(update_activity)
+    .service(delete_activity)
+    .service(list_exceptions)
+    .service(save_exception)
+    .service(delete_exception);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::StatusCode, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:activity_controller_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_manage_activities_with_categories_and_tags() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session: Cookie = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/categories").cookie(session.clone())
+            .set_json(json!({ "name": "Sport", "color": "#00aa00", "icon": "run" }))
+            .to_request();
+        let category: Value = test::call_and_read_body_json(&app, req).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Run", "start": "2025-03-01T07:00:00Z", "end": "2025-03-01T08:00:00Z", "category_id": category["id"], "tags": ["outdoor", "morning"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let run: Value = test::read_body_json(res).await;
+        assert_eq!(run["tags"], json!(["morning", "outdoor"]));
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Read", "start": "2025-03-02T20:00:00Z", "tags": ["evening"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": " ", "start": "2025-03-02T20:00:00Z", "end": "2025-03-02T19:00:00Z" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["errors"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::get().uri(&format!("/api/activities?category={}&tag=Morning", category["id"])).cookie(session.clone()).to_request();
+        let activities: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(activities["items"].as_array().unwrap().len(), 1);
+        assert_eq!(activities["items"][0]["title"], "Run");
+        assert_eq!(activities["next_cursor"], Value::Null);
+
+        let req = test::TestRequest::get().uri("/api/activities?any_tag=evening,outdoor").cookie(session.clone()).to_request();
+        let activities: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(activities["items"].as_array().unwrap().len(), 2);
+
+        let req = test::TestRequest::get().uri("/api/activities?sort=title&limit=1").cookie(session.clone()).to_request();
+        let first: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(first["items"][0]["title"], "Read");
+        let req = test::TestRequest::get().uri(first["next"].as_str().unwrap()).cookie(session.clone()).to_request();
+        let second: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(second["items"][0]["title"], "Run");
+        assert_eq!(second["next"], Value::Null);
+
+        let req = test::TestRequest::get().uri("/api/activities?sort=-duration").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri("/api/activities?category=sport").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri("/api/tags").cookie(session.clone()).to_request();
+        let tags: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(tags.as_array().unwrap().len(), 3);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Yoga", "start": "2025-03-03T18:00:00Z", "end": "2025-03-03T19:00:00Z", "recurrence": "freq=weekly;byday=mo,th;count=4" }))
+            .to_request();
+        let yoga: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(yoga["recurrence"], "FREQ=WEEKLY;BYDAY=MO,TH;COUNT=4");
+
+        let req = with_csrf_token(test::TestRequest::put()).uri(&format!("/api/activities/{}/exceptions/2025-03-06T18:00:00Z", yoga["id"])).cookie(session.clone())
+            .set_json(json!({ "skipped": true }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = with_csrf_token(test::TestRequest::put()).uri(&format!("/api/activities/{}/exceptions/2025-03-07T18:00:00Z", yoga["id"])).cookie(session.clone())
+            .set_json(json!({ "skipped": true }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/api/activities?from=2025-03-03T00:00:00Z&to=2025-04-01T00:00:00Z&limit=2").cookie(session.clone()).to_request();
+        let first: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get().uri(first["next"].as_str().unwrap()).cookie(session.clone()).to_request();
+        let second: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(second["next_cursor"], Value::Null);
+        let occurrences = first["items"].as_array().unwrap().iter()
+            .chain(second["items"].as_array().unwrap())
+            .filter(|a| a["id"] == yoga["id"])
+            .map(|a| a["occurrence"].as_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(occurrences, vec!["2025-03-13T18:00:00Z", "2025-03-10T18:00:00Z", "2025-03-03T18:00:00Z"]);
+
+        let req = test::TestRequest::get().uri("/api/activities?from=2025-04-01T00:00:00Z&to=2025-03-03T00:00:00Z").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri("/api/activities/search?q=outd").cookie(session.clone()).to_request();
+        let hits: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(hits.as_array().unwrap().len(), 1);
+        assert_eq!(hits[0]["id"], run["id"]);
+        assert_eq!(hits[0]["snippet"], "morning <mark>outdoor</mark>");
+
+        let req = test::TestRequest::get().uri("/api/activities/search?q=%3F").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri(&format!("/api/activities/{}", run["id"])).cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri(&format!("/api/activities/{}", run["id"])).cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
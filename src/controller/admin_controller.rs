@@ -0,0 +1,99 @@
+use actix_web::{get, post, put, web::{Data, Json, Path, Query, ServiceConfig}, HttpResponse, Responder};
+
+use crate::{controller::{pagination::ListQuery, role_guard::{Admin, RoleToken}, security_events::SecurityEvents}, domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditFilter, AuditOutcome, NewAuditEvent}, mfa_policy::MfaPolicy, mfa_policy_api::MfaPolicyApi, security_notification::SecurityNotification, user::UserSort, user_api::UserApi}, error::errors::ApiError};
+
+/// Event of an admin action on another account, the admin is named in the detail
+fn admin_event(event_type: AuditEventType, token: &RoleToken<Admin>, user_id: i32) -> NewAuditEvent {
+    NewAuditEvent::new(event_type, AuditOutcome::Success)
+        .user(user_id)
+        .detail(&format!("by admin {}", token.auth_token().authenticated_user().id))
+}
+
+/// Paged, e.g. `?sort=-name&limit=20`
+#[get("/admin/users")]
+async fn list_users(_token: RoleToken<Admin>, query: ListQuery<UserSort>, user_api: Data<dyn UserApi>) -> Result<impl Responder, ApiError> {
+    let users = user_api.find_overview(&query.page).await?;
+
+    Ok(query.response(users))
+}
+
+#[post("/admin/users/{user_id}/lock")]
+async fn lock_user(token: RoleToken<Admin>, user_id: Path<i32>, user_api: Data<dyn UserApi>, events: SecurityEvents)
+    -> Result<impl Responder, ApiError>
+{
+    let user_id = user_id.into_inner();
+    if token.auth_token().authenticated_user().id == user_id {
+        return Err(ApiError::Validation("Admins cannot lock their own account".to_owned()));
+    }
+
+    let user = user_api.set_locked(user_id, true).await?;
+    events.record(admin_event(AuditEventType::AccountLocked, &token, user_id)).await;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+#[post("/admin/users/{user_id}/unlock")]
+async fn unlock_user(token: RoleToken<Admin>, user_id: Path<i32>, user_api: Data<dyn UserApi>, events: SecurityEvents)
+    -> Result<impl Responder, ApiError>
+{
+    let user = user_api.set_locked(user_id.into_inner(), false).await?;
+    events.record(admin_event(AuditEventType::AccountUnlocked, &token, user.id)).await;
+
+    Ok(HttpResponse::Ok().json(user))
+}
+
+/// Removes the second factor, the user has to enroll again after the next login
+#[post("/admin/users/{user_id}/reset-mfa")]
+async fn reset_mfa(token: RoleToken<Admin>, user_id: Path<i32>, user_api: Data<dyn UserApi>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user = user_api.find_by_id(user_id.into_inner()).await?;
+    let mut creds = user_api.find_credentials_by_user_id(user.id).await?;
+    creds.mfa_config = None;
+    user_api.save_credentials(creds).await?;
+    events.record(admin_event(AuditEventType::MfaDisabled, &token, user.id)).await;
+    events.notify(&user, SecurityNotification::MfaDisabled).await;
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Number of accounts whose password hash still uses weaker argon2 parameters than configured
+#[get("/admin/password-hashes")]
+async fn password_hash_report(_token: RoleToken<Admin>, user_api: Data<dyn UserApi>) -> Result<impl Responder, ApiError> {
+    let report = user_api.password_hash_report().await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Security events of all users, e.g. `?event_type=login&outcome=failure&from=2024-01-01T00:00:00Z`
+#[get("/admin/audit-events")]
+async fn list_audit_events(_token: RoleToken<Admin>, filter: Query<AuditFilter>, audit_api: Data<dyn AuditApi>) -> Result<impl Responder, ApiError> {
+    let events = audit_api.find(&filter).await?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[get("/admin/mfa-policy")]
+async fn get_mfa_policy(_token: RoleToken<Admin>, policy_api: Data<dyn MfaPolicyApi>) -> Result<impl Responder, ApiError> {
+    let policy = policy_api.find_policy().await?;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+/// Applies to logins after the change, users that are logged in keep their session
+#[put("/admin/mfa-policy")]
+async fn set_mfa_policy(_token: RoleToken<Admin>, body: Json<MfaPolicy>, policy_api: Data<dyn MfaPolicyApi>) -> Result<impl Responder, ApiError> {
+    let policy = policy_api.save_policy(body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(policy))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_users)
+    .service(lock_user)
+    .service(unlock_user)
+    .service(reset_mfa)
+    .service(password_hash_report)
+    .service(list_audit_events)
+    .service(get_mfa_policy)
+    .service(set_mfa_policy);
+}
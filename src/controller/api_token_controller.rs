@@ -0,0 +1,77 @@
+use actix_web::{delete, get, post, web::{Data, Json, Path, ServiceConfig}, HttpMessage, HttpRequest, HttpResponse, Responder};
+use authfix::AuthToken;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+
+use crate::{domain::{api_token::TokenScope, api_token_api::ApiTokenApi, user::User}, error::errors::ApiError, middleware::bearer_auth::BearerAuthenticated};
+
+const DEFAULT_EXPIRES_IN_DAYS: u32 = 30;
+const MAX_EXPIRES_IN_DAYS: u32 = 365;
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+struct CreateTokenRequest {
+    name: String,
+    scopes: Vec<TokenScope>,
+    expires_in_days: Option<u32>,
+}
+
+/// Tokens can only be managed with a session, otherwise a leaked token could mint new ones
+fn require_session(req: &HttpRequest) -> Result<(), ApiError> {
+    if let Some(bearer) = req.extensions().get::<BearerAuthenticated>() {
+        log::warn!("API token '{}' (id = {}) tried to manage API tokens", bearer.token.name, bearer.token.id);
+        Err(ApiError::Forbidden("API tokens cannot be managed with an API token".to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+#[get("/tokens")]
+async fn list_tokens(req: HttpRequest, token: AuthToken<User>, token_api: Data<dyn ApiTokenApi>) -> Result<impl Responder, ApiError> {
+    require_session(&req)?;
+    let tokens = token_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[post("/tokens")]
+async fn create_token(req: HttpRequest, body: Json<CreateTokenRequest>, token: AuthToken<User>, token_api: Data<dyn ApiTokenApi>)
+    -> Result<impl Responder, ApiError>
+{
+    require_session(&req)?;
+
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(ApiError::Validation(format!("Name must have between 1 and {} characters", MAX_NAME_LENGTH)));
+    }
+
+    if body.scopes.is_empty() {
+        return Err(ApiError::Validation("At least one scope is required".to_owned()));
+    }
+
+    let expires_in_days = body.expires_in_days.unwrap_or(DEFAULT_EXPIRES_IN_DAYS);
+    if expires_in_days == 0 || expires_in_days > MAX_EXPIRES_IN_DAYS {
+        return Err(ApiError::Validation(format!("expires_in_days must be between 1 and {}", MAX_EXPIRES_IN_DAYS)));
+    }
+
+    let expires_at = Utc::now() + Duration::days(expires_in_days.into());
+    let created = token_api.create_token(token.authenticated_user().id, name, body.scopes.clone(), expires_at).await?;
+
+    Ok(HttpResponse::Created().json(created))
+}
+
+#[delete("/tokens/{token_id}")]
+async fn revoke_token(req: HttpRequest, token_id: Path<i32>, token: AuthToken<User>, token_api: Data<dyn ApiTokenApi>)
+    -> Result<impl Responder, ApiError>
+{
+    require_session(&req)?;
+    token_api.revoke(token.authenticated_user().id, token_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_tokens)
+    .service(create_token)
+    .service(revoke_token);
+}
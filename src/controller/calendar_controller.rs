@@ -0,0 +1,163 @@
+use actix_web::{delete, get, http::header::CONTENT_DISPOSITION, post, web::{self, Bytes, Data, Path, PayloadConfig, Query, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::config::Config, domain::{calendar_api::CalendarApi, user::User, user_api::UserApi}, error::errors::{ApiError, CalendarError, QueryUserError}};
+
+/// Calendar clients reload the whole feed, older activities are left out
+const FEED_PAST_DAYS: i64 = 365;
+/// Calendars exported from other tools exceed the default body limit
+pub(crate) const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct CalendarFeed {
+    /// Contains the secret, calendar clients subscribe to it without a session
+    url: String,
+}
+
+fn calendar_response(calendar: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"activities.ics\""))
+        .body(calendar)
+}
+
+/// Registered before the activity routes, `export.ics` is no activity id
+#[get("/activities/export.ics")]
+async fn export_activities(query: Query<ExportQuery>, token: AuthToken<User>, calendar_api: Data<dyn CalendarApi>) -> Result<impl Responder, ApiError> {
+    let calendar = calendar_api.export(token.authenticated_user().id, query.from, query.to).await?;
+
+    Ok(calendar_response(calendar))
+}
+
+/// Takes the `.ics` file as request body
+async fn import_activities(body: Bytes, token: AuthToken<User>, calendar_api: Data<dyn CalendarApi>) -> Result<impl Responder, ApiError> {
+    let calendar = String::from_utf8(body.to_vec())
+        .map_err(|_| ApiError::Validation("The calendar must be UTF-8 encoded".to_owned()))?;
+    let summary = calendar_api.import(token.authenticated_user().id, calendar).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Replaces an existing feed, its URL stops working
+#[post("/calendar/feed")]
+async fn create_feed(token: AuthToken<User>, calendar_api: Data<dyn CalendarApi>, config: Data<Config>) -> Result<impl Responder, ApiError> {
+    let secret = calendar_api.create_feed(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Created().json(CalendarFeed { url: format!("{}/api/calendar/feed/{}.ics", config.mail.public_url, secret) }))
+}
+
+#[delete("/calendar/feed")]
+async fn delete_feed(token: AuthToken<User>, calendar_api: Data<dyn CalendarApi>) -> Result<impl Responder, ApiError> {
+    calendar_api.delete_feed(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Public path: the secret authenticates the request, feeds of locked users are not found
+#[get("/calendar/feed/{secret}.ics")]
+async fn feed(secret: Path<String>, calendar_api: Data<dyn CalendarApi>, user_api: Data<dyn UserApi>) -> Result<impl Responder, ApiError> {
+    let user_id = calendar_api.find_feed_user_id(&secret).await?;
+    match user_api.find_by_id(user_id).await {
+        Ok(user) if !user.locked => {},
+        Ok(_) | Err(QueryUserError::NotFound) => return Err(CalendarError::FeedNotFound.into()),
+        Err(e) => return Err(e.into()),
+    }
+
+    let calendar = calendar_api.export(user_id, Some(Utc::now() - Duration::days(FEED_PAST_DAYS)), None).await?;
+
+    Ok(calendar_response(calendar))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(export_activities)
+    .service(web::resource("/activities/import")
+        .app_data(PayloadConfig::new(MAX_IMPORT_SIZE))
+        .route(web::post().to(import_activities)))
+    .service(create_feed)
+    .service(delete_feed)
+    .service(feed);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::{header::CONTENT_TYPE, StatusCode}, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:calendar_controller_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_import_and_export_activities_and_serve_feed_without_session() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session: Cookie = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Yoga", "start": "2025-03-03T18:00:00Z", "end": "2025-03-03T19:00:00Z", "recurrence": "FREQ=WEEKLY;COUNT=10" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = test::TestRequest::get().uri("/api/activities/export.ics?from=2025-03-01T00:00:00Z&to=2025-04-01T00:00:00Z").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/calendar; charset=utf-8");
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains("RRULE:FREQ=WEEKLY;COUNT=10\r\n"));
+
+        let req = test::TestRequest::get().uri("/api/activities/export.ics").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:run-1@other\r\nDTSTART:20250301T070000Z\r\nDURATION:PT45M\r\nSUMMARY:Run\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities/import").cookie(session.clone())
+            .insert_header((CONTENT_TYPE, "text/calendar"))
+            .set_payload(ics)
+            .to_request();
+        let summary: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(summary, json!({ "created": 1, "updated": 0, "skipped": 0, "errors": [] }));
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities/import").cookie(session.clone())
+            .set_payload("no calendar")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/calendar/feed").cookie(session.clone()).to_request();
+        let feed: Value = test::call_and_read_body_json(&app, req).await;
+        let url = feed["url"].as_str().unwrap();
+        let path = &url[url.find("/api/").unwrap()..];
+
+        let req = test::TestRequest::get().uri(path).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert!(body.contains("SUMMARY:Yoga\r\n"));
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri("/api/calendar/feed").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri(path).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,74 @@
+use actix_web::{delete, get, post, put, web::{Data, Json, Path, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use serde::Deserialize;
+
+use crate::{domain::{category::NewCategory, category_api::CategoryApi, tag_api::TagApi, user::User}, error::errors::ApiError};
+
+#[derive(Deserialize)]
+struct RenameTagRequest {
+    name: String,
+}
+
+#[get("/categories")]
+async fn list_categories(token: AuthToken<User>, category_api: Data<dyn CategoryApi>) -> Result<impl Responder, ApiError> {
+    let categories = category_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+#[post("/categories")]
+async fn create_category(body: Json<NewCategory>, token: AuthToken<User>, category_api: Data<dyn CategoryApi>) -> Result<impl Responder, ApiError> {
+    let category = category_api.create(token.authenticated_user().id, body.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(category))
+}
+
+#[put("/categories/{category_id}")]
+async fn update_category(category_id: Path<i32>, body: Json<NewCategory>, token: AuthToken<User>, category_api: Data<dyn CategoryApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let category = category_api.update(token.authenticated_user().id, category_id.into_inner(), body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(category))
+}
+
+/// Activities of the category are kept without category, its goals are deleted
+#[delete("/categories/{category_id}")]
+async fn delete_category(category_id: Path<i32>, token: AuthToken<User>, category_api: Data<dyn CategoryApi>) -> Result<impl Responder, ApiError> {
+    category_api.delete(token.authenticated_user().id, category_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Tags are created through activities, so there is no create endpoint
+#[get("/tags")]
+async fn list_tags(token: AuthToken<User>, tag_api: Data<dyn TagApi>) -> Result<impl Responder, ApiError> {
+    let tags = tag_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[put("/tags/{tag_id}")]
+async fn rename_tag(tag_id: Path<i32>, body: Json<RenameTagRequest>, token: AuthToken<User>, tag_api: Data<dyn TagApi>) -> Result<impl Responder, ApiError> {
+    let tag = tag_api.rename(token.authenticated_user().id, tag_id.into_inner(), &body.name).await?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+/// Removes the tag from all activities
+#[delete("/tags/{tag_id}")]
+async fn delete_tag(tag_id: Path<i32>, token: AuthToken<User>, tag_api: Data<dyn TagApi>) -> Result<impl Responder, ApiError> {
+    tag_api.delete(token.authenticated_user().id, tag_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_categories)
+    .service(create_category)
+    .service(update_category)
+    .service(delete_category)
+    .service(list_tags)
+    .service(rename_tag)
+    .service(delete_tag);
+}
@@ -0,0 +1,119 @@
+use actix_web::{get, http::header::CONTENT_DISPOSITION, web::{self, Bytes, Data, PayloadConfig, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use futures::TryStreamExt;
+
+use crate::{domain::{exchange_api::{ExchangeApi, ExchangeFormat}, user::User}, error::errors::ApiError};
+
+use super::calendar_controller::MAX_IMPORT_SIZE;
+
+fn export_response(exchange_api: &dyn ExchangeApi, user_id: i32, format: ExchangeFormat) -> HttpResponse {
+    let (content_type, file_name) = match format {
+        ExchangeFormat::Csv => ("text/csv; charset=utf-8", "activities.csv"),
+        ExchangeFormat::Json => ("application/json", "activities.json"),
+    };
+    // errors after the first chunk abort the response, the status is already sent
+    let body = exchange_api.export(user_id, format)
+        .map_ok(Bytes::from)
+        .map_err(|e| {
+            log::error!("Export of activities failed: {}", e);
+            ApiError::from(e)
+        });
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)))
+        .streaming(body)
+}
+
+/// Registered before the activity routes, `export.csv` is no activity id
+#[get("/activities/export.csv")]
+async fn export_csv(token: AuthToken<User>, exchange_api: Data<dyn ExchangeApi>) -> impl Responder {
+    export_response(exchange_api.as_ref(), token.authenticated_user().id, ExchangeFormat::Csv)
+}
+
+#[get("/activities/export.json")]
+async fn export_json(token: AuthToken<User>, exchange_api: Data<dyn ExchangeApi>) -> impl Responder {
+    export_response(exchange_api.as_ref(), token.authenticated_user().id, ExchangeFormat::Json)
+}
+
+async fn import(format: ExchangeFormat, body: Bytes, token: AuthToken<User>, exchange_api: Data<dyn ExchangeApi>) -> Result<HttpResponse, ApiError> {
+    let content = String::from_utf8(body.to_vec())
+        .map_err(|_| ApiError::Validation("The file must be UTF-8 encoded".to_owned()))?;
+    let summary = exchange_api.import(token.authenticated_user().id, format, content).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Takes the `.csv` file as request body
+async fn import_csv(body: Bytes, token: AuthToken<User>, exchange_api: Data<dyn ExchangeApi>) -> Result<impl Responder, ApiError> {
+    import(ExchangeFormat::Csv, body, token, exchange_api).await
+}
+
+/// Takes the `.json` file as request body
+async fn import_json(body: Bytes, token: AuthToken<User>, exchange_api: Data<dyn ExchangeApi>) -> Result<impl Responder, ApiError> {
+    import(ExchangeFormat::Json, body, token, exchange_api).await
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(export_csv)
+    .service(export_json)
+    .service(web::resource("/activities/import.csv")
+        .app_data(PayloadConfig::new(MAX_IMPORT_SIZE))
+        .route(web::post().to(import_csv)))
+    .service(web::resource("/activities/import.json")
+        .app_data(PayloadConfig::new(MAX_IMPORT_SIZE))
+        .route(web::post().to(import_json)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::{header::CONTENT_TYPE, StatusCode}, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:exchange_controller_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_import_json_and_stream_csv_export() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session: Cookie = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities/import.json").cookie(session.clone())
+            .set_json(json!([
+                { "uid": "run@tracker", "title": "Run", "start": "2025-03-01T07:00:00Z", "category": "Sport", "tags": ["outdoor"] },
+                { "title": "Swim", "start": "soon" }
+            ]))
+            .to_request();
+        let summary: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(summary["created"], 1);
+        assert_eq!(summary["errors"][0]["index"], 2);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities/import.csv").cookie(session.clone())
+            .set_payload("title,end\nRead,")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri("/api/activities/export.csv").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert_eq!(body, "uid,title,description,start,end,category,tags,recurrence,time_zone,time_entries\r\nrun@tracker,Run,,2025-03-01T07:00:00Z,,Sport,outdoor,,,\r\n");
+
+        let req = test::TestRequest::get().uri("/api/activities/export.json").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,116 @@
+use actix_web::{delete, get, post, put, web::{Data, Json, Path, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+
+use crate::{domain::{goal::NewGoal, goal_api::GoalApi, user::User}, error::errors::ApiError};
+
+/// Goals with the progress of the current period and their streaks
+#[get("/goals")]
+async fn list_goals(token: AuthToken<User>, goal_api: Data<dyn GoalApi>) -> Result<impl Responder, ApiError> {
+    let goals = goal_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(goals))
+}
+
+#[post("/goals")]
+async fn create_goal(body: Json<NewGoal>, token: AuthToken<User>, goal_api: Data<dyn GoalApi>) -> Result<impl Responder, ApiError> {
+    let goal = goal_api.create(token.authenticated_user().id, body.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(goal))
+}
+
+#[get("/goals/{goal_id}")]
+async fn get_goal(goal_id: Path<i32>, token: AuthToken<User>, goal_api: Data<dyn GoalApi>) -> Result<impl Responder, ApiError> {
+    let goal = goal_api.find_by_id(token.authenticated_user().id, goal_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(goal))
+}
+
+#[put("/goals/{goal_id}")]
+async fn update_goal(goal_id: Path<i32>, body: Json<NewGoal>, token: AuthToken<User>, goal_api: Data<dyn GoalApi>) -> Result<impl Responder, ApiError> {
+    let goal = goal_api.update(token.authenticated_user().id, goal_id.into_inner(), body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(goal))
+}
+
+#[delete("/goals/{goal_id}")]
+async fn delete_goal(goal_id: Path<i32>, token: AuthToken<User>, goal_api: Data<dyn GoalApi>) -> Result<impl Responder, ApiError> {
+    goal_api.delete(token.authenticated_user().id, goal_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_goals)
+    .service(create_goal)
+    .service(get_goal)
+    .service(update_goal)
+    .service(delete_goal);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::StatusCode, test};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:goal_controller_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_manage_goals_with_progress() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session: Cookie = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Run", "start": Utc::now(), "tags": ["Sport"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/goals").cookie(session.clone())
+            .set_json(json!({ "title": "Exercise", "tag": "sport", "period": "day", "metric": "count", "target": 1 }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let goal: Value = test::read_body_json(res).await;
+        assert_eq!((goal["current"].as_i64(), goal["achieved"].as_bool(), goal["current_streak"].as_i64()), (Some(1), Some(true), Some(1)));
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/goals").cookie(session.clone())
+            .set_json(json!({ "title": " ", "category_id": 4711, "tag": "sport", "period": "week", "metric": "count", "target": 0 }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["errors"].as_array().unwrap().len(), 3);
+
+        let req = with_csrf_token(test::TestRequest::put()).uri(&format!("/api/goals/{}", goal["id"])).cookie(session.clone())
+            .set_json(json!({ "title": "Exercise more", "tag": "sport", "period": "day", "metric": "count", "target": 2 }))
+            .to_request();
+        let updated: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(updated["achieved"], false);
+
+        let req = test::TestRequest::get().uri("/api/goals").cookie(session.clone()).to_request();
+        let goals: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(goals[0]["title"], "Exercise more");
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri(&format!("/api/goals/{}", goal["id"])).cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get().uri(&format!("/api/goals/{}", goal["id"])).cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
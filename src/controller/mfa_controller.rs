@@ -1,28 +1,44 @@
 use actix_session::Session;
-use actix_web::{error, get, http::header::ContentType, post, web::{Data, Json, ServiceConfig}, HttpResponse, Responder, Result};
+use actix_web::{get, http::header::ContentType, post, put, web::{Data, Json, ServiceConfig}, HttpResponse, Responder};
 use authfix::{multifactor::factor_impl::authenticator::{Authenticator, AuthenticatorFactor, TotpSecretGenerator}, session::auth_flow::MfaRequestBody, AuthToken};
 
-use crate::domain::{user::{Mfa, User}, user_api::UserApi};
+use serde::{Deserialize, Serialize};
+
+use crate::{controller::security_events::SecurityEvents, domain::{audit_event::{AuditEventType, AuditOutcome, NewAuditEvent}, auth_api::AuthenticationApi, email_code::MFA_ID_EMAIL_CODE, security_notification::SecurityNotification, user::{Mfa, User}, user_api::UserApi, webauthn::MFA_ID_WEBAUTHN, webauthn_api::WebauthnApi}, error::errors::ApiError, service::mfa_policy_service::SESSION_KEY_MFA_ENROLLMENT_REQUIRED};
 
 const SESSION_KEY_TOTP_SECRET: &str = "totp_secret";
 
-#[get("/totp/debug-user-data")]
-async fn get_user_data(token: AuthToken<User>, user_api: Data<dyn UserApi>) -> impl Responder {
-    let creds = user_api.find_credentials_by_user_id(token.authenticated_user().id).await.unwrap();
+#[derive(Deserialize)]
+struct SelectFactorRequest {
+    mfa_id: String,
+    current_password: String,
+}
 
-    let mfa = creds.mfa_config.unwrap();
-    let r = format!(r#"{{
-        "user": "{}",
-        "mfa_id": "{}",
-        "secret": "{}"
-    }}"#, token.authenticated_user().name, mfa.mfa_id, mfa.secret.unwrap());
+#[derive(Serialize)]
+struct DebugUserData {
+    user: String,
+    mfa_id: String,
+    secret: String,
+}
 
-    HttpResponse::Ok().json(r)
+#[get("/totp/debug-user-data")]
+async fn get_user_data(token: AuthToken<User>, user_api: Data<dyn UserApi>) -> Result<impl Responder, ApiError> {
+    let creds = user_api.find_credentials_by_user_id(token.authenticated_user().id).await?;
+
+    let mfa = creds.mfa_config
+        .ok_or_else(|| ApiError::NotFound("No MFA configured".to_owned()))?;
+    let secret = mfa.secret
+        .ok_or_else(|| ApiError::NotFound("No MFA secret configured".to_owned()))?;
+    Ok(HttpResponse::Ok().json(DebugUserData {
+        user: token.authenticated_user().name.clone(),
+        mfa_id: mfa.mfa_id,
+        secret,
+    }))
 }
 
 
 #[get("/totp/qrcode")]
-async fn get_qrcode(token: AuthToken<User>, session: Session) -> Result<impl Responder> {
+async fn get_qrcode(token: AuthToken<User>, session: Session) -> Result<impl Responder, ApiError> {
     let email = &token.authenticated_user().email;
 
     let generator = TotpSecretGenerator::new("MyActivities", email);
@@ -30,7 +46,8 @@ async fn get_qrcode(token: AuthToken<User>, session: Session) -> Result<impl Res
 
     session.insert(SESSION_KEY_TOTP_SECRET, secret)?;
 
-    let qrcode = generator.qr_code().unwrap();
+    let qrcode = generator.qr_code()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
 
     Ok(HttpResponse::Ok()
         .insert_header(ContentType(mime::IMAGE_SVG))
@@ -38,47 +55,81 @@ async fn get_qrcode(token: AuthToken<User>, session: Session) -> Result<impl Res
 }
 
 #[post("/totp/set-secret")]
-async fn set_totp_secret(code: Json<MfaRequestBody>, token: AuthToken<User>, session: Session, user_api: Data<dyn UserApi>) 
-    -> Result<impl Responder> 
+async fn set_totp_secret(code: Json<MfaRequestBody>, token: AuthToken<User>, session: Session, user_api: Data<dyn UserApi>, events: SecurityEvents) -> Result<impl Responder, ApiError> 
 {
     let user_id = token.authenticated_user().id;
     let mut creds = user_api.find_credentials_by_user_id(user_id).await
-        .map_err(|err| {
-            log::error!("Cannot load credentials: {}", err);
-            error::ErrorBadRequest("Cannot save secret")
-        })?;
+        .map_err(|err| ApiError::Internal(format!("Cannot load credentials: {}", err)))?;
 
     let secret = session.get::<String>(SESSION_KEY_TOTP_SECRET)?;
 
     if let Some(secret) = secret {
         // It seems to be a good practice to check a generated code before saving the secret
         if !Authenticator::verify(&secret, code.code(), 0) {
-            return Err(error::ErrorUnauthorized("The TOTP was wrong"));
+            events.record(NewAuditEvent::new(AuditEventType::MfaEnrollment, AuditOutcome::Failure)
+                .user(user_id).detail("wrong TOTP code")).await;
+            return Err(ApiError::Unauthorized("The TOTP was wrong".to_owned()));
         }
 
         let mfa_config = Mfa::with_secret(&AuthenticatorFactor::id(), &secret);
         creds.set_mfa(mfa_config);
         user_api.save_credentials(creds).await
-            .map_err(|err| {
-                log::error!("Cannot save credentials after upating mfa_config: {}", err);
-                error::ErrorBadRequest("Cannot save secret")
-            })?;
+            .map_err(|err| ApiError::Internal(format!("Cannot save credentials after upating mfa_config: {}", err)))?;
 
         // clean up session
         session.remove(SESSION_KEY_TOTP_SECRET);
+        session.remove(SESSION_KEY_MFA_ENROLLMENT_REQUIRED);
+        events.record(NewAuditEvent::new(AuditEventType::MfaEnrollment, AuditOutcome::Success)
+            .user(user_id).detail(&AuthenticatorFactor::id())).await;
+        events.notify(&token.authenticated_user(), SecurityNotification::MfaEnrolled { mfa_id: AuthenticatorFactor::id() }).await;
         Ok(HttpResponse::Ok())   
     } else {
         log::error!("Session does not contain the secret.");
 
         // clean up session
         session.remove(SESSION_KEY_TOTP_SECRET);
-        Err(error::ErrorBadRequest("Cannot save secret"))
+        Err(ApiError::Validation("No TOTP secret pending. Request a new QR code first.".to_owned()))
     }
 }
 
+/// Switches between enrolled factors or enables the email code. The TOTP secret is kept while another factor is active.
+#[put("/mfa/factor")]
+async fn select_factor(body: Json<SelectFactorRequest>, token: AuthToken<User>, session: Session, user_api: Data<dyn UserApi>,
+    webauthn_api: Data<dyn WebauthnApi>, auth_api: Data<dyn AuthenticationApi>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user_id = token.authenticated_user().id;
+    events.confirm_password(auth_api.get_ref(), &token.authenticated_user(), &body.current_password, AuditEventType::MfaEnrollment).await?;
+    let mut creds = user_api.find_credentials_by_user_id(user_id).await?;
+    let secret = creds.mfa_config.as_ref().and_then(|mfa| mfa.secret.clone());
+
+    let mfa_config = if body.mfa_id == AuthenticatorFactor::id() {
+        let secret = secret.ok_or_else(|| ApiError::Validation("No TOTP secret enrolled".to_owned()))?;
+        Mfa::with_secret(&body.mfa_id, &secret)
+    } else if body.mfa_id == MFA_ID_WEBAUTHN {
+        if webauthn_api.find_by_user_id(user_id).await?.is_empty() {
+            return Err(ApiError::Validation("No security key registered".to_owned()));
+        }
+        Mfa { mfa_id: body.mfa_id.clone(), secret }
+    } else if body.mfa_id == MFA_ID_EMAIL_CODE {
+        // the email address is known to be valid, it was used for the login
+        Mfa { mfa_id: body.mfa_id.clone(), secret }
+    } else {
+        return Err(ApiError::Validation(format!("Unknown MFA factor: {}", body.mfa_id)));
+    };
+
+    creds.set_mfa(mfa_config);
+    user_api.save_credentials(creds).await?;
+    session.remove(SESSION_KEY_MFA_ENROLLMENT_REQUIRED);
+    events.record(NewAuditEvent::new(AuditEventType::MfaEnrollment, AuditOutcome::Success)
+        .user(user_id).detail(&body.mfa_id)).await;
+    events.notify(&token.authenticated_user(), SecurityNotification::MfaEnrolled { mfa_id: body.mfa_id.clone() }).await;
+
+    Ok(HttpResponse::NoContent())
+}
 
 pub fn config(cfg: &mut ServiceConfig) {
     cfg.service(get_qrcode)
     .service(set_totp_secret)
+    .service(select_factor)
     .service(get_user_data);
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use actix_session::Session;
+use actix_web::{get, http::header::LOCATION, web::{Data, Query, ServiceConfig}, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+
+use crate::{config::config::Config, error::errors::{ApiError, OidcError}, service::{auth_service::{ExternalLogin, ExternalLoginState}, oidc_service::{OidcFlowState, OidcService}}};
+
+const SESSION_KEY_OIDC_FLOW: &str = "oidc_flow";
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[get("/oidc/login")]
+async fn login(session: Session, oidc: Data<OidcService>) -> Result<impl Responder, ApiError> {
+    let (url, flow) = oidc.start_login().await?;
+    session.insert(SESSION_KEY_OIDC_FLOW, flow)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url.to_string()))
+        .finish())
+}
+
+/// Continues like a password login, users with a second factor are sent to the MFA page
+#[get("/oidc/callback")]
+async fn callback(query: Query<CallbackQuery>, req: HttpRequest, session: Session, oidc: Data<OidcService>, external_login: Data<ExternalLogin>, config: Data<Config>)
+    -> Result<impl Responder, ApiError>
+{
+    // the flow state can only be used once
+    let flow = session.remove_as::<OidcFlowState>(SESSION_KEY_OIDC_FLOW)
+        .and_then(|flow| flow.ok())
+        .ok_or(OidcError::InvalidState)?;
+
+    if let Some(error) = &query.error {
+        return Err(ApiError::Unauthorized(format!("Login rejected by provider: {} {}", error, query.error_description.as_deref().unwrap_or(""))));
+    }
+
+    if query.state.as_deref() != Some(flow.state.as_str()) {
+        return Err(OidcError::InvalidState.into());
+    }
+
+    let code = query.code.as_deref()
+        .ok_or_else(|| ApiError::Validation("Missing authorization code".to_owned()))?;
+
+    let user = oidc.finish_login(code, &flow).await?;
+
+    let state = external_login.login(&user, &req).await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let redirect = match (state, config.oidc.as_ref()) {
+        (ExternalLoginState::Authenticated, Some(oidc)) => oidc.post_login_redirect.clone(),
+        (ExternalLoginState::MfaNeeded(mfa_id), Some(oidc)) => {
+            let separator = if oidc.mfa_redirect.contains('?') { '&' } else { '?' };
+            format!("{}{}mfa_id={}", oidc.mfa_redirect, separator, mfa_id)
+        },
+        (_, None) => "/".to_owned(),
+    };
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, redirect))
+        .finish())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(login)
+    .service(callback);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, dev::ServiceResponse, http::{header::LOCATION, StatusCode}, test};
+    use authfix::multifactor::factor_impl::authenticator::AuthenticatorFactor;
+    use google_authenticator::GoogleAuthenticator;
+    use reqwest::Url;
+    use serde_json::json;
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::{Mfa, User}, user_api::UserApi}, middleware::csrf::with_csrf_token, service::{oidc_service::tests::{oidc_config, start_mock_issuer}, user_service::UserService}};
+
+    const DB: &str = "file:oidc_controller_test?mode=memory&cache=shared";
+
+    fn session_cookie<B>(res: &ServiceResponse<B>) -> Cookie<'static> {
+        res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned()
+    }
+
+    #[actix_web::test]
+    async fn should_require_second_factor_after_oidc_login() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let user = user_service.save_user_with_credentials(User::new(0, "hans@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let authenticator = GoogleAuthenticator::new();
+        let secret = authenticator.create_secret(20);
+        let mut creds = user_service.find_credentials_by_user_id(user.id).await.unwrap();
+        creds.set_mfa(Mfa::with_secret(&AuthenticatorFactor::id(), &secret));
+        user_service.save_credentials(creds).await.unwrap();
+
+        let mock = start_mock_issuer("hans@example.org");
+        let config = Config { oidc: Some(oidc_config(&mock.issuer)), ..Config::default() };
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(config))).await;
+
+        let req = test::TestRequest::get().uri("/api/oidc/login").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        let authorize = Url::parse(res.headers().get(LOCATION).unwrap().to_str().unwrap()).unwrap();
+        let param = |name: &str| authorize.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned()).unwrap();
+        *mock.nonce.lock().unwrap() = param("nonce");
+
+        let req = test::TestRequest::get().uri(&format!("/api/oidc/callback?code=abc&state={}", param("state"))).cookie(session_cookie(&res)).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(res.headers().get(LOCATION).unwrap(), &format!("/login?mfa_id={}", AuthenticatorFactor::id()));
+        let session = session_cookie(&res);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login/mfa").cookie(session)
+            .set_json(json!({ "code": authenticator.get_code(&secret, 0).unwrap() }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let session = session_cookie(&res);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
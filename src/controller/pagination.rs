@@ -0,0 +1,104 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, web::Query, FromRequest, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{domain::page::{Cursor, Page, PageRequest, SortField, SortOrder}, error::errors::ApiError};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Deserialize)]
+struct ListParams {
+    cursor: Option<String>,
+    limit: Option<usize>,
+    sort: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+/// Query parameters shared by the list endpoints, e.g. `?sort=-start&limit=20&from=2025-01-01T00:00:00Z`.
+/// A leading `-` sorts descending, the `cursor` is the `next_cursor` of the previous page.
+/// Other parameters are left to the endpoint.
+pub struct ListQuery<S: SortField> {
+    pub page: PageRequest<S>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    path: String,
+    query: String,
+}
+
+/// Envelope of a page, `next` is the link to the following page with the same parameters
+#[derive(Serialize)]
+pub struct PageResponse<T: Serialize> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub next: Option<String>,
+}
+
+impl<S: SortField> ListQuery<S> {
+    fn parse(req: &HttpRequest) -> Result<Self, ApiError> {
+        let params = Query::<ListParams>::from_query(req.query_string())
+            .map_err(|e| ApiError::Validation(e.to_string()))?
+            .into_inner();
+
+        let (sort, order) = match params.sort.as_deref().map(str::trim) {
+            None | Some("") => (S::default(), S::default().default_order()),
+            Some(field) => match field.strip_prefix('-') {
+                Some(field) => (field.parse().map_err(ApiError::Validation)?, SortOrder::Desc),
+                None => (field.parse().map_err(ApiError::Validation)?, SortOrder::Asc),
+            },
+        };
+        let cursor = params.cursor
+            .map(|token| Cursor::decode(&token).ok_or_else(|| ApiError::Validation("The cursor is invalid".to_owned())))
+            .transpose()?;
+        let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(ApiError::Validation(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+        }
+        if !S::DATE_RANGE && (params.from.is_some() || params.to.is_some()) {
+            return Err(ApiError::Validation("This list cannot be filtered by from and to".to_owned()));
+        }
+        if let (Some(from), Some(to)) = (params.from, params.to) {
+            if from > to {
+                return Err(ApiError::Validation("to must not be before from".to_owned()));
+            }
+        }
+
+        Ok(ListQuery {
+            page: PageRequest { sort, order, cursor, limit },
+            from: params.from,
+            to: params.to,
+            path: req.path().to_owned(),
+            query: req.query_string().to_owned(),
+        })
+    }
+
+    /// The request with the cursor replaced, other parameters are kept
+    fn next_link(&self, cursor: &str) -> String {
+        let mut params: Vec<&str> = self.query.split('&')
+            .filter(|param| !param.is_empty() && param.split('=').next() != Some("cursor"))
+            .collect();
+        let cursor = format!("cursor={}", cursor);
+        params.push(&cursor);
+
+        format!("{}?{}", self.path, params.join("&"))
+    }
+
+    pub fn response<T: Serialize>(&self, page: Page<T>) -> HttpResponse {
+        let next_cursor = page.next_cursor.map(|cursor| cursor.encode());
+        let next = next_cursor.as_deref().map(|cursor| self.next_link(cursor));
+
+        HttpResponse::Ok().json(PageResponse { items: page.items, next_cursor, next })
+    }
+}
+
+impl<S: SortField> FromRequest for ListQuery<S> {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Self::parse(req))
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::{get, http::header::CONTENT_DISPOSITION, web::{Data, Query, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{domain::{period::Period, report::{ReportGroup, ReportQuery}, report_api::ReportApi, user::User}, error::errors::ApiError, service::report_service::summary_csv};
+
+#[derive(Deserialize)]
+struct SummaryQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    period: Option<Period>,
+    group: Option<ReportGroup>,
+    /// `csv` for a spreadsheet, JSON otherwise
+    format: Option<String>,
+}
+
+/// E.g. `?from=2025-03-01T00:00:00Z&to=2025-04-01T00:00:00Z&period=week&group=category&format=csv`
+#[get("/reports/summary")]
+async fn summary(query: Query<SummaryQuery>, token: AuthToken<User>, report_api: Data<dyn ReportApi>) -> Result<impl Responder, ApiError> {
+    let query = query.into_inner();
+    let csv = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(format) => return Err(ApiError::Validation(format!("Unsupported format '{}', use json or csv", format))),
+    };
+    let report = ReportQuery { from: query.from, to: query.to, period: query.period, group: query.group };
+    let summary = report_api.summary(token.authenticated_user().id, report).await?;
+
+    if csv {
+        return Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((CONTENT_DISPOSITION, "attachment; filename=\"report.csv\""))
+            .body(summary_csv(&summary)));
+    }
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(summary);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::{header::CONTENT_TYPE, StatusCode}, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:report_controller_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_report_summary_as_json_and_csv() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let session: Cookie = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/activities").cookie(session.clone())
+            .set_json(json!({ "title": "Run", "start": "2025-03-01T07:00:00Z", "tags": ["outdoor"] }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let range = "from=2025-03-01T00:00:00Z&to=2025-04-01T00:00:00Z";
+        let req = test::TestRequest::get().uri(&format!("/api/reports/summary?{}&period=day&group=tag", range)).cookie(session.clone()).to_request();
+        let summary: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(summary["rows"], json!([{ "period": "2025-03-01", "group": "outdoor", "activities": 1, "tracked_seconds": 0 }]));
+        assert_eq!(summary["total"], json!({ "activities": 1, "tracked_seconds": 0 }));
+
+        let req = test::TestRequest::get().uri(&format!("/api/reports/summary?{}&group=tag&format=csv", range)).cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "text/csv; charset=utf-8");
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        assert_eq!(body, "period,tag,activities,tracked_seconds\r\n,outdoor,1,0\r\n");
+
+        let req = test::TestRequest::get().uri("/api/reports/summary?from=2025-04-01T00:00:00Z&to=2025-03-01T00:00:00Z").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri(&format!("/api/reports/summary?{}", range)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
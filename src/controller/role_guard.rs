@@ -0,0 +1,58 @@
+use std::{future::{ready, Ready}, marker::PhantomData};
+
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use authfix::{AuthToken, AuthTokenExt};
+
+use crate::{domain::user::{Role, User}, error::errors::ApiError};
+
+/// Marks a role that a [RoleToken] requires
+pub trait RequiredRole {
+    fn role() -> Role;
+}
+
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    fn role() -> Role {
+        Role::Admin
+    }
+}
+
+/// Extractor on top of [AuthToken] that rejects users without the required role with 403.
+///
+/// The role is read from the user stored in the session, so role changes take effect after the next login.
+/// ```ignore
+/// #[get("/admin/users")]
+/// async fn list_users(token: RoleToken<Admin>) -> impl Responder { ... }
+/// ```
+pub struct RoleToken<R: RequiredRole> {
+    token: AuthToken<User>,
+    phantom_data: PhantomData<R>,
+}
+
+impl<R: RequiredRole> RoleToken<R> {
+    pub fn auth_token(&self) -> &AuthToken<User> {
+        &self.token
+    }
+}
+
+impl<R: RequiredRole> FromRequest for RoleToken<R> {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = match req.auth_token::<User>() {
+            Some(token) => token,
+            None => return ready(Err(ApiError::Internal("'RoleToken' cannot be used in public routes.".to_owned()))),
+        };
+
+        if token.authenticated_user().role.includes(R::role()) {
+            ready(Ok(RoleToken {
+                token,
+                phantom_data: PhantomData,
+            }))
+        } else {
+            ready(Err(ApiError::Forbidden(format!("Requires role '{}'", R::role().as_str()))))
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpRequest};
+
+use crate::{domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditOutcome, NewAuditEvent}, auth_api::AuthenticationApi, notification_api::NotificationApi, security_notification::SecurityNotification, user::User}, error::errors::{ApiError, FieldError}, service::{audit_service::record_event, notification_service::send_notification}};
+
+/// Extractor for handlers that change sensitive account data.
+///
+/// Records audit events with the IP address and user agent of the request and notifies users by mail.
+/// Failures are only logged, the change itself already happened.
+/// ```ignore
+/// #[put("/account/password")]
+/// async fn change_password(events: SecurityEvents, ...) -> impl Responder {
+///     events.record(NewAuditEvent::new(AuditEventType::PasswordChange, AuditOutcome::Success).user(user.id)).await;
+///     events.notify(&user, SecurityNotification::PasswordChanged).await;
+/// }
+/// ```
+pub struct SecurityEvents {
+    req: HttpRequest,
+    audit_api: Data<dyn AuditApi>,
+    notification_api: Data<dyn NotificationApi>,
+}
+
+impl SecurityEvents {
+    pub async fn record(&self, event: NewAuditEvent) {
+        record_event(self.audit_api.get_ref(), event.request(&self.req)).await;
+    }
+
+    pub async fn notify(&self, user: &User, notification: SecurityNotification) {
+        send_notification(self.notification_api.get_ref(), user, notification).await;
+    }
+
+    /// Asks for the password again before a sensitive change, a wrong one is recorded as failed `event_type`
+    pub async fn confirm_password(&self, auth_api: &dyn AuthenticationApi, user: &User, password: &str, event_type: AuditEventType) -> Result<(), ApiError> {
+        if auth_api.is_password_correct(user, password).await? {
+            return Ok(());
+        }
+
+        self.record(NewAuditEvent::new(event_type, AuditOutcome::Failure).user(user.id).detail("wrong current password")).await;
+        Err(ApiError::InvalidFields(vec![FieldError::new("current_password", "incorrect", "Current password is incorrect.")]))
+    }
+}
+
+impl FromRequest for SecurityEvents {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, ApiError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let audit_api = req.app_data::<Data<dyn AuditApi>>().cloned();
+        let notification_api = req.app_data::<Data<dyn NotificationApi>>().cloned();
+
+        ready(match (audit_api, notification_api) {
+            (Some(audit_api), Some(notification_api)) => Ok(SecurityEvents {
+                req: req.clone(),
+                audit_api,
+                notification_api,
+            }),
+            _ => Err(ApiError::Internal("Audit log or notifications are not configured".to_owned())),
+        })
+    }
+}
@@ -0,0 +1,72 @@
+use actix_web::{delete, get, post, put, web::{Data, Json, Path, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+use serde::Deserialize;
+
+use crate::{domain::{time_entry::NewTimeEntry, time_entry_api::TimeEntryApi, user::User}, error::errors::ApiError};
+
+#[derive(Deserialize, Default)]
+struct StartTimerRequest {
+    note: Option<String>,
+}
+
+/// Lists the entries of the activity with their total duration
+#[get("/activities/{activity_id}/time-entries")]
+async fn list_entries(activity_id: Path<i32>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>) -> Result<impl Responder, ApiError> {
+    let entries = time_entry_api.find_by_activity(token.authenticated_user().id, activity_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// The body is optional
+#[post("/activities/{activity_id}/time-entries/start")]
+async fn start_timer(activity_id: Path<i32>, body: Option<Json<StartTimerRequest>>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let note = body.map(|body| body.into_inner()).unwrap_or_default().note;
+    let entry = time_entry_api.start(token.authenticated_user().id, activity_id.into_inner(), note).await?;
+
+    Ok(HttpResponse::Created().json(entry))
+}
+
+#[post("/activities/{activity_id}/time-entries/stop")]
+async fn stop_timer(activity_id: Path<i32>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>) -> Result<impl Responder, ApiError> {
+    let entry = time_entry_api.stop(token.authenticated_user().id, activity_id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[post("/activities/{activity_id}/time-entries")]
+async fn create_entry(activity_id: Path<i32>, body: Json<NewTimeEntry>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let entry = time_entry_api.create(token.authenticated_user().id, activity_id.into_inner(), body.into_inner()).await?;
+
+    Ok(HttpResponse::Created().json(entry))
+}
+
+#[put("/activities/{activity_id}/time-entries/{entry_id}")]
+async fn update_entry(path: Path<(i32, i32)>, body: Json<NewTimeEntry>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>)
+    -> Result<impl Responder, ApiError>
+{
+    let (activity_id, entry_id) = path.into_inner();
+    let entry = time_entry_api.update(token.authenticated_user().id, activity_id, entry_id, body.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+#[delete("/activities/{activity_id}/time-entries/{entry_id}")]
+async fn delete_entry(path: Path<(i32, i32)>, token: AuthToken<User>, time_entry_api: Data<dyn TimeEntryApi>) -> Result<impl Responder, ApiError> {
+    let (activity_id, entry_id) = path.into_inner();
+    time_entry_api.delete(token.authenticated_user().id, activity_id, entry_id).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_entries)
+    .service(start_timer)
+    .service(stop_timer)
+    .service(create_entry)
+    .service(update_entry)
+    .service(delete_entry);
+}
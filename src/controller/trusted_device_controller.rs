@@ -0,0 +1,114 @@
+use actix_web::{delete, get, web::{Data, Path, ServiceConfig}, HttpResponse, Responder};
+use authfix::AuthToken;
+
+use crate::{domain::{trusted_device_api::TrustedDeviceApi, user::User}, error::errors::ApiError};
+
+#[get("/trusted-devices")]
+async fn list_devices(token: AuthToken<User>, trusted_device_api: Data<dyn TrustedDeviceApi>) -> Result<impl Responder, ApiError> {
+    let devices = trusted_device_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(devices))
+}
+
+/// The device has to pass the second factor again on the next login
+#[delete("/trusted-devices/{device_id}")]
+async fn revoke_device(device_id: Path<i32>, token: AuthToken<User>, trusted_device_api: Data<dyn TrustedDeviceApi>) -> Result<impl Responder, ApiError> {
+    trusted_device_api.revoke(token.authenticated_user().id, device_id.into_inner()).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+#[delete("/trusted-devices")]
+async fn revoke_all_devices(token: AuthToken<User>, trusted_device_api: Data<dyn TrustedDeviceApi>) -> Result<impl Responder, ApiError> {
+    trusted_device_api.revoke_all(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::NoContent())
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(list_devices)
+    .service(revoke_device)
+    .service(revoke_all_devices);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, dev::ServiceResponse, http::StatusCode, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::{Mfa, User}, user_api::UserApi, webauthn::MFA_ID_WEBAUTHN, webauthn_api::WebauthnApi}, middleware::csrf::with_csrf_token, service::{trusted_device_service::TRUSTED_DEVICE_COOKIE, user_service::UserService, webauthn_service::{soft_authenticator::SoftAuthenticator, WebauthnService}}};
+
+    const DB: &str = "file:trusted_device_controller_test?mode=memory&cache=shared";
+
+    fn cookie<B>(res: &ServiceResponse<B>, name: &str) -> Option<Cookie<'static>> {
+        res.response().cookies()
+            .find(|c| c.name() == name)
+            .map(|c| c.into_owned())
+    }
+
+    fn login_request(trusted_device: Option<Cookie<'static>>) -> test::TestRequest {
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }));
+
+        match trusted_device {
+            Some(trusted_device) => req.cookie(trusted_device),
+            None => req,
+        }
+    }
+
+    #[actix_web::test]
+    async fn should_skip_mfa_on_trusted_device_until_revoked() {
+        let _db = create_db(&DbConfig::new(DB));
+        let config = Config::default();
+        let rp = config.webauthn.clone();
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let webauthn = WebauthnService::new(Arc::new(DbConfig::new(DB)), rp.clone());
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let mut authenticator = SoftAuthenticator::new();
+        let options = webauthn.start_registration(&user).await.unwrap();
+        let response = authenticator.register(&rp.rp_id, &rp.origin, &options.challenge);
+        webauthn.finish_registration(user.id, "key", &options.challenge, serde_json::from_value(response).unwrap()).await.unwrap();
+        let mut creds = user_service.find_credentials_by_user_id(user.id).await.unwrap();
+        creds.set_mfa(Mfa::new(MFA_ID_WEBAUTHN));
+        user_service.save_credentials(creds).await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(config))).await;
+
+        let res = test::call_service(&app, login_request(None).to_request()).await;
+        let session = cookie(&res, "sessionId").unwrap();
+        let body: Value = test::read_body_json(res).await;
+        assert_eq!(body["status"], "MfaNeeded");
+        let req = test::TestRequest::get().uri("/api/webauthn/login-options").cookie(session.clone()).to_request();
+        let options: Value = test::call_and_read_body_json(&app, req).await;
+        let assertion = authenticator.assert(&rp.rp_id, &rp.origin, options["challenge"].as_str().unwrap());
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login/mfa").cookie(session)
+            .set_json(json!({ "code": assertion.to_string() }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let trusted_device = cookie(&res, TRUSTED_DEVICE_COOKIE).unwrap();
+
+        // A tampered cookie is ignored
+        let tampered = Cookie::new(TRUSTED_DEVICE_COOKIE, format!("{}x", trusted_device.value()));
+        let body: Value = test::call_and_read_body_json(&app, login_request(Some(tampered)).to_request()).await;
+        assert_eq!(body["status"], "MfaNeeded");
+
+        let res = test::call_service(&app, login_request(Some(trusted_device.clone())).to_request()).await;
+        let session = cookie(&res, "sessionId").unwrap();
+        let body: Value = test::read_body_json(res).await;
+        assert_eq!(body["status"], "Success");
+
+        let req = test::TestRequest::get().uri("/api/trusted-devices").cookie(session.clone()).to_request();
+        let devices: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(devices.as_array().unwrap().len(), 1);
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri(&format!("/api/trusted-devices/{}", devices[0]["id"])).cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let body: Value = test::call_and_read_body_json(&app, login_request(Some(trusted_device)).to_request()).await;
+        assert_eq!(body["status"], "MfaNeeded");
+    }
+}
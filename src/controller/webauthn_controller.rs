@@ -0,0 +1,219 @@
+use actix_session::Session;
+use actix_web::{delete, get, post, web::{Data, Json, Path, ServiceConfig}, HttpResponse, Responder};
+use authfix::{multifactor::factor_impl::authenticator::AuthenticatorFactor, AuthToken};
+use serde::Deserialize;
+
+use crate::{controller::security_events::SecurityEvents, domain::{audit_event::{AuditEventType, AuditOutcome, NewAuditEvent}, auth_api::AuthenticationApi, security_notification::SecurityNotification, user::{Mfa, User}, user_api::UserApi, webauthn::{RegistrationResponse, RequestOptions, MFA_ID_WEBAUTHN}, webauthn_api::WebauthnApi}, error::errors::ApiError, service::{mfa_policy_service::SESSION_KEY_MFA_ENROLLMENT_REQUIRED, webauthn_factor::SESSION_KEY_WEBAUTHN_LOGIN}};
+
+const SESSION_KEY_WEBAUTHN_REGISTRATION: &str = "webauthn_registration";
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+struct FinishRegistrationRequest {
+    name: String,
+    credential: RegistrationResponse,
+}
+
+#[derive(Deserialize)]
+struct DeleteCredentialRequest {
+    current_password: String,
+}
+
+#[post("/webauthn/register/start")]
+async fn start_registration(token: AuthToken<User>, session: Session, webauthn_api: Data<dyn WebauthnApi>) -> Result<impl Responder, ApiError> {
+    let options = webauthn_api.start_registration(&token.authenticated_user()).await?;
+    session.insert(SESSION_KEY_WEBAUTHN_REGISTRATION, &options.challenge)?;
+
+    Ok(HttpResponse::Ok().json(options))
+}
+
+/// Stores the new key. If the user has no second factor yet, WebAuthn becomes the active one.
+#[post("/webauthn/register/finish")]
+async fn finish_registration(body: Json<FinishRegistrationRequest>, token: AuthToken<User>, session: Session,
+    webauthn_api: Data<dyn WebauthnApi>, user_api: Data<dyn UserApi>, events: SecurityEvents)
+    -> Result<impl Responder, ApiError>
+{
+    let body = body.into_inner();
+    let name = body.name.trim().to_owned();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(ApiError::Validation(format!("Name must have between 1 and {} characters", MAX_NAME_LENGTH)));
+    }
+
+    // a challenge must only be used once
+    let challenge = session.remove_as::<String>(SESSION_KEY_WEBAUTHN_REGISTRATION)
+        .and_then(|challenge| challenge.ok())
+        .ok_or_else(|| ApiError::Validation("No registration pending. Start the registration first.".to_owned()))?;
+
+    let user_id = token.authenticated_user().id;
+    let credential = webauthn_api.finish_registration(user_id, &name, &challenge, body.credential).await?;
+
+    let mut creds = user_api.find_credentials_by_user_id(user_id).await?;
+    if creds.mfa_config.is_none() {
+        creds.set_mfa(Mfa::new(MFA_ID_WEBAUTHN));
+        user_api.save_credentials(creds).await?;
+    }
+    session.remove(SESSION_KEY_MFA_ENROLLMENT_REQUIRED);
+    events.record(NewAuditEvent::new(AuditEventType::MfaEnrollment, AuditOutcome::Success)
+        .user(user_id).detail(&format!("{} key '{}'", MFA_ID_WEBAUTHN, name))).await;
+    events.notify(&token.authenticated_user(), SecurityNotification::MfaEnrolled { mfa_id: MFA_ID_WEBAUTHN.to_owned() }).await;
+
+    Ok(HttpResponse::Created().json(credential))
+}
+
+#[get("/webauthn/credentials")]
+async fn list_credentials(token: AuthToken<User>, webauthn_api: Data<dyn WebauthnApi>) -> Result<impl Responder, ApiError> {
+    let credentials = webauthn_api.find_by_user_id(token.authenticated_user().id).await?;
+
+    Ok(HttpResponse::Ok().json(credentials))
+}
+
+/// Removes a key. Without keys left the user falls back to TOTP if a secret is enrolled, otherwise MFA is disabled.
+#[delete("/webauthn/credentials/{credential_id}")]
+async fn delete_credential(credential_id: Path<i32>, body: Json<DeleteCredentialRequest>, token: AuthToken<User>, webauthn_api: Data<dyn WebauthnApi>,
+    user_api: Data<dyn UserApi>, auth_api: Data<dyn AuthenticationApi>, events: SecurityEvents) -> Result<impl Responder, ApiError>
+{
+    let user_id = token.authenticated_user().id;
+    events.confirm_password(auth_api.get_ref(), &token.authenticated_user(), &body.current_password, AuditEventType::MfaDisabled).await?;
+    webauthn_api.delete(user_id, credential_id.into_inner()).await?;
+
+    let mut creds = user_api.find_credentials_by_user_id(user_id).await?;
+    let uses_webauthn = creds.mfa_config.as_ref().is_some_and(|mfa| mfa.mfa_id == MFA_ID_WEBAUTHN);
+    if uses_webauthn && webauthn_api.find_by_user_id(user_id).await?.is_empty() {
+        creds.mfa_config = creds.mfa_config
+            .and_then(|mfa| mfa.secret)
+            .map(|secret| Mfa::with_secret(&AuthenticatorFactor::id(), &secret));
+        let disabled = creds.mfa_config.is_none();
+        user_api.save_credentials(creds).await?;
+
+        if disabled {
+            events.record(NewAuditEvent::new(AuditEventType::MfaDisabled, AuditOutcome::Success)
+                .user(user_id).detail("last security key removed")).await;
+            events.notify(&token.authenticated_user(), SecurityNotification::MfaDisabled).await;
+        }
+    }
+
+    Ok(HttpResponse::NoContent())
+}
+
+/// Public path: during the MFA step of the login the user is not yet authenticated.
+/// Only returns the options that were created for the pending login of this session.
+#[get("/webauthn/login-options")]
+async fn login_options(session: Session) -> Result<impl Responder, ApiError> {
+    let options = session.get::<RequestOptions>(SESSION_KEY_WEBAUTHN_LOGIN)?
+        .ok_or_else(|| ApiError::NotFound("No WebAuthn login pending".to_owned()))?;
+
+    Ok(HttpResponse::Ok().json(options))
+}
+
+pub fn config(cfg: &mut ServiceConfig) {
+    cfg.service(start_registration)
+    .service(finish_registration)
+    .service(list_credentials)
+    .service(delete_credential)
+    .service(login_options);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, dev::ServiceResponse, http::StatusCode, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::{user_service::UserService, webauthn_service::soft_authenticator::SoftAuthenticator}};
+
+    const DB: &str = "file:webauthn_controller_test?mode=memory&cache=shared";
+
+    fn session_cookie<B>(res: &ServiceResponse<B>, current: Cookie<'static>) -> Cookie<'static> {
+        res.response().cookies()
+            .find(|c| c.name() == "sessionId")
+            .map(|c| c.into_owned())
+            .unwrap_or(current)
+    }
+
+    #[actix_web::test]
+    async fn should_login_with_software_authenticator_as_second_factor() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let config = Config::default();
+        let rp = config.webauthn.clone();
+        let mut authenticator = SoftAuthenticator::new();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(config))).await;
+        let login = json!({ "email": "test@example.org", "password": "test123" });
+
+        // Login with password only and register the key
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login").set_json(&login).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = session_cookie(&res, Cookie::new("sessionId", ""));
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/webauthn/register/start").cookie(cookie.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        let cookie = session_cookie(&res, cookie);
+        let options: Value = test::read_body_json(res).await;
+        assert_eq!(options["rp"]["id"], "localhost");
+
+        let credential = authenticator.register(&rp.rp_id, &rp.origin, options["challenge"].as_str().unwrap());
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/webauthn/register/finish").cookie(cookie.clone())
+            .set_json(json!({ "name": "Software key", "credential": credential }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        // The next login needs the key
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login").set_json(&login).to_request();
+        let res = test::call_service(&app, req).await;
+        let cookie = session_cookie(&res, Cookie::new("sessionId", ""));
+        let body: Value = test::read_body_json(res).await;
+        assert_eq!(body["mfaId"], "WEBAUTHN_MFA");
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(cookie.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get().uri("/api/webauthn/login-options").cookie(cookie.clone()).to_request();
+        let options: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(options["allowCredentials"][0]["id"], authenticator.credential_id());
+
+        let assertion = authenticator.assert(&rp.rp_id, &rp.origin, options["challenge"].as_str().unwrap());
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login/mfa").cookie(cookie.clone())
+            .set_json(json!({ "code": assertion.to_string() }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = session_cookie(&res, cookie);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(cookie.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Removing the key or switching the factor asks for the password again
+        let req = test::TestRequest::get().uri("/api/webauthn/credentials").cookie(cookie.clone()).to_request();
+        let credentials: Value = test::call_and_read_body_json(&app, req).await;
+        let uri = format!("/api/webauthn/credentials/{}", credentials[0]["id"]);
+
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/mfa/factor").cookie(cookie.clone())
+            .set_json(json!({ "mfa_id": "EMAIL_CODE_MFA", "current_password": "wrong" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri(&uri).cookie(cookie.clone())
+            .set_json(json!({ "current_password": "wrong" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let req = test::TestRequest::get().uri("/api/webauthn/credentials").cookie(cookie.clone()).to_request();
+        let credentials: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(credentials.as_array().unwrap().len(), 1);
+
+        let req = with_csrf_token(test::TestRequest::delete()).uri(&uri).cookie(cookie)
+            .set_json(json!({ "current_password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -1,4 +1,41 @@
 #[allow(dead_code)]
 pub mod user;
 pub mod user_api;
-pub mod auth_api;
\ No newline at end of file
+pub mod auth_api;
+pub mod api_token;
+pub mod api_token_api;
+pub mod webauthn;
+pub mod webauthn_api;
+pub mod mail;
+pub mod mail_api;
+pub mod email_code;
+pub mod email_code_api;
+pub mod trusted_device;
+pub mod trusted_device_api;
+pub mod mfa_policy;
+pub mod mfa_policy_api;
+pub mod password_reset_api;
+pub mod email_change_api;
+pub mod audit_event;
+pub mod audit_api;
+pub mod security_notification;
+pub mod notification_api;
+pub mod activity;
+pub mod activity_api;
+pub mod category;
+pub mod category_api;
+pub mod tag;
+pub mod tag_api;
+pub mod time_entry;
+pub mod time_entry_api;
+pub mod recurrence;
+pub mod calendar_api;
+pub mod activity_import;
+pub mod activity_record;
+pub mod exchange_api;
+pub mod page;
+pub mod period;
+pub mod report;
+pub mod report_api;
+pub mod goal;
+pub mod goal_api;
\ No newline at end of file
@@ -0,0 +1,303 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::error::errors::FieldError;
+
+use super::{page::{SortField, SortOrder}, recurrence::Recurrence, tag::normalize_tag};
+
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 10_000;
+const MAX_TAGS: usize = 20;
+const MAX_SEARCH_TERMS: usize = 10;
+const DEFAULT_SEARCH_RESULTS: usize = 20;
+const MAX_SEARCH_RESULTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Activity {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub start: DateTime<Utc>,
+    /// Open activities have no end yet
+    pub end: Option<DateTime<Utc>>,
+    pub category_id: Option<i32>,
+    /// Sorted by name
+    pub tags: Vec<String>,
+    /// RRULE of recurring activities, the start and end describe the first occurrence
+    pub recurrence: Option<String>,
+    /// IANA name of the zone the activity is planned in, the recurrence keeps its local time of day
+    pub time_zone: Option<String>,
+    /// Original start of an expanded occurrence, identifies it for exceptions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Activity {
+    /// Stored zones are validated, activities without one are in UTC
+    pub fn zone(&self) -> Tz {
+        self.time_zone.as_deref()
+            .and_then(|zone| zone.parse().ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// The occurrence of a recurring activity starting at `start`, it keeps the duration of the series
+    pub fn occurrence(&self, start: DateTime<Utc>) -> Activity {
+        Activity {
+            start,
+            end: self.end.map(|end| start + (end - self.start)),
+            occurrence: Some(start),
+            ..self.clone()
+        }
+    }
+}
+
+/// Body of the create and update requests
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewActivity {
+    pub title: String,
+    pub description: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub category_id: Option<i32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    #[serde(default)]
+    pub time_zone: Option<String>,
+}
+
+impl NewActivity {
+    /// Trims the texts and removes duplicate tags, the first spelling of a tag wins
+    pub fn validate(self) -> Result<NewActivity, Vec<FieldError>> {
+        let title = self.title.trim().to_owned();
+        let description = self.description
+            .map(|description| description.trim().to_owned())
+            .filter(|description| !description.is_empty());
+
+        let mut errors = Vec::new();
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            errors.push(FieldError::new("title", "invalid_length", &format!("Title must have between 1 and {} characters", MAX_TITLE_LENGTH)));
+        }
+        if description.as_ref().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LENGTH) {
+            errors.push(FieldError::new("description", "too_long", &format!("Description must have at most {} characters", MAX_DESCRIPTION_LENGTH)));
+        }
+        if self.end.is_some_and(|end| end < self.start) {
+            errors.push(FieldError::new("end", "before_start", "End must not be before the start"));
+        }
+
+        // stored in the canonical form
+        let recurrence = match self.recurrence.as_deref().map(str::trim).filter(|rule| !rule.is_empty()) {
+            Some(rule) => match rule.parse::<Recurrence>() {
+                Ok(rule) => Some(rule.to_string()),
+                Err(message) => {
+                    errors.push(FieldError::new("recurrence", "invalid_rule", &message));
+                    None
+                },
+            },
+            None => None,
+        };
+
+        // stored with the canonical name of the IANA database
+        let time_zone = match self.time_zone.as_deref().map(str::trim).filter(|zone| !zone.is_empty()) {
+            Some(zone) => match zone.parse::<Tz>() {
+                Ok(zone) => Some(zone.name().to_owned()),
+                Err(_) => {
+                    errors.push(FieldError::new("time_zone", "unknown", &format!("Unknown time zone '{}'", zone)));
+                    None
+                },
+            },
+            None => None,
+        };
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in &self.tags {
+            match normalize_tag(tag) {
+                Ok(tag) if !tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) => tags.push(tag),
+                Ok(_) => {},
+                Err(message) => {
+                    errors.push(FieldError::new("tags", "invalid_length", &message));
+                    break;
+                },
+            }
+        }
+        if tags.len() > MAX_TAGS {
+            errors.push(FieldError::new("tags", "too_many", &format!("An activity can have at most {} tags", MAX_TAGS)));
+        }
+
+        if errors.is_empty() {
+            Ok(NewActivity { title, description, tags, recurrence, time_zone, ..self })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Skips or changes a single occurrence of a recurring activity
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActivityException {
+    pub activity_id: i32,
+    /// Original start of the occurrence
+    pub occurrence: DateTime<Utc>,
+    pub skipped: bool,
+    /// Missing values are taken from the series
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl ActivityException {
+    /// Returns the changed occurrence or `None` if it is skipped
+    pub fn apply(&self, occurrence: Activity) -> Option<Activity> {
+        if self.skipped {
+            return None;
+        }
+
+        // a moved occurrence without a new end keeps its duration
+        let start = self.start.unwrap_or(occurrence.start);
+        Some(Activity {
+            title: self.title.clone().unwrap_or(occurrence.title),
+            description: self.description.clone().or(occurrence.description),
+            start,
+            end: self.end.or(occurrence.end.map(|end| end + (start - occurrence.start))),
+            ..occurrence
+        })
+    }
+}
+
+/// Body of the request that skips or changes an occurrence
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewActivityException {
+    #[serde(default)]
+    pub skipped: bool,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl NewActivityException {
+    pub fn validate(self) -> Result<NewActivityException, Vec<FieldError>> {
+        let title = self.title.map(|title| title.trim().to_owned());
+        let description = self.description
+            .map(|description| description.trim().to_owned())
+            .filter(|description| !description.is_empty());
+
+        let mut errors = Vec::new();
+        if title.as_ref().is_some_and(|title| title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH) {
+            errors.push(FieldError::new("title", "invalid_length", &format!("Title must have between 1 and {} characters", MAX_TITLE_LENGTH)));
+        }
+        if description.as_ref().is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LENGTH) {
+            errors.push(FieldError::new("description", "too_long", &format!("Description must have at most {} characters", MAX_DESCRIPTION_LENGTH)));
+        }
+
+        if errors.is_empty() {
+            Ok(NewActivityException { title, description, ..self })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// All conditions must match
+#[derive(Debug, Clone, Default)]
+pub struct ActivityFilter {
+    /// Activities that end at or after this time, open activities count as ending at their start
+    pub from: Option<DateTime<Utc>>,
+    /// Activities that start before this time. Recurring activities are expanded into their occurrences
+    /// up to this time, without it they are listed once.
+    pub to: Option<DateTime<Utc>>,
+    /// Activities in any of these categories
+    pub category_ids: Vec<i32>,
+    /// Activities with all of these tags
+    pub all_tags: Vec<String>,
+    /// Activities with at least one of these tags
+    pub any_tags: Vec<String>,
+    /// Only recurring (`true`) or only single (`false`) activities
+    pub recurring: Option<bool>,
+}
+
+/// Words of a search, every word must occur as prefix of a word in the title, description or tags
+#[derive(Debug, Clone)]
+pub struct ActivitySearch {
+    pub terms: Vec<String>,
+    /// At most [MAX_SEARCH_RESULTS]
+    pub limit: usize,
+}
+
+impl ActivitySearch {
+    /// Punctuation only separates words, a search without any word is invalid
+    pub fn new(text: &str, limit: Option<usize>) -> Result<ActivitySearch, Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let terms: Vec<String> = text.split(|c: char| !c.is_alphanumeric())
+            .filter(|term| !term.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+
+        if terms.is_empty() {
+            errors.push(FieldError::new("q", "required", "The search must contain at least one word"));
+        } else if terms.len() > MAX_SEARCH_TERMS {
+            errors.push(FieldError::new("q", "too_many_words", &format!("The search must have at most {} words", MAX_SEARCH_TERMS)));
+        }
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_RESULTS);
+        if !(1..=MAX_SEARCH_RESULTS).contains(&limit) {
+            errors.push(FieldError::new("limit", "out_of_range", &format!("Limit must be between 1 and {}", MAX_SEARCH_RESULTS)));
+        }
+
+        if errors.is_empty() {
+            Ok(ActivitySearch { terms, limit })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Activity found by a search, best matches first
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivitySearchHit {
+    #[serde(flatten)]
+    pub activity: Activity,
+    /// HTML escaped excerpt of the best matching field, the matching words are wrapped in `<mark>`
+    pub snippet: String,
+}
+
+/// `start` newest first by default, `title` and `updated_at`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ActivitySort {
+    #[default]
+    Start,
+    Title,
+    UpdatedAt,
+}
+
+impl FromStr for ActivitySort {
+    type Err = String;
+
+    fn from_str(field: &str) -> Result<Self, Self::Err> {
+        match field {
+            "start" => Ok(ActivitySort::Start),
+            "title" => Ok(ActivitySort::Title),
+            "updated_at" => Ok(ActivitySort::UpdatedAt),
+            _ => Err(format!("Activities cannot be sorted by '{}', use start, title or updated_at", field)),
+        }
+    }
+}
+
+impl SortField for ActivitySort {
+    fn default_order(&self) -> SortOrder {
+        match self {
+            ActivitySort::Title => SortOrder::Asc,
+            ActivitySort::Start | ActivitySort::UpdatedAt => SortOrder::Desc,
+        }
+    }
+
+    const DATE_RANGE: bool = true;
+}
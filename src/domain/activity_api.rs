@@ -0,0 +1,31 @@
+use async_trait::async_trait;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::ActivityError;
+
+use super::{activity::{Activity, ActivityException, ActivityFilter, ActivitySearch, ActivitySearchHit, ActivitySort, NewActivity, NewActivityException}, activity_import::{ImportSummary, ImportedActivity}, page::{Page, PageRequest}};
+
+/// Activities are always accessed through their owner, foreign ids are reported as not found
+#[async_trait]
+pub trait ActivityApi: Send + Sync {
+    async fn create(&self, user_id: i32, activity: NewActivity) -> Result<Activity, ActivityError>;
+    async fn find_by_id(&self, user_id: i32, activity_id: i32) -> Result<Activity, ActivityError>;
+    /// Newest first
+    async fn find(&self, user_id: i32, filter: &ActivityFilter) -> Result<Vec<Activity>, ActivityError>;
+    /// Like [ActivityApi::find] in the requested order. With an end of the range the page is taken from the expanded occurrences.
+    async fn find_page(&self, user_id: i32, filter: &ActivityFilter, page: &PageRequest<ActivitySort>) -> Result<Page<Activity>, ActivityError>;
+    /// Ranked by relevance, recurring activities are found once as series
+    async fn search(&self, user_id: i32, search: &ActivitySearch) -> Result<Vec<ActivitySearchHit>, ActivityError>;
+    async fn update(&self, user_id: i32, activity_id: i32, activity: NewActivity) -> Result<Activity, ActivityError>;
+    async fn delete(&self, user_id: i32, activity_id: i32) -> Result<(), ActivityError>;
+    /// Ordered by occurrence
+    async fn find_exceptions(&self, user_id: i32, activity_id: i32) -> Result<Vec<ActivityException>, ActivityError>;
+    /// Replaces an existing exception of the occurrence
+    async fn save_exception(&self, user_id: i32, activity_id: i32, occurrence: DateTime<Utc>, exception: NewActivityException)
+        -> Result<ActivityException, ActivityError>;
+    async fn delete_exception(&self, user_id: i32, activity_id: i32, occurrence: DateTime<Utc>) -> Result<(), ActivityError>;
+    /// Creates the activities in one transaction. Activities imported before with the same UID are updated,
+    /// their category is kept.
+    async fn import(&self, user_id: i32, activities: Vec<ImportedActivity>) -> Result<ImportSummary, ActivityError>;
+}
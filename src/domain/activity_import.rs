@@ -0,0 +1,40 @@
+use serde::Serialize;
+
+use super::{activity::NewActivity, time_entry::NewTimeEntry};
+
+/// Activity read from an upload, the UID identifies it when the same source is imported again
+#[derive(Debug, Clone)]
+pub struct ImportedActivity {
+    /// Position in the upload or the line a CSV record starts at, starting with 1
+    pub index: usize,
+    pub uid: Option<String>,
+    pub activity: NewActivity,
+    /// Name of the category, missing categories are created
+    pub category: Option<String>,
+    /// Only added to created activities, every entry needs an end
+    pub time_entries: Vec<NewTimeEntry>,
+}
+
+/// Reason why an entry of the upload was skipped
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportIssue {
+    pub index: usize,
+    pub uid: Option<String>,
+    pub message: String,
+}
+
+/// Unchanged and invalid entries are skipped, the invalid ones are listed in `errors`
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub created: u32,
+    pub updated: u32,
+    pub skipped: u32,
+    pub errors: Vec<ImportIssue>,
+}
+
+impl ImportSummary {
+    pub fn reject(&mut self, issue: ImportIssue) {
+        self.skipped += 1;
+        self.errors.push(issue);
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::time_entry::NewTimeEntry;
+
+/// An activity in the CSV and JSON exchange formats, the category is referenced by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    /// Matches the activity when the file is imported again
+    #[serde(default)]
+    pub uid: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub recurrence: Option<String>,
+    /// IANA zone the recurrence is expanded in
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub time_entries: Vec<TimeEntryRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeEntryRecord {
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl From<TimeEntryRecord> for NewTimeEntry {
+    fn from(record: TimeEntryRecord) -> Self {
+        NewTimeEntry {
+            start: record.start,
+            end: record.end,
+            note: record.note,
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::ApiTokenError;
+
+use super::api_token::{ApiToken, CreatedApiToken, TokenScope};
+
+#[async_trait]
+pub trait ApiTokenApi: Send + Sync {
+    async fn create_token(&self, user_id: i32, name: &str, scopes: Vec<TokenScope>, expires_at: DateTime<Utc>) -> Result<CreatedApiToken, ApiTokenError>;
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<ApiToken>, ApiTokenError>;
+    async fn revoke(&self, user_id: i32, token_id: i32) -> Result<(), ApiTokenError>;
+    /// Looks up the token by its plain secret and records its usage
+    async fn authenticate(&self, secret: &str) -> Result<ApiToken, ApiTokenError>;
+}
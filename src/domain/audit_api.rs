@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+
+use crate::error::errors::AuditError;
+
+use super::audit_event::{AuditEvent, AuditFilter, NewAuditEvent};
+
+#[async_trait]
+pub trait AuditApi: Send + Sync {
+    async fn record(&self, event: NewAuditEvent) -> Result<(), AuditError>;
+    /// Newest events first
+    async fn find(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>, AuditError>;
+    /// True if the user logged in before, but never with this user agent.
+    /// Has to be called before the current login is recorded.
+    async fn is_new_device(&self, user_id: i32, user_agent: Option<&str>) -> Result<bool, AuditError>;
+}
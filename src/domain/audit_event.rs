@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use actix_web::{http::header::USER_AGENT, HttpRequest};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longer user agents are cut, they are only shown to users and admins
+const MAX_USER_AGENT_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    Login,
+    Mfa,
+    Logout,
+    Registration,
+    PasswordChange,
+    PasswordResetRequest,
+    PasswordReset,
+    EmailChangeRequest,
+    EmailChange,
+    MfaEnrollment,
+    MfaDisabled,
+    TrustedDevice,
+    AccountLocked,
+    AccountUnlocked,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::Login => "login",
+            AuditEventType::Mfa => "mfa",
+            AuditEventType::Logout => "logout",
+            AuditEventType::Registration => "registration",
+            AuditEventType::PasswordChange => "password_change",
+            AuditEventType::PasswordResetRequest => "password_reset_request",
+            AuditEventType::PasswordReset => "password_reset",
+            AuditEventType::EmailChangeRequest => "email_change_request",
+            AuditEventType::EmailChange => "email_change",
+            AuditEventType::MfaEnrollment => "mfa_enrollment",
+            AuditEventType::MfaDisabled => "mfa_disabled",
+            AuditEventType::TrustedDevice => "trusted_device",
+            AuditEventType::AccountLocked => "account_locked",
+            AuditEventType::AccountUnlocked => "account_unlocked",
+        }
+    }
+}
+
+impl FromStr for AuditEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "login" => Ok(AuditEventType::Login),
+            "mfa" => Ok(AuditEventType::Mfa),
+            "logout" => Ok(AuditEventType::Logout),
+            "registration" => Ok(AuditEventType::Registration),
+            "password_change" => Ok(AuditEventType::PasswordChange),
+            "password_reset_request" => Ok(AuditEventType::PasswordResetRequest),
+            "password_reset" => Ok(AuditEventType::PasswordReset),
+            "email_change_request" => Ok(AuditEventType::EmailChangeRequest),
+            "email_change" => Ok(AuditEventType::EmailChange),
+            "mfa_enrollment" => Ok(AuditEventType::MfaEnrollment),
+            "mfa_disabled" => Ok(AuditEventType::MfaDisabled),
+            "trusted_device" => Ok(AuditEventType::TrustedDevice),
+            "account_locked" => Ok(AuditEventType::AccountLocked),
+            "account_unlocked" => Ok(AuditEventType::AccountUnlocked),
+            _ => Err(format!("Unknown audit event type: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(AuditOutcome::Success),
+            "failure" => Ok(AuditOutcome::Failure),
+            _ => Err(format!("Unknown audit outcome: {}", s)),
+        }
+    }
+}
+
+/// A stored security event. Events are never changed or deleted.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    /// None if the event cannot be attributed, e.g. a login with an unknown email address
+    pub user_id: Option<i32>,
+    pub event_type: AuditEventType,
+    pub outcome: AuditOutcome,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An event to record, see [crate::domain::audit_api::AuditApi::record]
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub user_id: Option<i32>,
+    pub event_type: AuditEventType,
+    pub outcome: AuditOutcome,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(event_type: AuditEventType, outcome: AuditOutcome) -> Self {
+        NewAuditEvent {
+            user_id: None,
+            event_type,
+            outcome,
+            ip: None,
+            user_agent: None,
+            detail: None,
+        }
+    }
+
+    pub fn user(mut self, user_id: i32) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    /// Takes the peer address, forwarding headers can be set by any client
+    pub fn request(mut self, req: &HttpRequest) -> Self {
+        self.ip = req.peer_addr().map(|addr| addr.ip().to_string());
+        self.user_agent = req.headers().get(USER_AGENT)
+            .and_then(|ua| ua.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_owned());
+        self
+    }
+}
+
+/// Query of the admin endpoint, all conditions are optional
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditFilter {
+    pub user_id: Option<i32>,
+    pub event_type: Option<AuditEventType>,
+    pub outcome: Option<AuditOutcome>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+}
@@ -1,8 +1,10 @@
 use async_trait::async_trait;
 
+use crate::error::errors::QueryUserError;
+
 use super::user::User;
 
 #[async_trait]
 pub trait AuthenticationApi: Send + Sync {
-    async fn is_password_correct(&self, user: &User, password: &str) -> bool; 
+    async fn is_password_correct(&self, user: &User, password: &str) -> Result<bool, QueryUserError>;
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::CalendarError;
+
+use super::activity_import::ImportSummary;
+
+/// iCalendar export of the activities, also published as a feed that calendar clients subscribe to
+#[async_trait]
+pub trait CalendarApi: Send + Sync {
+    /// Calendar with the activities that overlap the range, recurring activities are written with their rule
+    async fn export(&self, user_id: i32, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<String, CalendarError>;
+    /// Creates activities from the events of the calendar, events are identified by their UID
+    async fn import(&self, user_id: i32, calendar: String) -> Result<ImportSummary, CalendarError>;
+    /// Replaces the feed of the user, the secret is only returned here
+    async fn create_feed(&self, user_id: i32) -> Result<String, CalendarError>;
+    async fn delete_feed(&self, user_id: i32) -> Result<(), CalendarError>;
+    /// Owner of the feed with the secret
+    async fn find_feed_user_id(&self, secret: &str) -> Result<i32, CalendarError>;
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::errors::FieldError;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_ICON_LENGTH: usize = 50;
+
+/// User-defined group of activities, every activity belongs to at most one category
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Category {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub name: String,
+    /// Hex color like `#1e88e5`
+    pub color: String,
+    /// Name of an icon of the frontend
+    pub icon: Option<String>,
+}
+
+/// Body of the create and update requests
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewCategory {
+    pub name: String,
+    pub color: String,
+    pub icon: Option<String>,
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl NewCategory {
+    /// Trims all values and lowercases the color
+    pub fn validate(self) -> Result<NewCategory, Vec<FieldError>> {
+        let name = self.name.trim().to_owned();
+        let color = self.color.trim().to_lowercase();
+        let icon = self.icon
+            .map(|icon| icon.trim().to_owned())
+            .filter(|icon| !icon.is_empty());
+
+        let mut errors = Vec::new();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", "invalid_length", &format!("Name must have between 1 and {} characters", MAX_NAME_LENGTH)));
+        }
+        if !is_hex_color(&color) {
+            errors.push(FieldError::new("color", "invalid_format", "Color must be a hex color like #1e88e5"));
+        }
+        if icon.as_ref().is_some_and(|icon| icon.chars().count() > MAX_ICON_LENGTH) {
+            errors.push(FieldError::new("icon", "too_long", &format!("Icon must have at most {} characters", MAX_ICON_LENGTH)));
+        }
+
+        if errors.is_empty() {
+            Ok(NewCategory { name, color, icon })
+        } else {
+            Err(errors)
+        }
+    }
+}
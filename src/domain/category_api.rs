@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+
+use crate::error::errors::CategoryError;
+
+use super::category::{Category, NewCategory};
+
+#[async_trait]
+pub trait CategoryApi: Send + Sync {
+    async fn create(&self, user_id: i32, category: NewCategory) -> Result<Category, CategoryError>;
+    /// Sorted by name
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<Category>, CategoryError>;
+    async fn update(&self, user_id: i32, category_id: i32, category: NewCategory) -> Result<Category, CategoryError>;
+    /// Activities of the category are kept without category
+    async fn delete(&self, user_id: i32, category_id: i32) -> Result<(), CategoryError>;
+}
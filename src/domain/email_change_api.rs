@@ -0,0 +1,12 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::EmailChangeError;
+
+#[async_trait]
+pub trait EmailChangeApi: Send + Sync {
+    /// Creates a single-use token for the new address, a pending change of the user is replaced
+    async fn create_token(&self, user_id: i32, email: &str, expires_at: DateTime<Utc>) -> Result<String, EmailChangeError>;
+    /// Consumes a valid token of the user and returns the address it confirms
+    async fn take_email(&self, user_id: i32, token: &str) -> Result<String, EmailChangeError>;
+}
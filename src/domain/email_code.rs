@@ -0,0 +1,2 @@
+/// `mfa_id` of the email one-time code factor in the users credentials
+pub const MFA_ID_EMAIL_CODE: &str = "EMAIL_CODE_MFA";
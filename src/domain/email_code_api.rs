@@ -0,0 +1,11 @@
+use async_trait::async_trait;
+
+use crate::{domain::user::User, error::errors::EmailCodeError};
+
+#[async_trait]
+pub trait EmailCodeApi: Send + Sync {
+    /// Creates a new code for the user and sends it by mail. A previously sent code becomes invalid.
+    async fn send_code(&self, user: &User) -> Result<(), EmailCodeError>;
+    /// Checks the code. Every wrong attempt is counted, the code is deleted once it was used.
+    async fn verify_code(&self, user_id: i32, code: &str) -> Result<(), EmailCodeError>;
+}
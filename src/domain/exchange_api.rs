@@ -0,0 +1,25 @@
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::error::errors::ExchangeError;
+
+use super::activity_import::ImportSummary;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExchangeFormat {
+    /// Header row with the fields of [super::activity_record::ActivityRecord], tags are separated by `;`
+    /// and the time entries are a JSON array
+    Csv,
+    /// Array of [super::activity_record::ActivityRecord]
+    Json,
+}
+
+/// Bulk exchange of all activities of a user with spreadsheets and other tools
+#[async_trait]
+pub trait ExchangeApi: Send + Sync {
+    /// Streams the activities oldest first, so large exports are not held in memory
+    fn export(&self, user_id: i32, format: ExchangeFormat) -> BoxStream<'static, Result<String, ExchangeError>>;
+    /// Creates an activity for every valid entry or updates the one with its uid, invalid entries are reported
+    /// with their position or the line of the CSV file
+    async fn import(&self, user_id: i32, format: ExchangeFormat, content: String) -> Result<ImportSummary, ExchangeError>;
+}
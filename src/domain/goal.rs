@@ -0,0 +1,148 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::errors::FieldError;
+
+use super::{period::Period, tag::normalize_tag};
+
+const MAX_TITLE_LENGTH: usize = 200;
+
+/// What counts towards the target
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GoalMetric {
+    /// Activities and occurrences of recurring activities started in the period, skipped occurrences do not count
+    Count,
+    /// Seconds of the time entries started in the period
+    Duration,
+}
+
+impl GoalMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GoalMetric::Count => "count",
+            GoalMetric::Duration => "duration",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<GoalMetric> {
+        match value {
+            "count" => Some(GoalMetric::Count),
+            "duration" => Some(GoalMetric::Duration),
+            _ => None,
+        }
+    }
+}
+
+/// Target per period for the activities of a category or with a tag, e.g. exercise 3 times per week
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Goal {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub title: String,
+    /// Either the category or the tag is set
+    pub category_id: Option<i32>,
+    pub tag: Option<String>,
+    pub period: Period,
+    pub metric: GoalMetric,
+    /// Number of activities or seconds
+    pub target: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body of the create and update requests
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewGoal {
+    pub title: String,
+    pub category_id: Option<i32>,
+    pub tag: Option<String>,
+    pub period: Period,
+    pub metric: GoalMetric,
+    pub target: i64,
+}
+
+impl NewGoal {
+    /// Trims the title and the tag
+    pub fn validate(self) -> Result<NewGoal, Vec<FieldError>> {
+        let title = self.title.trim().to_owned();
+        let mut errors = Vec::new();
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            errors.push(FieldError::new("title", "invalid_length", &format!("Title must have between 1 and {} characters", MAX_TITLE_LENGTH)));
+        }
+
+        let tag = match self.tag.as_deref().map(normalize_tag).transpose() {
+            Ok(tag) => tag,
+            Err(message) => {
+                errors.push(FieldError::new("tag", "invalid_length", &message));
+                None
+            },
+        };
+        if self.category_id.is_some() == (tag.is_some() || self.tag.is_some()) {
+            errors.push(FieldError::new("category_id", "category_or_tag", "A goal needs either a category or a tag"));
+        }
+        if self.target <= 0 {
+            errors.push(FieldError::new("target", "not_positive", "Target must be positive"));
+        }
+
+        if errors.is_empty() {
+            Ok(NewGoal { title, tag, ..self })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// A goal with the progress of the current period
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalProgress {
+    #[serde(flatten)]
+    pub goal: Goal,
+    pub period_start: NaiveDate,
+    /// Number of activities or seconds in the current period
+    pub current: i64,
+    pub achieved: bool,
+    /// Achieved periods in a row up to now, the current period only breaks it when it is over
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+impl GoalProgress {
+    /// Takes the values per period start, periods without activities may be missing
+    pub fn new(goal: Goal, values: &[(NaiveDate, i64)], today: NaiveDate) -> GoalProgress {
+        let period_start = goal.period.start_of(today);
+        let mut achieved: Vec<NaiveDate> = values.iter()
+            .filter(|(_, value)| *value >= goal.target)
+            .map(|(start, _)| *start)
+            .collect();
+        achieved.sort();
+
+        let mut longest_streak = 0;
+        let mut streak = 0;
+        for (index, start) in achieved.iter().enumerate() {
+            streak = match index.checked_sub(1).map(|previous| achieved[previous]) {
+                Some(previous) if goal.period.previous(*start) == previous => streak + 1,
+                _ => 1,
+            };
+            longest_streak = longest_streak.max(streak);
+        }
+
+        let current = values.iter().find(|(start, _)| *start == period_start).map(|(_, value)| *value).unwrap_or_default();
+        let is_achieved = current >= goal.target;
+        let mut current_streak = 0;
+        let mut start = if is_achieved { period_start } else { goal.period.previous(period_start) };
+        while achieved.binary_search(&start).is_ok() {
+            current_streak += 1;
+            start = goal.period.previous(start);
+        }
+
+        GoalProgress {
+            goal,
+            period_start,
+            current,
+            achieved: is_achieved,
+            current_streak,
+            longest_streak,
+        }
+    }
+}
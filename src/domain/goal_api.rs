@@ -0,0 +1,16 @@
+use async_trait::async_trait;
+
+use crate::error::errors::GoalError;
+
+use super::goal::{GoalProgress, NewGoal};
+
+/// Goals are always accessed through their owner, foreign ids are reported as not found
+#[async_trait]
+pub trait GoalApi: Send + Sync {
+    async fn create(&self, user_id: i32, goal: NewGoal) -> Result<GoalProgress, GoalError>;
+    async fn find_by_id(&self, user_id: i32, goal_id: i32) -> Result<GoalProgress, GoalError>;
+    /// Oldest first
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<GoalProgress>, GoalError>;
+    async fn update(&self, user_id: i32, goal_id: i32, goal: NewGoal) -> Result<GoalProgress, GoalError>;
+    async fn delete(&self, user_id: i32, goal_id: i32) -> Result<(), GoalError>;
+}
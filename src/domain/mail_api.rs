@@ -0,0 +1,10 @@
+use async_trait::async_trait;
+
+use crate::error::errors::MailError;
+
+use super::mail::Mail;
+
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
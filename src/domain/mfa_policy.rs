@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use super::user::Role;
+
+/// Decides which users must set up a second factor before they can use the API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MfaPolicy {
+    pub required_for_all: bool,
+    #[serde(default)]
+    pub required_roles: Vec<Role>,
+}
+
+impl MfaPolicy {
+    pub fn applies_to(&self, role: Role) -> bool {
+        self.required_for_all || self.required_roles.contains(&role)
+    }
+}
@@ -0,0 +1,13 @@
+use async_trait::async_trait;
+
+use crate::error::errors::MfaPolicyError;
+
+use super::{mfa_policy::MfaPolicy, user::User};
+
+#[async_trait]
+pub trait MfaPolicyApi: Send + Sync {
+    async fn find_policy(&self) -> Result<MfaPolicy, MfaPolicyError>;
+    async fn save_policy(&self, policy: MfaPolicy) -> Result<MfaPolicy, MfaPolicyError>;
+    /// True if the policy applies to the user and no second factor is configured
+    async fn requires_enrollment(&self, user: &User) -> Result<bool, MfaPolicyError>;
+}
@@ -0,0 +1,11 @@
+use async_trait::async_trait;
+
+use crate::error::errors::MailError;
+
+use super::{security_notification::SecurityNotification, user::User};
+
+#[async_trait]
+pub trait NotificationApi: Send + Sync {
+    /// Mails the notification to the current address of the user
+    async fn notify(&self, user: &User, notification: SecurityNotification) -> Result<(), MailError>;
+}
@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Fields a list can be sorted by, parsed from the `sort` query parameter
+pub trait SortField: FromStr<Err = String> + Copy + Default + Send + Sync + 'static {
+    fn default_order(&self) -> SortOrder;
+    /// Lists without dates reject the `from` and `to` filters
+    const DATE_RANGE: bool;
+}
+
+/// Position after the last item of a page: the sort key of the item as text and its id to break ties
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub key: String,
+    pub id: i32,
+}
+
+impl Cursor {
+    /// Opaque and URL safe token for clients
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(token: &str) -> Option<Cursor> {
+        let json = URL_SAFE_NO_PAD.decode(token).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PageRequest<S: SortField> {
+    pub sort: S,
+    pub order: SortOrder,
+    /// First page without cursor
+    pub cursor: Option<Cursor>,
+    pub limit: usize,
+}
+
+impl<S: SortField> PageRequest<S> {
+    /// Comparison of the keyset condition, the items after the cursor in the sort order
+    pub fn operator(&self) -> &'static str {
+        match self.order {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        }
+    }
+
+    pub fn direction(&self) -> &'static str {
+        match self.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Missing on the last page
+    pub next_cursor: Option<Cursor>,
+}
+
+impl<T> Page<T> {
+    /// Takes the items with their cursors queried with one more than the limit,
+    /// the additional item shows that a next page exists
+    pub fn new(mut items: Vec<(T, Cursor)>, limit: usize) -> Page<T> {
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|(_, cursor)| cursor.clone())
+        } else {
+            None
+        };
+
+        Page { items: items.into_iter().map(|(item, _)| item).collect(), next_cursor }
+    }
+}
@@ -0,0 +1,14 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::PasswordResetError;
+
+#[async_trait]
+pub trait PasswordResetApi: Send + Sync {
+    /// Creates a single-use reset token. Older tokens of the user stay valid until they expire.
+    async fn create_token(&self, user_id: i32, expires_at: DateTime<Utc>) -> Result<String, PasswordResetError>;
+    /// Returns the user of a valid token without consuming it
+    async fn find_user_id(&self, token: &str) -> Result<i32, PasswordResetError>;
+    /// Invalidates all tokens of the user, called after the password was changed
+    async fn delete_tokens(&self, user_id: i32) -> Result<(), PasswordResetError>;
+}
@@ -0,0 +1,282 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// Upper bound of the occurrences expanded for one series and request
+pub const MAX_OCCURRENCES: usize = 1000;
+const MAX_COUNT: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// A weekday of `BYDAY`, monthly rules may pick the n-th weekday of the month, e.g. `2TU` or `-1FR`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByDay {
+    pub ordinal: Option<i8>,
+    pub weekday: Weekday,
+}
+
+/// Subset of the RFC 5545 RRULE with `FREQ`, `INTERVAL`, `BYDAY`, `UNTIL` and `COUNT`.
+/// Occurrences are computed in the time zone of the series and keep its local time of day across daylight saving changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<ByDay>,
+    /// Inclusive, a date without time includes the whole day
+    pub until: Option<DateTime<Utc>>,
+    /// The series start is the first occurrence
+    pub count: Option<u32>,
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    match code {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_by_day(value: &str) -> Result<ByDay, String> {
+    let invalid = || format!("Invalid BYDAY value '{}'", value);
+    if value.len() < 2 || !value.is_ascii() {
+        return Err(invalid());
+    }
+
+    let (ordinal, code) = value.split_at(value.len() - 2);
+    let weekday = parse_weekday(code).ok_or_else(invalid)?;
+    let ordinal = match ordinal {
+        "" => None,
+        ordinal => Some(ordinal.trim_start_matches('+').parse::<i8>()
+            .ok()
+            .filter(|n| *n != 0 && (-5..=5).contains(n))
+            .ok_or_else(invalid)?),
+    };
+
+    Ok(ByDay { ordinal, weekday })
+}
+
+fn parse_until(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim_end_matches('Z');
+    if let Ok(until) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Ok(Utc.from_utc_datetime(&until));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(23, 59, 59))
+        .map(|until| Utc.from_utc_datetime(&until))
+        .ok_or_else(|| format!("Invalid UNTIL value '{}'", value))
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    /// Accepts the rule with or without the `RRULE:` prefix, names and values are case-insensitive
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim().to_ascii_uppercase();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(&rule);
+
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut by_day = Vec::new();
+        let mut until = None;
+        let mut count = None;
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(|| format!("Invalid rule part '{}'", part))?;
+            match name {
+                "FREQ" => frequency = Some(match value {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    _ => return Err(format!("Unsupported frequency '{}'", value)),
+                }),
+                "INTERVAL" => interval = value.parse().ok()
+                    .filter(|interval| *interval > 0)
+                    .ok_or("INTERVAL must be a positive number")?,
+                "BYDAY" => by_day = value.split(',').map(parse_by_day).collect::<Result<_, _>>()?,
+                "UNTIL" => until = Some(parse_until(value)?),
+                "COUNT" => count = Some(value.parse().ok()
+                    .filter(|count| (1..=MAX_COUNT).contains(count))
+                    .ok_or_else(|| format!("COUNT must be between 1 and {}", MAX_COUNT))?),
+                _ => return Err(format!("Unsupported rule part '{}'", name)),
+            }
+        }
+
+        let frequency = frequency.ok_or("FREQ is required")?;
+        if until.is_some() && count.is_some() {
+            return Err("UNTIL and COUNT must not be combined".to_owned());
+        }
+        if frequency != Frequency::Monthly && by_day.iter().any(|day| day.ordinal.is_some()) {
+            return Err("Numbered weekdays are only supported by monthly rules".to_owned());
+        }
+
+        Ok(Recurrence { frequency, interval, by_day, until, count })
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        };
+        write!(f, "FREQ={}", frequency)?;
+
+        if self.interval > 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days = self.by_day.iter()
+                .map(|day| format!("{}{}", day.ordinal.map(|n| n.to_string()).unwrap_or_default(), weekday_code(day.weekday)))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(f, ";BYDAY={}", days)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Local times skipped by a daylight saving change are moved forward by the gap, repeated ones take the earlier instant
+pub fn local_to_utc(zone: Tz, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    zone.from_local_datetime(&time).earliest()
+        .or_else(|| zone.from_local_datetime(&(time + Duration::hours(1))).earliest())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Days of the month with the weekday, the ordinal picks one of them counted from the start or the end
+fn weekdays_of_month(month_start: NaiveDate, day: ByDay) -> Vec<NaiveDate> {
+    let days: Vec<NaiveDate> = month_start.iter_days()
+        .take_while(|date| date.month() == month_start.month())
+        .filter(|date| date.weekday() == day.weekday)
+        .collect();
+
+    match day.ordinal {
+        None => days,
+        Some(n) if n > 0 => days.get(n as usize - 1).into_iter().copied().collect(),
+        Some(n) => days.len().checked_sub(n.unsigned_abs() as usize)
+            .and_then(|index| days.get(index))
+            .into_iter()
+            .copied()
+            .collect(),
+    }
+}
+
+impl Recurrence {
+    /// First day and candidate dates of the n-th period after the series start, `None` beyond the supported dates
+    fn period(&self, first: NaiveDate, period: u64) -> Option<(NaiveDate, Vec<NaiveDate>)> {
+        let steps = period.checked_mul(self.interval.into())?;
+        match self.frequency {
+            Frequency::Daily => {
+                let date = first.checked_add_days(Days::new(steps))?;
+                let matches = self.by_day.is_empty() || self.by_day.iter().any(|day| day.weekday == date.weekday());
+                Some((date, if matches { vec![date] } else { vec![] }))
+            },
+            Frequency::Weekly => {
+                let week = first.checked_sub_days(Days::new(first.weekday().num_days_from_monday().into()))?
+                    .checked_add_days(Days::new(steps.checked_mul(7)?))?;
+                let mut weekdays: Vec<Weekday> = self.by_day.iter().map(|day| day.weekday).collect();
+                if weekdays.is_empty() {
+                    weekdays.push(first.weekday());
+                }
+
+                let mut dates = weekdays.iter()
+                    .filter_map(|weekday| week.checked_add_days(Days::new(weekday.num_days_from_monday().into())))
+                    .collect::<Vec<_>>();
+                dates.sort();
+                dates.dedup();
+                Some((week, dates))
+            },
+            Frequency::Monthly => {
+                let month_start = first.with_day(1)?.checked_add_months(Months::new(steps.try_into().ok()?))?;
+                let mut dates = if self.by_day.is_empty() {
+                    // months without the day of the series start are skipped
+                    month_start.with_day(first.day()).into_iter().collect()
+                } else {
+                    self.by_day.iter().flat_map(|day| weekdays_of_month(month_start, *day)).collect::<Vec<_>>()
+                };
+                dates.sort();
+                dates.dedup();
+                Some((month_start, dates))
+            },
+        }
+    }
+
+    /// Whole periods between the series start and `date` that cannot contain an occurrence at or after `date`
+    fn periods_before(&self, first: NaiveDate, date: NaiveDate) -> u64 {
+        let periods = match self.frequency {
+            Frequency::Daily => (date - first).num_days() / i64::from(self.interval),
+            Frequency::Weekly => (date - first).num_days() / (7 * i64::from(self.interval)),
+            Frequency::Monthly => {
+                let months = (date.year() - first.year()) * 12 + date.month() as i32 - first.month() as i32;
+                i64::from(months) / i64::from(self.interval)
+            },
+        };
+
+        (periods - 1).max(0) as u64
+    }
+
+    /// Starts of the occurrences in `[from, to)` of a series starting at `start` in `zone`, in order and without limit
+    pub fn iter_occurrences(&self, start: DateTime<Utc>, zone: Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let local_start = start.with_timezone(&zone);
+        let first = local_start.date_naive();
+        let time = local_start.time();
+        let last = to.with_timezone(&zone).date_naive();
+
+        // the periods before the range only have to be visited to count the occurrences
+        let skipped = match self.count {
+            Some(_) => 0,
+            None => self.periods_before(first, from.with_timezone(&zone).date_naive()),
+        };
+        (skipped..)
+            .map_while(move |period| self.period(first, period))
+            .take_while(move |(period_start, _)| *period_start <= last)
+            .flat_map(move |(_, dates)| dates.into_iter().filter(move |date| *date >= first))
+            .filter_map(move |date| local_to_utc(zone, date.and_time(time)))
+            .enumerate()
+            .take_while(move |(counted, occurrence)| {
+                *occurrence < to && self.until.is_none_or(|until| *occurrence <= until) && self.count.is_none_or(|count| *counted < count as usize)
+            })
+            .map(|(_, occurrence)| occurrence)
+            .filter(move |occurrence| *occurrence >= from)
+    }
+
+    /// Starts of the occurrences in `[from, to)` of a series starting at `start` in `zone`, at most [MAX_OCCURRENCES]
+    pub fn occurrences(&self, start: DateTime<Utc>, zone: Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        self.iter_occurrences(start, zone, from, to).take(MAX_OCCURRENCES).collect()
+    }
+
+    pub fn is_occurrence(&self, start: DateTime<Utc>, zone: Tz, occurrence: DateTime<Utc>) -> bool {
+        self.occurrences(start, zone, occurrence, occurrence + Duration::seconds(1)).contains(&occurrence)
+    }
+}
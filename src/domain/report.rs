@@ -0,0 +1,67 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::period::Period;
+
+/// Longest range of a report, days are the smallest period
+const MAX_REPORT_DAYS: i64 = 3 * 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportGroup {
+    Category,
+    /// Activities with several tags count for each of them
+    Tag,
+}
+
+/// Activities are counted in the period of their start, recurring activities with every occurrence,
+/// at most [super::recurrence::MAX_OCCURRENCES] per series. Time entries count in the period of their start,
+/// running entries up to now.
+#[derive(Debug, Clone)]
+pub struct ReportQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub period: Option<Period>,
+    pub group: Option<ReportGroup>,
+}
+
+impl ReportQuery {
+    pub fn validate(self) -> Result<ReportQuery, String> {
+        if self.to <= self.from {
+            return Err("to must be after from".to_owned());
+        }
+        if self.to - self.from > Duration::days(MAX_REPORT_DAYS) {
+            return Err(format!("A report covers at most {} days", MAX_REPORT_DAYS));
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRow {
+    /// First day of the period, e.g. `2025-03-03` for a week or `2025-03-01` for March. Null without period.
+    pub period: Option<String>,
+    /// Name of the category or tag, null without group and for activities without category or tag
+    pub group: Option<String>,
+    pub activities: i64,
+    pub tracked_seconds: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReportTotal {
+    pub activities: i64,
+    pub tracked_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportSummary {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub period: Option<Period>,
+    pub group: Option<ReportGroup>,
+    /// Ordered by period and group
+    pub rows: Vec<ReportRow>,
+    /// Each activity or occurrence counts once, also when grouped by tag
+    pub total: ReportTotal,
+}
@@ -0,0 +1,11 @@
+use async_trait::async_trait;
+
+use crate::error::errors::ReportError;
+
+use super::report::{ReportQuery, ReportSummary};
+
+/// Aggregates the activities and tracked time of a user
+#[async_trait]
+pub trait ReportApi: Send + Sync {
+    async fn summary(&self, user_id: i32, query: ReportQuery) -> Result<ReportSummary, ReportError>;
+}
@@ -0,0 +1,12 @@
+/// A sensitive change of an account the user is told about by mail
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecurityNotification {
+    /// Successful login from a browser the user never logged in with before
+    NewDeviceLogin { user_agent: Option<String>, ip: Option<String> },
+    /// A second factor was set up or another factor was selected
+    MfaEnrolled { mfa_id: String },
+    MfaDisabled,
+    PasswordChanged,
+    /// Sent to the previous address, the new one could be controlled by an attacker
+    EmailChanged { new_email: String },
+}
@@ -0,0 +1,22 @@
+use serde::Serialize;
+
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Free-form label of activities. Tags are created with the first activity that uses them
+/// and removed with the last one, names are unique per user ignoring case.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub activity_count: u32,
+}
+
+/// Trims the name and checks its length
+pub fn normalize_tag(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!("Tags must have between 1 and {} characters", MAX_TAG_LENGTH));
+    }
+
+    Ok(name.to_owned())
+}
@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+
+use crate::error::errors::TagError;
+
+use super::tag::Tag;
+
+/// Tags are created through activities, see [super::tag::Tag]
+#[async_trait]
+pub trait TagApi: Send + Sync {
+    /// Sorted by name
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<Tag>, TagError>;
+    async fn rename(&self, user_id: i32, tag_id: i32, name: &str) -> Result<Tag, TagError>;
+    /// Removes the tag from all activities
+    async fn delete(&self, user_id: i32, tag_id: i32) -> Result<(), TagError>;
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::errors::FieldError;
+
+const MAX_NOTE_LENGTH: usize = 500;
+
+/// Tracked time of an activity. A user has at most one running entry, it has no end yet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeEntry {
+    pub id: i32,
+    pub activity_id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+impl TimeEntry {
+    /// Running entries count until now
+    pub fn duration_seconds(&self, now: DateTime<Utc>) -> i64 {
+        (self.end.unwrap_or(now) - self.start).num_seconds().max(0)
+    }
+}
+
+/// Body of the requests that create or edit entries manually
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewTimeEntry {
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+impl NewTimeEntry {
+    pub fn validate(self) -> Result<NewTimeEntry, Vec<FieldError>> {
+        let note = self.note
+            .map(|note| note.trim().to_owned())
+            .filter(|note| !note.is_empty());
+
+        let mut errors = Vec::new();
+        if self.end.is_some_and(|end| end <= self.start) {
+            errors.push(FieldError::new("end", "before_start", "End must be after the start"));
+        }
+        if self.start > Utc::now() {
+            errors.push(FieldError::new("start", "in_future", "Start must not be in the future"));
+        }
+        if note.as_ref().is_some_and(|note| note.chars().count() > MAX_NOTE_LENGTH) {
+            errors.push(FieldError::new("note", "too_long", &format!("Note must have at most {} characters", MAX_NOTE_LENGTH)));
+        }
+
+        if errors.is_empty() {
+            Ok(NewTimeEntry { note, ..self })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// All entries of an activity, newest first
+#[derive(Debug, Serialize)]
+pub struct TimeEntries {
+    pub entries: Vec<TimeEntryView>,
+    /// Includes a running entry until now
+    pub total_seconds: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeEntryView {
+    #[serde(flatten)]
+    pub entry: TimeEntry,
+    pub duration_seconds: i64,
+}
+
+impl TimeEntries {
+    pub fn new(entries: Vec<TimeEntry>, now: DateTime<Utc>) -> Self {
+        let entries = entries.into_iter()
+            .map(|entry| TimeEntryView {
+                duration_seconds: entry.duration_seconds(now),
+                entry,
+            })
+            .collect::<Vec<_>>();
+
+        TimeEntries {
+            total_seconds: entries.iter().map(|e| e.duration_seconds).sum(),
+            entries,
+        }
+    }
+}
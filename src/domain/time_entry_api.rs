@@ -0,0 +1,18 @@
+use async_trait::async_trait;
+
+use crate::error::errors::TimeEntryError;
+
+use super::time_entry::{NewTimeEntry, TimeEntries, TimeEntry};
+
+/// Time entries are accessed through the activity and its owner
+#[async_trait]
+pub trait TimeEntryApi: Send + Sync {
+    /// Starts a timer now. Fails with [TimeEntryError::TimerRunning] if the user has a running timer.
+    async fn start(&self, user_id: i32, activity_id: i32, note: Option<String>) -> Result<TimeEntry, TimeEntryError>;
+    /// Stops the running timer of the activity
+    async fn stop(&self, user_id: i32, activity_id: i32) -> Result<TimeEntry, TimeEntryError>;
+    async fn create(&self, user_id: i32, activity_id: i32, entry: NewTimeEntry) -> Result<TimeEntry, TimeEntryError>;
+    async fn update(&self, user_id: i32, activity_id: i32, entry_id: i32, entry: NewTimeEntry) -> Result<TimeEntry, TimeEntryError>;
+    async fn delete(&self, user_id: i32, activity_id: i32, entry_id: i32) -> Result<(), TimeEntryError>;
+    async fn find_by_activity(&self, user_id: i32, activity_id: i32) -> Result<TimeEntries, TimeEntryError>;
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A browser that skips the second factor until `expires_at`. Only the hash of its secret is stored.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustedDevice {
+    pub id: i32,
+    /// Browser the device was last seen with, unknown until the device was used once
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Returned once after creation, the secret is only sent to the browser as cookie
+#[derive(Debug)]
+pub struct CreatedTrustedDevice {
+    pub device: TrustedDevice,
+    pub secret: String,
+}
@@ -0,0 +1,16 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::error::errors::TrustedDeviceError;
+
+use super::trusted_device::{CreatedTrustedDevice, TrustedDevice};
+
+#[async_trait]
+pub trait TrustedDeviceApi: Send + Sync {
+    async fn create(&self, user_id: i32, expires_at: DateTime<Utc>) -> Result<CreatedTrustedDevice, TrustedDeviceError>;
+    /// Looks up the device of the user by its plain secret and records its usage
+    async fn authenticate(&self, user_id: i32, secret: &str, user_agent: Option<&str>) -> Result<TrustedDevice, TrustedDeviceError>;
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<TrustedDevice>, TrustedDeviceError>;
+    async fn revoke(&self, user_id: i32, device_id: i32) -> Result<(), TrustedDeviceError>;
+    async fn revoke_all(&self, user_id: i32) -> Result<(), TrustedDeviceError>;
+}
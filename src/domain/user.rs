@@ -1,26 +1,96 @@
+use std::str::FromStr;
+
 use authfix::session::AccountInfo;
 use serde::{Deserialize, Serialize};
 
+use super::page::{SortField, SortOrder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Admins have all rights of a user
+    pub fn includes(&self, other: Role) -> bool {
+        *self == Role::Admin || *self == other
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: i32,
     pub email: String,
     pub name: String,
+    pub role: Role,
+    pub locked: bool,
 }
 
-impl AccountInfo for User {}
+impl AccountInfo for User {
+    fn user_identification(&self) -> String {
+        self.email.clone()
+    }
+
+    fn is_account_locked(&self) -> bool {
+        self.locked
+    }
+}
 
 impl User {
     pub fn new(id: i32, email: String, name: String) -> Self {
         User {
             id,
-            email: email,
-            name: name,
+            email,
+            name,
+            role: Role::User,
+            locked: false,
         }
     }
+
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
     
 }
 
+/// A user as seen in the admin area
+#[derive(Debug, Serialize)]
+pub struct UserOverview {
+    #[serde(flatten)]
+    pub user: User,
+    pub mfa_enabled: bool,
+}
+
+/// How many stored password hashes use the configured argon2 parameters
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PasswordHashReport {
+    pub current: usize,
+    /// Weaker parameters or another algorithm, upgraded on the next login of the user
+    pub outdated: usize,
+}
+
 pub struct Credentials {
     pub id: i32,
     pub password: String,
@@ -62,4 +132,33 @@ impl Mfa {
             secret: Some(secret.to_owned()),
         }
     }    
-}
\ No newline at end of file
+}
+/// `id` by default, `name` and `email`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UserSort {
+    #[default]
+    Id,
+    Name,
+    Email,
+}
+
+impl FromStr for UserSort {
+    type Err = String;
+
+    fn from_str(field: &str) -> Result<Self, Self::Err> {
+        match field {
+            "id" => Ok(UserSort::Id),
+            "name" => Ok(UserSort::Name),
+            "email" => Ok(UserSort::Email),
+            _ => Err(format!("Users cannot be sorted by '{}', use id, name or email", field)),
+        }
+    }
+}
+
+impl SortField for UserSort {
+    fn default_order(&self) -> SortOrder {
+        SortOrder::Asc
+    }
+
+    const DATE_RANGE: bool = false;
+}
@@ -2,14 +2,27 @@ use async_trait::async_trait;
 
 use crate::{domain::user::User, error::errors::{QueryUserError, UserUpdateError}};
 
-use super::user::Credentials;
+use super::{page::{Page, PageRequest}, user::{Credentials, PasswordHashReport, UserOverview, UserSort}};
 
 #[async_trait]
 pub trait UserApi: Send + Sync {
+    /// Ignores case and surrounding whitespace, addresses of older accounts may not be lower case
     async fn find_by_email(&self, email: &str) -> Result<User, QueryUserError>;
     async fn find_by_id(&self, user_id: i32) -> Result<User, QueryUserError>;
     async fn save_user_with_credentials(&self, user: User, password: &str) -> Result<User, UserUpdateError>;
+    /// Takes in the plain text password, the caller has to check it against the password policy.
+    /// Revokes the API tokens and trusted devices of the user, they were granted with the old password.
+    async fn update_password(&self, user_id: i32, password: &str) -> Result<(), UserUpdateError>;
+    /// Re-hashes the verified plain text password if the stored hash uses outdated parameters.
+    /// Returns true if the hash was replaced.
+    async fn upgrade_password_hash(&self, user_id: i32, password: &str) -> Result<bool, UserUpdateError>;
+    async fn password_hash_report(&self) -> Result<PasswordHashReport, QueryUserError>;
+    async fn update_email(&self, user_id: i32, email: &str) -> Result<User, UserUpdateError>;
     async fn save_credentials(&self, credentials: Credentials) -> Result<Credentials, UserUpdateError>;
     async fn find_credentials_by_user_id(&self, user_id: i32) -> Result<Credentials, QueryUserError>;
+    async fn find_overview(&self, page: &PageRequest<UserSort>) -> Result<Page<UserOverview>, QueryUserError>;
+    async fn set_locked(&self, user_id: i32, locked: bool) -> Result<User, UserUpdateError>;
+    async fn find_by_external_identity(&self, issuer: &str, subject: &str) -> Result<User, QueryUserError>;
+    async fn link_external_identity(&self, user_id: i32, issuer: &str, subject: &str) -> Result<(), UserUpdateError>;
 }
 
@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `mfa_id` of the WebAuthn factor in the users credentials
+pub const MFA_ID_WEBAUTHN: &str = "WEBAUTHN_MFA";
+
+/// A registered security key or passkey. A user can register several of them.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebauthnCredential {
+    pub id: i32,
+    pub name: String,
+    /// base64url encoded credential id as chosen by the authenticator
+    pub credential_id: String,
+    /// Uncompressed SEC1 encoded P-256 public key
+    #[serde(skip)]
+    pub public_key: Vec<u8>,
+    #[serde(skip)]
+    pub sign_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The public key credential returned by `navigator.credentials.create()`, binary fields base64url encoded
+#[derive(Debug, Deserialize)]
+pub struct RegistrationResponse {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+/// The public key credential returned by `navigator.credentials.get()`, binary fields base64url encoded
+#[derive(Debug, Deserialize)]
+pub struct AssertionResponse {
+    pub id: String,
+    pub response: AuthenticatorAssertion,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorAssertion {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub id: String,
+}
+
+impl CredentialDescriptor {
+    pub fn public_key(id: &str) -> Self {
+        Self {
+            credential_type: "public-key".to_owned(),
+            id: id.to_owned(),
+        }
+    }
+}
+
+/// Options for `navigator.credentials.get()`, kept in the session until the assertion is checked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+    pub timeout: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicKeyParameters {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+/// Options for `navigator.credentials.create()`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<PublicKeyParameters>,
+    pub timeout: u32,
+    pub attestation: String,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+}
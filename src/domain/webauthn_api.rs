@@ -0,0 +1,19 @@
+use async_trait::async_trait;
+
+use crate::{domain::user::User, error::errors::WebauthnError};
+
+use super::webauthn::{AssertionResponse, CreationOptions, RegistrationResponse, RequestOptions, WebauthnCredential};
+
+#[async_trait]
+pub trait WebauthnApi: Send + Sync {
+    /// Creates the options for registering a new key. Keys the user already has are excluded.
+    async fn start_registration(&self, user: &User) -> Result<CreationOptions, WebauthnError>;
+    /// Verifies the attestation against the challenge of the registration ceremony and stores the new key
+    async fn finish_registration(&self, user_id: i32, name: &str, challenge: &str, response: RegistrationResponse) -> Result<WebauthnCredential, WebauthnError>;
+    /// Creates the options for an assertion with all keys of the user
+    async fn start_authentication(&self, user_id: i32) -> Result<RequestOptions, WebauthnError>;
+    /// Verifies the signed assertion against the challenge and records the usage of the key
+    async fn finish_authentication(&self, user_id: i32, challenge: &str, response: AssertionResponse) -> Result<WebauthnCredential, WebauthnError>;
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<WebauthnCredential>, WebauthnError>;
+    async fn delete(&self, user_id: i32, id: i32) -> Result<(), WebauthnError>;
+}
@@ -1,54 +1,896 @@
+use actix_session::{SessionGetError, SessionInsertError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
 use thiserror::Error;
 use tokio::task::JoinError;
 
+const PROBLEM_JSON: &str = "application/problem+json";
+
 #[derive(Error, Debug)]
-#[error("Cannot query user: {msg}")]
-pub struct QueryUserError {
-    msg: String,
+pub enum QueryUserError {
+    #[error("Cannot query user: not found")]
+    NotFound,
+    #[error("Cannot query user: {0}")]
+    Database(String),
+    #[error("Cannot query user: {0}")]
+    TaskJoin(String),
 }
 
 #[derive(Error, Debug)]
-#[error("Cannot save user: {msg}")]
-pub struct UserUpdateError {
-    msg: String,
+pub enum UserUpdateError {
+    #[error("Cannot save user: not found")]
+    NotFound,
+    #[error("Cannot save user: {0}")]
+    Conflict(String),
+    #[error("Cannot save user: {0}")]
+    Invalid(String),
+    #[error("Cannot save user: {0}")]
+    Hashing(String),
+    #[error("Cannot save user: {0}")]
+    Database(String),
+    #[error("Cannot save user: {0}")]
+    TaskJoin(String),
 }
 
-impl UserUpdateError {
-    pub fn new(msg: &str) -> Self {
-        Self { msg: msg.to_owned() }
+/// Returns true if the statement failed because of a UNIQUE or PRIMARY KEY constraint
+pub fn is_unique_violation(e: &rusqlite::Error) -> bool {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _) => {
+            err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+                || err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+        },
+        _ => false,
     }
 }
 
-
 impl From<rusqlite::Error> for UserUpdateError {
     fn from(e: rusqlite::Error) -> Self {
-        Self {
-            msg:  e.to_string()
+        if is_unique_violation(&e) {
+            if e.to_string().contains("users.email") {
+                UserUpdateError::Conflict("Email address is already in use".to_owned())
+            } else {
+                UserUpdateError::Conflict(e.to_string())
+            }
+        } else {
+            UserUpdateError::Database(e.to_string())
         }
     }
 }
 
 impl From<JoinError> for UserUpdateError {
     fn from(e: JoinError) -> Self {
-        Self {
-            msg:  e.to_string()
-        }
+        UserUpdateError::TaskJoin(e.to_string())
     }
 }
 
 impl From<rusqlite::Error> for QueryUserError {
     fn from(e: rusqlite::Error) -> Self {
-        Self {
-            msg:  e.to_string()
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => QueryUserError::NotFound,
+            _ => QueryUserError::Database(e.to_string()),
         }
     }
 }
 
 impl From<JoinError> for QueryUserError {
     fn from(e: JoinError) -> Self {
+        QueryUserError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ApiTokenError {
+    #[error("API token not found")]
+    NotFound,
+    #[error("API token expired")]
+    Expired,
+    #[error("Cannot access API tokens: {0}")]
+    Database(String),
+    #[error("Cannot access API tokens: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for ApiTokenError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => ApiTokenError::NotFound,
+            _ => ApiTokenError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for ApiTokenError {
+    fn from(e: JoinError) -> Self {
+        ApiTokenError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WebauthnError {
+    #[error("Security key not found")]
+    NotFound,
+    #[error("Security key is already registered")]
+    AlreadyRegistered,
+    #[error("Invalid WebAuthn response: {0}")]
+    InvalidResponse(String),
+    #[error("Cannot access security keys: {0}")]
+    Database(String),
+    #[error("Cannot access security keys: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for WebauthnError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => WebauthnError::NotFound,
+            e if is_unique_violation(&e) => WebauthnError::AlreadyRegistered,
+            _ => WebauthnError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for WebauthnError {
+    fn from(e: JoinError) -> Self {
+        WebauthnError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TrustedDeviceError {
+    #[error("Trusted device not found")]
+    NotFound,
+    #[error("Trusted device expired")]
+    Expired,
+    #[error("Cannot access trusted devices: {0}")]
+    Database(String),
+    #[error("Cannot access trusted devices: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for TrustedDeviceError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => TrustedDeviceError::NotFound,
+            _ => TrustedDeviceError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for TrustedDeviceError {
+    fn from(e: JoinError) -> Self {
+        TrustedDeviceError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MfaPolicyError {
+    #[error("Invalid MFA policy: {0}")]
+    Invalid(String),
+    #[error("Cannot access MFA policy: {0}")]
+    Database(String),
+    #[error("Cannot access MFA policy: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for MfaPolicyError {
+    fn from(e: rusqlite::Error) -> Self {
+        MfaPolicyError::Database(e.to_string())
+    }
+}
+
+impl From<JoinError> for MfaPolicyError {
+    fn from(e: JoinError) -> Self {
+        MfaPolicyError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Cannot access audit log: {0}")]
+    Database(String),
+    #[error("Cannot access audit log: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for AuditError {
+    fn from(e: rusqlite::Error) -> Self {
+        AuditError::Database(e.to_string())
+    }
+}
+
+impl From<JoinError> for AuditError {
+    fn from(e: JoinError) -> Self {
+        AuditError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PasswordResetError {
+    #[error("Password reset token is invalid or expired")]
+    InvalidToken,
+    #[error("Cannot access password reset tokens: {0}")]
+    Database(String),
+    #[error("Cannot access password reset tokens: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for PasswordResetError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => PasswordResetError::InvalidToken,
+            _ => PasswordResetError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for PasswordResetError {
+    fn from(e: JoinError) -> Self {
+        PasswordResetError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EmailChangeError {
+    #[error("Email confirmation token is invalid or expired")]
+    InvalidToken,
+    #[error("Cannot access email changes: {0}")]
+    Database(String),
+    #[error("Cannot access email changes: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for EmailChangeError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => EmailChangeError::InvalidToken,
+            _ => EmailChangeError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for EmailChangeError {
+    fn from(e: JoinError) -> Self {
+        EmailChangeError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("Cannot send mail: {0}")]
+    Send(String),
+}
+
+#[derive(Error, Debug)]
+pub enum EmailCodeError {
+    #[error("No code was requested")]
+    NotFound,
+    #[error("Code is expired")]
+    Expired,
+    #[error("Too many wrong attempts")]
+    TooManyAttempts,
+    #[error("Code is wrong")]
+    Invalid,
+    #[error("Cannot send code: {0}")]
+    Mail(#[from] MailError),
+    #[error("Cannot access email codes: {0}")]
+    Database(String),
+    #[error("Cannot access email codes: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for EmailCodeError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => EmailCodeError::NotFound,
+            _ => EmailCodeError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for EmailCodeError {
+    fn from(e: JoinError) -> Self {
+        EmailCodeError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("OIDC provider request failed: {0}")]
+    Provider(String),
+    #[error("OIDC login state is missing or does not match")]
+    InvalidState,
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(String),
+    #[error("No account exists for this identity")]
+    NoAccount,
+    #[error("Account is locked")]
+    AccountLocked,
+    #[error("Cannot link identity: {0}")]
+    Database(String),
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError::Provider(e.to_string())
+    }
+}
+
+impl From<QueryUserError> for OidcError {
+    fn from(e: QueryUserError) -> Self {
+        OidcError::Database(e.to_string())
+    }
+}
+
+impl From<UserUpdateError> for OidcError {
+    fn from(e: UserUpdateError) -> Self {
+        OidcError::Database(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ActivityError {
+    #[error("Activity not found")]
+    NotFound,
+    #[error("The activity has no such occurrence")]
+    OccurrenceNotFound,
+    #[error("Invalid activity: {0:?}")]
+    Invalid(Vec<FieldError>),
+    #[error("Cannot access activities: {0}")]
+    Database(String),
+    #[error("Cannot access activities: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for ActivityError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => ActivityError::NotFound,
+            _ => ActivityError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for ActivityError {
+    fn from(e: JoinError) -> Self {
+        ActivityError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CategoryError {
+    #[error("Category not found")]
+    NotFound,
+    #[error("A category with this name already exists")]
+    AlreadyExists,
+    #[error("Invalid category: {0:?}")]
+    Invalid(Vec<FieldError>),
+    #[error("Cannot access categories: {0}")]
+    Database(String),
+    #[error("Cannot access categories: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for CategoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => CategoryError::NotFound,
+            e if is_unique_violation(&e) => CategoryError::AlreadyExists,
+            _ => CategoryError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for CategoryError {
+    fn from(e: JoinError) -> Self {
+        CategoryError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TagError {
+    #[error("Tag not found")]
+    NotFound,
+    #[error("A tag with this name already exists")]
+    AlreadyExists,
+    #[error("Invalid tag: {0}")]
+    Invalid(String),
+    #[error("Cannot access tags: {0}")]
+    Database(String),
+    #[error("Cannot access tags: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for TagError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => TagError::NotFound,
+            e if is_unique_violation(&e) => TagError::AlreadyExists,
+            _ => TagError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for TagError {
+    fn from(e: JoinError) -> Self {
+        TagError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TimeEntryError {
+    #[error("Time entry not found")]
+    NotFound,
+    #[error("Activity not found")]
+    ActivityNotFound,
+    #[error("Another timer is already running")]
+    TimerRunning,
+    #[error("Invalid time entry: {0:?}")]
+    Invalid(Vec<FieldError>),
+    #[error("Cannot access time entries: {0}")]
+    Database(String),
+    #[error("Cannot access time entries: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for TimeEntryError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => TimeEntryError::NotFound,
+            // only the index on running entries is unique
+            e if is_unique_violation(&e) => TimeEntryError::TimerRunning,
+            _ => TimeEntryError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for TimeEntryError {
+    fn from(e: JoinError) -> Self {
+        TimeEntryError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CalendarError {
+    #[error("Calendar feed not found")]
+    FeedNotFound,
+    #[error("Invalid calendar: {0}")]
+    Invalid(String),
+    #[error("Cannot read activities: {0}")]
+    Activity(#[from] ActivityError),
+    #[error("Cannot access calendar feeds: {0}")]
+    Database(String),
+    #[error("Cannot access calendar feeds: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for CalendarError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => CalendarError::FeedNotFound,
+            _ => CalendarError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for CalendarError {
+    fn from(e: JoinError) -> Self {
+        CalendarError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExchangeError {
+    #[error("Invalid file: {0}")]
+    Invalid(String),
+    #[error("Cannot access activities: {0}")]
+    Activity(#[from] ActivityError),
+    #[error("Cannot export activities: {0}")]
+    Database(String),
+    #[error("Cannot export activities: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for ExchangeError {
+    fn from(e: rusqlite::Error) -> Self {
+        ExchangeError::Database(e.to_string())
+    }
+}
+
+impl From<JoinError> for ExchangeError {
+    fn from(e: JoinError) -> Self {
+        ExchangeError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ReportError {
+    #[error("Invalid report: {0}")]
+    Invalid(String),
+    #[error("Cannot read activities: {0}")]
+    Activity(#[from] ActivityError),
+    #[error("Cannot create report: {0}")]
+    Database(String),
+    #[error("Cannot create report: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for ReportError {
+    fn from(e: rusqlite::Error) -> Self {
+        ReportError::Database(e.to_string())
+    }
+}
+
+impl From<JoinError> for ReportError {
+    fn from(e: JoinError) -> Self {
+        ReportError::TaskJoin(e.to_string())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GoalError {
+    #[error("Goal not found")]
+    NotFound,
+    #[error("Invalid goal: {0:?}")]
+    Invalid(Vec<FieldError>),
+    #[error("Cannot read activities: {0}")]
+    Activity(#[from] ActivityError),
+    #[error("Cannot access goals: {0}")]
+    Database(String),
+    #[error("Cannot access goals: {0}")]
+    TaskJoin(String),
+}
+
+impl From<rusqlite::Error> for GoalError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => GoalError::NotFound,
+            _ => GoalError::Database(e.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for GoalError {
+    fn from(e: JoinError) -> Self {
+        GoalError::TaskJoin(e.to_string())
+    }
+}
+
+/// A single failed check of a request field, rendered in the `errors` member of the problem details
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    /// Stable identifier for clients, e.g. `too_short`
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &str, message: &str) -> Self {
         Self {
-            msg:  e.to_string()
+            field: field.to_owned(),
+            code: code.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Error type for the JSON API.
+///
+/// Every variant is rendered as a RFC 7807 `application/problem+json` body.
+/// Messages of `Internal` errors are only logged and never sent to the client.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Validation failed: {0}")]
+    Validation(String),
+    #[error("Validation failed: {0:?}")]
+    InvalidFields(Vec<FieldError>),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    /// The MFA policy requires a second factor that the user has not set up yet
+    #[error("MFA enrollment required")]
+    MfaEnrollmentRequired,
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ProblemDetails {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<Vec<FieldError>>,
+}
+
+impl ApiError {
+    fn problem_type(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not-found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => "validation-error",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::MfaEnrollmentRequired => "mfa-enrollment-required",
+            ApiError::Internal(_) => "internal-error",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::Validation(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg) => msg.clone(),
+            ApiError::InvalidFields(errors) => errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join(" "),
+            ApiError::MfaEnrollmentRequired => "A second factor must be set up before the API can be used".to_owned(),
+            ApiError::Internal(_) => "An unexpected error occurred".to_owned(),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) | ApiError::MfaEnrollmentRequired => StatusCode::FORBIDDEN,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(msg) = self {
+            log::error!("Internal error: {}", msg);
+        }
+
+        let status = self.status_code();
+        HttpResponse::build(status)
+            .content_type(PROBLEM_JSON)
+            .json(ProblemDetails {
+                problem_type: self.problem_type(),
+                title: status.canonical_reason().unwrap_or("Error"),
+                status: status.as_u16(),
+                detail: self.detail(),
+                errors: match self {
+                    ApiError::InvalidFields(errors) => Some(errors.clone()),
+                    _ => None,
+                },
+            })
+    }
+}
+
+impl From<QueryUserError> for ApiError {
+    fn from(e: QueryUserError) -> Self {
+        match e {
+            QueryUserError::NotFound => ApiError::NotFound("User not found".to_owned()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<UserUpdateError> for ApiError {
+    fn from(e: UserUpdateError) -> Self {
+        match e {
+            UserUpdateError::NotFound => ApiError::NotFound("User not found".to_owned()),
+            UserUpdateError::Conflict(msg) => ApiError::Conflict(msg),
+            UserUpdateError::Invalid(msg) => ApiError::Validation(msg),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ApiTokenError> for ApiError {
+    fn from(e: ApiTokenError) -> Self {
+        match e {
+            ApiTokenError::NotFound => ApiError::NotFound("API token not found".to_owned()),
+            ApiTokenError::Expired => ApiError::Unauthorized("API token expired".to_owned()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<WebauthnError> for ApiError {
+    fn from(e: WebauthnError) -> Self {
+        match e {
+            WebauthnError::NotFound => ApiError::NotFound(e.to_string()),
+            WebauthnError::AlreadyRegistered => ApiError::Conflict(e.to_string()),
+            WebauthnError::InvalidResponse(_) => ApiError::Validation(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<TrustedDeviceError> for ApiError {
+    fn from(e: TrustedDeviceError) -> Self {
+        match e {
+            TrustedDeviceError::NotFound => ApiError::NotFound(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<MfaPolicyError> for ApiError {
+    fn from(e: MfaPolicyError) -> Self {
+        match e {
+            MfaPolicyError::Invalid(msg) => ApiError::Validation(msg),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<AuditError> for ApiError {
+    fn from(e: AuditError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<PasswordResetError> for ApiError {
+    fn from(e: PasswordResetError) -> Self {
+        match e {
+            PasswordResetError::InvalidToken => ApiError::Validation(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<EmailChangeError> for ApiError {
+    fn from(e: EmailChangeError) -> Self {
+        match e {
+            EmailChangeError::InvalidToken => ApiError::Validation(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ActivityError> for ApiError {
+    fn from(e: ActivityError) -> Self {
+        match e {
+            ActivityError::NotFound | ActivityError::OccurrenceNotFound => ApiError::NotFound(e.to_string()),
+            ActivityError::Invalid(errors) => ApiError::InvalidFields(errors),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<CategoryError> for ApiError {
+    fn from(e: CategoryError) -> Self {
+        match e {
+            CategoryError::NotFound => ApiError::NotFound(e.to_string()),
+            CategoryError::AlreadyExists => ApiError::Conflict(e.to_string()),
+            CategoryError::Invalid(errors) => ApiError::InvalidFields(errors),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<TagError> for ApiError {
+    fn from(e: TagError) -> Self {
+        match e {
+            TagError::NotFound => ApiError::NotFound(e.to_string()),
+            TagError::AlreadyExists => ApiError::Conflict(e.to_string()),
+            TagError::Invalid(_) => ApiError::Validation(e.to_string()),
+            _ => ApiError::Internal(e.to_string()),
         }
     }
 }
 
+impl From<TimeEntryError> for ApiError {
+    fn from(e: TimeEntryError) -> Self {
+        match e {
+            TimeEntryError::NotFound | TimeEntryError::ActivityNotFound => ApiError::NotFound(e.to_string()),
+            TimeEntryError::TimerRunning => ApiError::Conflict(e.to_string()),
+            TimeEntryError::Invalid(errors) => ApiError::InvalidFields(errors),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<CalendarError> for ApiError {
+    fn from(e: CalendarError) -> Self {
+        match e {
+            CalendarError::FeedNotFound => ApiError::NotFound(e.to_string()),
+            CalendarError::Invalid(_) => ApiError::Validation(e.to_string()),
+            CalendarError::Activity(e) => e.into(),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ExchangeError> for ApiError {
+    fn from(e: ExchangeError) -> Self {
+        match e {
+            ExchangeError::Invalid(_) => ApiError::Validation(e.to_string()),
+            ExchangeError::Activity(e) => e.into(),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ReportError> for ApiError {
+    fn from(e: ReportError) -> Self {
+        match e {
+            ReportError::Invalid(_) => ApiError::Validation(e.to_string()),
+            ReportError::Activity(e) => e.into(),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<GoalError> for ApiError {
+    fn from(e: GoalError) -> Self {
+        match e {
+            GoalError::NotFound => ApiError::NotFound(e.to_string()),
+            GoalError::Invalid(errors) => ApiError::InvalidFields(errors),
+            GoalError::Activity(e) => e.into(),
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<MailError> for ApiError {
+    fn from(e: MailError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<OidcError> for ApiError {
+    fn from(e: OidcError) -> Self {
+        match e {
+            OidcError::InvalidState | OidcError::InvalidIdToken(_) => ApiError::Unauthorized(e.to_string()),
+            OidcError::NoAccount | OidcError::AccountLocked => ApiError::Forbidden(e.to_string()),
+            OidcError::Provider(_) | OidcError::Database(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<SessionGetError> for ApiError {
+    fn from(e: SessionGetError) -> Self {
+        ApiError::Internal(format!("Cannot read from session: {}", e))
+    }
+}
+
+impl From<SessionInsertError> for ApiError {
+    fn from(e: SessionInsertError) -> Self {
+        ApiError::Internal(format!("Cannot write to session: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, http::{header::CONTENT_TYPE, StatusCode}, ResponseError};
+
+    use super::ApiError;
+
+    #[test]
+    fn should_map_variants_to_status_codes() {
+        assert_eq!(ApiError::NotFound("x".to_owned()).status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(ApiError::Conflict("x".to_owned()).status_code(), StatusCode::CONFLICT);
+        assert_eq!(ApiError::Validation("x".to_owned()).status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(ApiError::Unauthorized("x".to_owned()).status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ApiError::Forbidden("x".to_owned()).status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(ApiError::MfaEnrollmentRequired.status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(ApiError::Internal("x".to_owned()).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_web::test]
+    async fn should_render_problem_json_without_leaking_internal_details() {
+        let res = ApiError::Internal("database is locked".to_owned()).error_response();
+
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+        let body = to_bytes(res.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(r#""status":500"#));
+        assert!(!body.contains("database is locked"));
+    }
+}
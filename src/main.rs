@@ -3,7 +3,8 @@ use std::sync::Arc;
 use actix_session::{config::{PersistentSession, SessionLifecycle}, storage::CookieSessionStore, SessionMiddleware};
 use actix_web::{cookie::Key, middleware::Logger, HttpServer};
 use config::{config::Config, db::DbConfig};
-use domain::{user::User, user_api::UserApi};
+use domain::{user::{Role, User}, user_api::UserApi};
+use error::errors::QueryUserError;
 use rusqlite::Connection;
 use service::user_service::UserService;
 
@@ -13,6 +14,7 @@ mod service;
 mod domain;
 mod error;
 mod app_factory;
+mod middleware;
 
 pub fn create_session_middleware (key: Key) -> SessionMiddleware<CookieSessionStore> {
     let persistent_session = PersistentSession::default();
@@ -26,10 +28,41 @@ pub fn create_session_middleware (key: Key) -> SessionMiddleware<CookieSessionSt
                 .build()    
 }
 
+/// Databases created by older versions lack columns that were added later
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+
+    Ok(())
+}
+
+/// Adds the activities matching the condition to the search index
+fn index_activities(condition: &str) -> String {
+    format!(
+        "INSERT INTO activity_search (rowid, title, description, tags) SELECT a.id, a.title, COALESCE(a.description, ''), \
+            COALESCE((SELECT group_concat(t.name, ' ' ORDER BY t.name) FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id), '') \
+            FROM activities a WHERE {}",
+        condition
+    )
+}
+
 // ToDo: This should placed inside an separate init / config module
 pub fn create_db(db_config: &DbConfig) -> Connection {
     let conn = Connection::open(db_config.get_database()).unwrap();
     conn.execute("CREATE TABLE IF NOT EXISTS users (id INTEGER PRIMARY KEY, name TEXT, email TEXT UNIQUE);", []).unwrap();
+    add_column_if_missing(&conn, "users", "role", "TEXT NOT NULL DEFAULT 'user'").unwrap();
+    add_column_if_missing(&conn, "users", "locked", "INTEGER NOT NULL DEFAULT 0").unwrap();
+    // addresses are looked up ignoring case, so they have to be unique ignoring case as well
+    if let Err(e) = conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS users_email_nocase ON users (email COLLATE NOCASE)", []) {
+        log::error!("Email addresses differing only in case must be merged before they are unique: {}", e);
+    }
 
     let credential_table = r#"
         CREATE TABLE IF NOT EXISTS credentials (
@@ -44,6 +77,274 @@ pub fn create_db(db_config: &DbConfig) -> Connection {
 
     conn.execute(credential_table, []).unwrap();
 
+    let api_token_table = r#"
+        CREATE TABLE IF NOT EXISTS api_tokens (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            last_used_at TEXT,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(api_token_table, []).unwrap();
+
+    let external_identity_table = r#"
+        CREATE TABLE IF NOT EXISTS external_identities (
+            id INTEGER PRIMARY KEY,
+            issuer TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id INTEGER NOT NULL,
+            UNIQUE (issuer, subject),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(external_identity_table, []).unwrap();
+
+    let webauthn_credential_table = r#"
+        CREATE TABLE IF NOT EXISTS webauthn_credentials (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            credential_id TEXT NOT NULL UNIQUE,
+            public_key BLOB NOT NULL,
+            sign_count INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            last_used_at TEXT,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(webauthn_credential_table, []).unwrap();
+
+    let email_code_table = r#"
+        CREATE TABLE IF NOT EXISTS email_codes (
+            user_id INTEGER PRIMARY KEY,
+            code_hash TEXT NOT NULL,
+            salt TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(email_code_table, []).unwrap();
+
+    let trusted_device_table = r#"
+        CREATE TABLE IF NOT EXISTS trusted_devices (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            user_agent TEXT,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            last_used_at TEXT,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(trusted_device_table, []).unwrap();
+
+    let password_reset_table = r#"
+        CREATE TABLE IF NOT EXISTS password_resets (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            expires_at TEXT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(password_reset_table, []).unwrap();
+
+    let email_change_table = r#"
+        CREATE TABLE IF NOT EXISTS email_changes (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            email TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            expires_at TEXT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(email_change_table, []).unwrap();
+
+    // append-only: the triggers reject changes to recorded events
+    let audit_table = r#"
+        CREATE TABLE IF NOT EXISTS audit_events (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER,
+            event_type TEXT NOT NULL,
+            outcome TEXT NOT NULL,
+            ip TEXT,
+            user_agent TEXT,
+            detail TEXT,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS audit_events_user_id ON audit_events (user_id, id);
+        CREATE TRIGGER IF NOT EXISTS audit_events_no_update BEFORE UPDATE ON audit_events
+        BEGIN
+            SELECT RAISE(ABORT, 'audit_events is append-only');
+        END;
+        CREATE TRIGGER IF NOT EXISTS audit_events_no_delete BEFORE DELETE ON audit_events
+        BEGIN
+            SELECT RAISE(ABORT, 'audit_events is append-only');
+        END;
+    "#;
+
+    conn.execute_batch(audit_table).unwrap();
+
+    conn.execute("CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL);", []).unwrap();
+
+    let category_table = r#"
+        CREATE TABLE IF NOT EXISTS categories (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL COLLATE NOCASE,
+            color TEXT NOT NULL,
+            icon TEXT,
+            UNIQUE (user_id, name),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute(category_table, []).unwrap();
+
+    let activity_table = r#"
+        CREATE TABLE IF NOT EXISTS activities (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            description TEXT,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            category_id INTEGER,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (category_id) REFERENCES categories(id)
+        );
+        CREATE INDEX IF NOT EXISTS activities_user_id ON activities (user_id, start_time);
+    "#;
+
+    conn.execute_batch(activity_table).unwrap();
+    add_column_if_missing(&conn, "activities", "recurrence", "TEXT").unwrap();
+    // IANA zone of the activity, missing for activities in UTC
+    add_column_if_missing(&conn, "activities", "time_zone", "TEXT").unwrap();
+    // UID of imported calendar events, imports of the same event update the activity
+    add_column_if_missing(&conn, "activities", "import_uid", "TEXT").unwrap();
+    conn.execute("CREATE UNIQUE INDEX IF NOT EXISTS activities_import_uid ON activities (user_id, import_uid) WHERE import_uid IS NOT NULL", []).unwrap();
+
+    let activity_exception_table = r#"
+        CREATE TABLE IF NOT EXISTS activity_exceptions (
+            id INTEGER PRIMARY KEY,
+            activity_id INTEGER NOT NULL,
+            occurrence TEXT NOT NULL,
+            skipped INTEGER NOT NULL DEFAULT 0,
+            title TEXT,
+            description TEXT,
+            start_time TEXT,
+            end_time TEXT,
+            UNIQUE (activity_id, occurrence),
+            FOREIGN KEY (activity_id) REFERENCES activities(id)
+        );
+    "#;
+
+    conn.execute_batch(activity_exception_table).unwrap();
+
+    let tag_table = r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL COLLATE NOCASE,
+            UNIQUE (user_id, name),
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+        CREATE TABLE IF NOT EXISTS activity_tags (
+            activity_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (activity_id, tag_id),
+            FOREIGN KEY (activity_id) REFERENCES activities(id),
+            FOREIGN KEY (tag_id) REFERENCES tags(id)
+        );
+        CREATE INDEX IF NOT EXISTS activity_tags_tag_id ON activity_tags (tag_id);
+    "#;
+
+    conn.execute_batch(tag_table).unwrap();
+
+    // the partial index allows one running timer per user
+    let time_entry_table = r#"
+        CREATE TABLE IF NOT EXISTS time_entries (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            activity_id INTEGER NOT NULL,
+            start_time TEXT NOT NULL,
+            end_time TEXT,
+            note TEXT,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (activity_id) REFERENCES activities(id)
+        );
+        CREATE INDEX IF NOT EXISTS time_entries_activity_id ON time_entries (activity_id, start_time);
+        CREATE UNIQUE INDEX IF NOT EXISTS time_entries_running ON time_entries (user_id) WHERE end_time IS NULL;
+    "#;
+
+    conn.execute_batch(time_entry_table).unwrap();
+
+    let calendar_feed_table = r#"
+        CREATE TABLE IF NOT EXISTS calendar_feeds (
+            user_id INTEGER PRIMARY KEY,
+            secret_hash TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id)
+        );
+    "#;
+
+    conn.execute_batch(calendar_feed_table).unwrap();
+
+    let goal_table = r#"
+        CREATE TABLE IF NOT EXISTS goals (
+            id INTEGER PRIMARY KEY,
+            user_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            category_id INTEGER,
+            tag TEXT COLLATE NOCASE,
+            period TEXT NOT NULL,
+            metric TEXT NOT NULL,
+            target INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users(id),
+            FOREIGN KEY (category_id) REFERENCES categories(id)
+        );
+        CREATE INDEX IF NOT EXISTS goals_user_id ON goals (user_id);
+    "#;
+
+    conn.execute_batch(goal_table).unwrap();
+
+    // the rowid is the activity id, triggers keep the index in sync with activities, tags and tag names
+    conn.execute("CREATE VIRTUAL TABLE IF NOT EXISTS activity_search USING fts5 (title, description, tags, tokenize = 'unicode61 remove_diacritics 2')", []).unwrap();
+    let search_triggers = [
+        ("activity_search_insert", "AFTER INSERT ON activities", "NEW.id"),
+        ("activity_search_update", "AFTER UPDATE OF title, description ON activities", "NEW.id"),
+        ("activity_search_delete", "AFTER DELETE ON activities", "OLD.id"),
+        ("activity_search_tag_added", "AFTER INSERT ON activity_tags", "NEW.activity_id"),
+        ("activity_search_tag_removed", "AFTER DELETE ON activity_tags", "OLD.activity_id"),
+        ("activity_search_tag_renamed", "AFTER UPDATE OF name ON tags", "SELECT activity_id FROM activity_tags WHERE tag_id = NEW.id"),
+    ];
+    for (name, event, activity_ids) in search_triggers {
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS {} {} BEGIN DELETE FROM activity_search WHERE rowid IN ({}); {}; END;",
+            name, event, activity_ids, index_activities(&format!("a.id IN ({})", activity_ids))
+        )).unwrap();
+    }
+    // activities created before the index existed
+    conn.execute(&index_activities("a.id NOT IN (SELECT rowid FROM activity_search)"), []).unwrap();
+
     conn
 }
 
@@ -55,33 +356,33 @@ pub async fn create_test_user(db_config: DbConfig) {
         Ok(_) => {
             println!("Test user `Hans` already created");
         },
-        Err(_) => {
-            // assuming it was a not found error
+        Err(QueryUserError::NotFound) => {
             let user = User::new(0, "test@example.org".to_owned(), "Hans".to_owned());
 
             let user = user_service.save_user_with_credentials(user, "test123").await.expect("Cannot save test user");
             println!("Test user with id = {} created.", user.id);
         },
+        Err(e) => panic!("Cannot look up test user: {}", e),
     }    
 
     match user_service.find_by_email("linda@example.org").await {
         Ok(_) => {
             println!("Test user `Linda` already created");
         },
-        Err(_) => {
-            // assuming it was a not found error
-            let user = User::new(0, "linda@example.org".to_owned(), "Linda".to_owned());
+        Err(QueryUserError::NotFound) => {
+            let user = User::new(0, "linda@example.org".to_owned(), "Linda".to_owned()).with_role(Role::Admin);
 
             let user = user_service.save_user_with_credentials(user, "linda123").await.expect("Cannot save test user");
             println!("Test user with id = {} created.", user.id);
         },
+        Err(e) => panic!("Cannot look up test user: {}", e),
     }    
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
-    let config = Config::from_env();
+    let config = Arc::new(Config::from_env());
 
     let db_config = DbConfig::new("activities_db.sqlite3");
     create_db(&db_config);
@@ -90,8 +391,9 @@ async fn main() -> std::io::Result<()> {
     let encrypt_key_for_cookies = Key::generate();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
 
+    let app_config = Arc::clone(&config);
     let server = HttpServer::new(move || {
-        app_factory::create_app(encrypt_key_for_cookies.clone(), DbConfig::new("activities_db.sqlite3"))
+        app_factory::create_app(encrypt_key_for_cookies.clone(), DbConfig::new("activities_db.sqlite3"), Arc::clone(&app_config))
         .wrap(Logger::default())
     })
     .bind((config.host.clone(), config.port))?
@@ -0,0 +1,7 @@
+pub mod bearer_auth;
+pub mod mfa_enrollment;
+pub mod logout_audit;
+pub mod csrf;
+pub mod security_headers;
+pub mod login_failure;
+pub mod locked_account;
\ No newline at end of file
@@ -0,0 +1,259 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+
+use actix_session::SessionExt;
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::{header::{HeaderMap, AUTHORIZATION, COOKIE, SET_COOKIE}, Method}, Error, HttpMessage};
+use futures::future::LocalBoxFuture;
+
+use crate::{domain::{api_token::{ApiToken, TokenScope}, api_token_api::ApiTokenApi, mfa_policy_api::MfaPolicyApi, user_api::UserApi}, error::errors::{ApiError, ApiTokenError}, service::auth_service::AUTHFIX_SESSION_KEY_USER};
+
+/// Inserted into the request extensions if the request was authenticated by a personal API token
+#[derive(Clone)]
+pub struct BearerAuthenticated {
+    pub token: ApiToken,
+}
+
+/// Route prefixes usable with API tokens. Account, security and admin routes need a session,
+/// so a leaked token can neither take over the account nor act with an admin's role.
+const TOKEN_PATHS: [&str; 6] = [
+    "/api/current-user",
+    "/api/activities",
+    "/api/categories",
+    "/api/tags",
+    "/api/reports",
+    "/api/goals",
+];
+
+/// Accepts `Authorization: Bearer <token>` as an alternative to the session cookie on the [TOKEN_PATHS].
+///
+/// authfix only knows session authentication, so the user of a valid token is put into a fresh,
+/// request-scoped session before the session middleware runs. Cookies are ignored on the way in
+/// and dropped on the way out, so no session is ever persisted for token requests.
+/// Users that have to set up a second factor first are rejected, like sessions in the enrollment-only state.
+/// This middleware must therefore wrap the app built by `SessionLoginAppBuilder`.
+pub struct BearerTokenAuth {
+    token_api: Arc<dyn ApiTokenApi>,
+    user_api: Arc<dyn UserApi>,
+    mfa_policy_api: Arc<dyn MfaPolicyApi>,
+}
+
+impl BearerTokenAuth {
+    pub fn new(token_api: Arc<dyn ApiTokenApi>, user_api: Arc<dyn UserApi>, mfa_policy_api: Arc<dyn MfaPolicyApi>) -> Self {
+        Self {
+            token_api,
+            user_api,
+            mfa_policy_api,
+        }
+    }
+}
+
+fn bearer_secret(headers: &HeaderMap) -> Option<String> {
+    headers.get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|secret| secret.trim().to_owned())
+}
+
+fn is_token_path(path: &str) -> bool {
+    TOKEN_PATHS.iter().any(|prefix| path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+}
+
+fn required_scope(method: &Method) -> TokenScope {
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        TokenScope::Read
+    } else {
+        TokenScope::Write
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BearerTokenAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = BearerTokenAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BearerTokenAuthMiddleware {
+            service: Rc::new(service),
+            token_api: Arc::clone(&self.token_api),
+            user_api: Arc::clone(&self.user_api),
+            mfa_policy_api: Arc::clone(&self.mfa_policy_api),
+        }))
+    }
+}
+
+pub struct BearerTokenAuthMiddleware<S> {
+    service: Rc<S>,
+    token_api: Arc<dyn ApiTokenApi>,
+    user_api: Arc<dyn UserApi>,
+    mfa_policy_api: Arc<dyn MfaPolicyApi>,
+}
+
+impl<S, B> Service<ServiceRequest> for BearerTokenAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        let secret = match bearer_secret(req.headers()) {
+            Some(secret) => secret,
+            None => return Box::pin(service.call(req)),
+        };
+        if !is_token_path(req.path()) {
+            return Box::pin(ready(Err(ApiError::Forbidden("API tokens cannot be used for this route, log in instead".to_owned()).into())));
+        }
+
+        let token_api = Arc::clone(&self.token_api);
+        let user_api = Arc::clone(&self.user_api);
+        let mfa_policy_api = Arc::clone(&self.mfa_policy_api);
+
+        Box::pin(async move {
+            let token = token_api.authenticate(&secret).await
+                .map_err(|e| match e {
+                    ApiTokenError::NotFound => ApiError::Unauthorized("Invalid API token".to_owned()),
+                    e => e.into(),
+                })?;
+
+            let scope = required_scope(req.method());
+            if !token.has_scope(scope) {
+                return Err(ApiError::Forbidden(format!("API token lacks the '{}' scope", scope.as_str())).into());
+            }
+
+            let user = user_api.find_by_id(token.user_id).await
+                .map_err(ApiError::from)?;
+            if user.locked {
+                return Err(ApiError::Unauthorized("Account is locked".to_owned()).into());
+            }
+            if mfa_policy_api.requires_enrollment(&user).await.map_err(ApiError::from)? {
+                return Err(ApiError::MfaEnrollmentRequired.into());
+            }
+
+            req.headers_mut().remove(COOKIE);
+            req.get_session().insert(AUTHFIX_SESSION_KEY_USER, &user)
+                .map_err(ApiError::from)?;
+            req.extensions_mut().insert(BearerAuthenticated { token });
+
+            let mut res = service.call(req).await?;
+            res.headers_mut().remove(SET_COOKIE);
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::Key, http::{header::{AUTHORIZATION, SET_COOKIE}, StatusCode}, test};
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{api_token::TokenScope, api_token_api::ApiTokenApi, mfa_policy::MfaPolicy, mfa_policy_api::MfaPolicyApi, user::{Role, User}, user_api::UserApi}, service::{api_token_service::ApiTokenService, mfa_policy_service::MfaPolicyService, user_service::UserService}};
+
+    const DB: &str = "file:bearer_auth_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_authenticate_with_bearer_token_without_creating_a_session() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let token_service = ApiTokenService::new(Arc::new(DbConfig::new(DB)));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let read_token = token_service.create_token(user.id, "read", vec![TokenScope::Read], Utc::now() + Duration::days(1)).await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = test::TestRequest::get().uri("/api/current-user")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", read_token.secret)))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(SET_COOKIE).is_none());
+
+        let req = test::TestRequest::post().uri("/api/totp/set-secret")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", read_token.secret)))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().uri("/api/current-user")
+            .insert_header((AUTHORIZATION, "Bearer mat_invalid"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn should_only_accept_tokens_on_data_routes() {
+        const DB: &str = "file:bearer_auth_routes_test?mode=memory&cache=shared";
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let token_service = ApiTokenService::new(Arc::new(DbConfig::new(DB)));
+        let admin = user_service.save_user_with_credentials(User::new(0, "admin@example.org".to_owned(), "Linda".to_owned()).with_role(Role::Admin), "test123").await.unwrap();
+        let token = token_service.create_token(admin.id, "script", vec![TokenScope::Read, TokenScope::Write], Utc::now() + Duration::days(1)).await.unwrap();
+        let bearer = (AUTHORIZATION, format!("Bearer {}", token.secret));
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = test::TestRequest::post().uri("/api/activities").insert_header(bearer.clone())
+            .set_json(json!({ "title": "Run", "start": "2025-03-01T07:00:00Z" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let requests = [
+            test::TestRequest::delete().uri("/api/webauthn/credentials/1"),
+            test::TestRequest::post().uri("/api/webauthn/register/start"),
+            test::TestRequest::put().uri("/api/mfa/factor").set_json(json!({ "mfa_id": "email_code" })),
+            test::TestRequest::get().uri("/api/totp/qrcode"),
+            test::TestRequest::delete().uri("/api/trusted-devices"),
+            test::TestRequest::get().uri("/api/tokens"),
+            test::TestRequest::put().uri("/api/account/password").set_json(json!({ "current_password": "test123", "new_password": "a much longer password" })),
+            test::TestRequest::get().uri("/api/admin/users"),
+            test::TestRequest::post().uri(&format!("/api/admin/users/{}/unlock", admin.id)),
+            test::TestRequest::put().uri("/api/admin/mfa-policy").set_json(json!({ "required_for_all": false, "required_roles": [] })),
+            test::TestRequest::post().uri("/api/calendar/feed"),
+            test::TestRequest::get().uri("/api/activitiesfoo"),
+        ];
+        for req in requests {
+            let req = req.insert_header(bearer.clone()).to_request();
+            let path = req.path().to_owned();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+    }
+
+    #[actix_web::test]
+    async fn should_reject_tokens_of_users_that_have_to_enroll_a_second_factor() {
+        const DB: &str = "file:bearer_auth_enrollment_test?mode=memory&cache=shared";
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let token_service = ApiTokenService::new(Arc::new(DbConfig::new(DB)));
+        let policy_service = MfaPolicyService::new(Arc::new(DbConfig::new(DB)));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let token = token_service.create_token(user.id, "read", vec![TokenScope::Read], Utc::now() + Duration::days(1)).await.unwrap();
+        policy_service.save_policy(MfaPolicy { required_for_all: true, required_roles: vec![] }).await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = test::TestRequest::get().uri("/api/activities")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token.secret)))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["type"], "mfa-enrollment-required");
+    }
+}
@@ -0,0 +1,191 @@
+use std::{future::{ready, Ready}, rc::Rc};
+
+use actix_web::{cookie::{Cookie, SameSite}, dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::{header::{HeaderName, HeaderValue}, Method}, Error, HttpMessage};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use futures::future::LocalBoxFuture;
+
+use crate::{error::errors::ApiError, middleware::bearer_auth::BearerAuthenticated};
+
+/// Cookie and header names the Angular `HttpClient` uses for its XSRF protection by default
+pub const CSRF_COOKIE: &str = "XSRF-TOKEN";
+pub const CSRF_HEADER: &str = "x-xsrf-token";
+
+/// Double-submit CSRF protection for all state-changing `/api` requests.
+///
+/// Every response to a request without token cookie sets a new random token in a cookie that
+/// scripts of the own origin can read. Non-GET requests must echo it in the [CSRF_HEADER] header,
+/// other origins can neither read the cookie nor send custom headers with a plain form post.
+/// The token is also returned in that header, for frontends on an allowed CORS origin.
+/// Requests authenticated by an API token carry no cookies and are exempt,
+/// so this middleware must be wrapped inside `BearerTokenAuth`.
+pub struct CsrfProtection;
+
+fn is_safe(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD || method == Method::OPTIONS
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compares without returning early, so the time taken does not reveal the matching prefix
+fn tokens_match(cookie: &str, header: &str) -> bool {
+    cookie.len() == header.len()
+        && cookie.bytes().zip(header.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn check_token(req: &ServiceRequest) -> Result<(), ApiError> {
+    if !req.path().starts_with("/api/") || is_safe(req.method()) || req.extensions().contains::<BearerAuthenticated>() {
+        return Ok(());
+    }
+
+    let cookie = req.cookie(CSRF_COOKIE);
+    let header = req.headers().get(CSRF_HEADER).and_then(|value| value.to_str().ok());
+    match (cookie, header) {
+        (Some(cookie), Some(header)) if !cookie.value().is_empty() && tokens_match(cookie.value(), header) => Ok(()),
+        _ => Err(ApiError::Forbidden("Missing or invalid CSRF token".to_owned())),
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfProtectionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfProtectionMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfProtectionMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfProtectionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Err(e) = check_token(&req) {
+            log::warn!("Rejected {} {}: {}", req.method(), req.path(), e);
+            return Box::pin(ready(Err(e.into())));
+        }
+
+        let current = req.cookie(CSRF_COOKIE)
+            .map(|cookie| cookie.value().to_owned())
+            .filter(|token| !token.is_empty());
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+
+            let token = match current {
+                Some(token) => token,
+                None => {
+                    // readable by the frontend on purpose, the token protects nothing if it leaks to the own origin
+                    let cookie = Cookie::build(CSRF_COOKIE, generate_token())
+                        .path("/")
+                        .same_site(SameSite::Strict)
+                        .finish();
+                    if let Err(e) = res.response_mut().add_cookie(&cookie) {
+                        log::error!("Cannot set CSRF cookie: {}", e);
+                    }
+                    cookie.value().to_owned()
+                },
+            };
+            // frontends on other origins cannot read the cookie, allowed origins get the header through CORS
+            if let Ok(value) = HeaderValue::from_str(&token) {
+                res.headers_mut().insert(HeaderName::from_static(CSRF_HEADER), value);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+/// Adds a matching token cookie and header to a test request
+#[cfg(test)]
+pub fn with_csrf_token(req: actix_web::test::TestRequest) -> actix_web::test::TestRequest {
+    req.cookie(Cookie::new(CSRF_COOKIE, "test-token"))
+        .insert_header((CSRF_HEADER, "test-token"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::Key, http::{header::CONTENT_TYPE, StatusCode}, test};
+    use serde_json::json;
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, service::user_service::UserService};
+
+    use super::{with_csrf_token, CSRF_COOKIE, CSRF_HEADER};
+
+    const DB: &str = "file:csrf_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_reject_state_changing_requests_without_matching_token() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        // The token is handed out with any response
+        let req = test::TestRequest::get().uri("/api/test").to_request();
+        let res = test::call_service(&app, req).await;
+        let token = res.response().cookies().find(|c| c.name() == CSRF_COOKIE).unwrap().into_owned();
+        assert!(!token.http_only().unwrap_or(false));
+
+        // A cross-origin form post can neither read the cookie nor set the header
+        let req = test::TestRequest::post().uri("/api/login")
+            .cookie(token.clone())
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload("email=test%40example.org&password=test123")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let login = json!({ "email": "test@example.org", "password": "test123" });
+        let req = test::TestRequest::post().uri("/api/login").cookie(token.clone())
+            .insert_header((CSRF_HEADER, "guessed"))
+            .set_json(&login)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post().uri("/api/login").cookie(token.clone())
+            .insert_header((CSRF_HEADER, token.value()))
+            .set_json(&login)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.response().cookies().all(|c| c.name() != CSRF_COOKIE));
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = test::TestRequest::post().uri("/api/logout").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/logout").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, Error, HttpMessage};
+use authfix::AuthToken;
+use futures::future::LocalBoxFuture;
+
+use crate::{domain::{user::User, user_api::UserApi}, error::errors::{ApiError, QueryUserError}, middleware::bearer_auth::BearerAuthenticated};
+
+/// Rejects requests of sessions whose account was locked or deleted after the login.
+///
+/// The session carries a copy of the user taken at login and cookie sessions cannot be revoked
+/// on the server, so the account is loaded again for every authenticated request.
+/// Token requests are skipped, `BearerTokenAuth` already loads their user.
+/// Needs the `AuthToken` inserted by authfix, so it has to wrap a scope inside the app built by `SessionLoginAppBuilder`.
+pub struct LockedAccountGuard {
+    user_api: Arc<dyn UserApi>,
+}
+
+impl LockedAccountGuard {
+    pub fn new(user_api: Arc<dyn UserApi>) -> Self {
+        Self {
+            user_api,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for LockedAccountGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LockedAccountGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LockedAccountGuardMiddleware {
+            service: Rc::new(service),
+            user_api: Arc::clone(&self.user_api),
+        }))
+    }
+}
+
+pub struct LockedAccountGuardMiddleware<S> {
+    service: Rc<S>,
+    user_api: Arc<dyn UserApi>,
+}
+
+impl<S, B> Service<ServiceRequest> for LockedAccountGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let user_id = match req.extensions().get::<BearerAuthenticated>() {
+            Some(_) => None,
+            None => req.extensions().get::<AuthToken<User>>().map(|token| token.authenticated_user().id),
+        };
+        let Some(user_id) = user_id else {
+            return Box::pin(self.service.call(req));
+        };
+
+        let service = Rc::clone(&self.service);
+        let user_api = Arc::clone(&self.user_api);
+
+        Box::pin(async move {
+            match user_api.find_by_id(user_id).await {
+                Ok(user) if user.locked => Err(ApiError::Unauthorized("Account is locked".to_owned()).into()),
+                Ok(_) => service.call(req).await,
+                Err(QueryUserError::NotFound) => Err(ApiError::Unauthorized("Account no longer exists".to_owned()).into()),
+                Err(e) => Err(ApiError::from(e).into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::{Cookie, Key}, http::StatusCode, test};
+    use serde_json::json;
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{user::{Role, User}, user_api::UserApi}, middleware::csrf::with_csrf_token, service::user_service::UserService};
+
+    const DB: &str = "file:locked_account_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_reject_existing_sessions_of_locked_accounts() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        user_service.save_user_with_credentials(User::new(0, "admin@example.org".to_owned(), "Linda".to_owned()).with_role(Role::Admin), "test123").await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let mut sessions: Vec<Cookie> = Vec::new();
+        for email in ["test@example.org", "admin@example.org"] {
+            let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+                .set_json(json!({ "email": email, "password": "test123" }))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            sessions.push(res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned());
+        }
+        let (session, admin_session) = (sessions[0].clone(), sessions[1].clone());
+
+        let req = with_csrf_token(test::TestRequest::post()).uri(&format!("/api/admin/users/{}/lock", user.id)).cookie(admin_session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri(&format!("/api/admin/users/{}/unlock", user.id)).cookie(admin_session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{future::{ready, Ready}, rc::Rc};
+
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, Error};
+use futures::future::LocalBoxFuture;
+
+use crate::service::auth_service::with_login_failure_slot;
+
+/// Path of authfix's login route
+const LOGIN_PATH: &str = "/api/login";
+
+/// Gives every login request its own slot for the reason of a failed login, see [with_login_failure_slot].
+///
+/// The login route belongs to authfix, so the slot is opened around it.
+/// This middleware must therefore wrap the app built by `SessionLoginAppBuilder`.
+pub struct LoginFailureSlot;
+
+impl<S, B> Transform<S, ServiceRequest> for LoginFailureSlot
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LoginFailureSlotMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LoginFailureSlotMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct LoginFailureSlotMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for LoginFailureSlotMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if req.path() != LOGIN_PATH {
+            return Box::pin(self.service.call(req));
+        }
+
+        Box::pin(with_login_failure_slot(self.service.call(req)))
+    }
+}
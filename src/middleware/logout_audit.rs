@@ -0,0 +1,85 @@
+use std::{future::{ready, Ready}, rc::Rc, sync::Arc};
+
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, Error, HttpMessage};
+use authfix::AuthToken;
+use futures::future::LocalBoxFuture;
+
+use crate::{domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditOutcome, NewAuditEvent}, user::User}, service::audit_service::record_event};
+
+/// Path of authfix's logout route
+const LOGOUT_PATH: &str = "/api/logout";
+
+/// Records logouts in the audit log.
+///
+/// The logout route belongs to authfix and offers no hook. The `AuthToken` of the logged out user
+/// stays in the request extensions, so it is read after the response was created.
+/// This middleware must therefore wrap the app built by `SessionLoginAppBuilder`.
+pub struct LogoutAudit {
+    audit_api: Arc<dyn AuditApi>,
+}
+
+impl LogoutAudit {
+    pub fn new(audit_api: Arc<dyn AuditApi>) -> Self {
+        Self {
+            audit_api,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for LogoutAudit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LogoutAuditMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LogoutAuditMiddleware {
+            service: Rc::new(service),
+            audit_api: Arc::clone(&self.audit_api),
+        }))
+    }
+}
+
+pub struct LogoutAuditMiddleware<S> {
+    service: Rc<S>,
+    audit_api: Arc<dyn AuditApi>,
+}
+
+impl<S, B> Service<ServiceRequest> for LogoutAuditMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if req.path() != LOGOUT_PATH {
+            return Box::pin(self.service.call(req));
+        }
+
+        let service = Rc::clone(&self.service);
+        let audit_api = Arc::clone(&self.audit_api);
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+
+            let user_id = res.request().extensions().get::<AuthToken<User>>()
+                .map(|token| token.authenticated_user().id);
+            if let (Some(user_id), false) = (user_id, res.response().status().is_server_error()) {
+                let event = NewAuditEvent::new(AuditEventType::Logout, AuditOutcome::Success).user(user_id).request(res.request());
+                record_event(audit_api.as_ref(), event).await;
+            }
+
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use std::{future::{ready, Ready}, rc::Rc};
+
+use actix_session::SessionExt;
+use actix_web::{dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, Error};
+use futures::future::LocalBoxFuture;
+
+use crate::{error::errors::ApiError, service::mfa_policy_service::SESSION_KEY_MFA_ENROLLMENT_REQUIRED};
+
+/// Routes that stay usable while a second factor has to be set up
+const ENROLLMENT_PATHS: [&str; 5] = [
+    "/api/totp/qrcode",
+    "/api/totp/set-secret",
+    "/api/webauthn/register/start",
+    "/api/webauthn/register/finish",
+    // enables the email code, which needs no setup
+    "/api/mfa/factor",
+];
+
+/// Rejects requests of sessions in the enrollment-only state with [ApiError::MfaEnrollmentRequired].
+///
+/// The state is set at login by `MfaEnrollmentCheck` and removed once a factor was enrolled.
+/// Needs the session, so it has to wrap a scope inside the app built by `SessionLoginAppBuilder`.
+pub struct MfaEnrollmentGuard;
+
+impl<S, B> Transform<S, ServiceRequest> for MfaEnrollmentGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = MfaEnrollmentGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MfaEnrollmentGuardMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct MfaEnrollmentGuardMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MfaEnrollmentGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if ENROLLMENT_PATHS.contains(&req.path()) {
+            return Box::pin(self.service.call(req));
+        }
+
+        match req.get_session().get::<bool>(SESSION_KEY_MFA_ENROLLMENT_REQUIRED) {
+            Ok(Some(true)) => Box::pin(ready(Err(ApiError::MfaEnrollmentRequired.into()))),
+            Ok(_) => Box::pin(self.service.call(req)),
+            Err(e) => Box::pin(ready(Err(ApiError::from(e).into()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::Key, http::StatusCode, test};
+    use serde_json::{json, Value};
+
+    use crate::{app_factory::create_app, config::{config::Config, db::DbConfig}, create_db, domain::{mfa_policy::MfaPolicy, mfa_policy_api::MfaPolicyApi, user::User, user_api::UserApi}, middleware::csrf::with_csrf_token, service::{mfa_policy_service::MfaPolicyService, user_service::UserService}};
+
+    const DB: &str = "file:mfa_enrollment_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_only_allow_enrollment_if_policy_requires_mfa() {
+        let _db = create_db(&DbConfig::new(DB));
+        let user_service = UserService::new(Arc::new(DbConfig::new(DB)));
+        let policy_service = MfaPolicyService::new(Arc::new(DbConfig::new(DB)));
+        user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        policy_service.save_policy(MfaPolicy { required_for_all: true, required_roles: vec![] }).await.unwrap();
+
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(Config::default()))).await;
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/login")
+            .set_json(json!({ "email": "test@example.org", "password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let problem: Value = test::read_body_json(res).await;
+        assert_eq!(problem["type"], "mfa-enrollment-required");
+
+        let req = test::TestRequest::get().uri("/api/totp/qrcode").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = with_csrf_token(test::TestRequest::put()).uri("/api/mfa/factor").cookie(session.clone())
+            .set_json(json!({ "mfa_id": "EMAIL_CODE_MFA", "current_password": "test123" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").map(|c| c.into_owned()).unwrap_or(session);
+
+        let req = test::TestRequest::get().uri("/api/current-user").cookie(session.clone()).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = with_csrf_token(test::TestRequest::post()).uri("/api/logout").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,154 @@
+use std::{future::{ready, Ready}, rc::Rc};
+
+use actix_web::{body::EitherBody, dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS}, test::TestRequest, Error, HttpRequest};
+use futures::future::LocalBoxFuture;
+
+/// Adds security headers to every response. Errors of inner middlewares, e.g. the 401 of the authentication,
+/// are turned into responses first, so the outer CORS middleware amends them as well.
+///
+/// Headers set by a handler are kept. `X-Frame-Options` duplicates `frame-ancestors` of the
+/// policy for browsers that do not support it.
+pub struct SecurityHeaders {
+    headers: Rc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+impl SecurityHeaders {
+    pub fn new(content_security_policy: &str) -> Self {
+        let content_security_policy = HeaderValue::from_str(content_security_policy)
+            .expect("Content security policy must be a valid header value");
+
+        Self {
+            headers: Rc::new(vec![
+                (CONTENT_SECURITY_POLICY, content_security_policy),
+                (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+                (REFERRER_POLICY, HeaderValue::from_static("no-referrer")),
+                (X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+            ]),
+        }
+    }
+}
+
+/// Detached copy of the request head for the response of an error. The request itself cannot be kept,
+/// the routing of the app needs it unshared.
+fn detached_request(req: &ServiceRequest) -> HttpRequest {
+    let mut copy = TestRequest::default()
+        .method(req.method().clone())
+        .uri(&req.uri().to_string())
+        .version(req.version());
+    for (name, value) in req.headers() {
+        copy = copy.append_header((name.clone(), value.clone()));
+    }
+    if let Some(addr) = req.peer_addr() {
+        copy = copy.peer_addr(addr);
+    }
+    copy.to_http_request()
+}
+
+fn add_headers(target: &mut HeaderMap, headers: &[(HeaderName, HeaderValue)]) {
+    for (name, value) in headers {
+        if !target.contains_key(name) {
+            target.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SecurityHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+            headers: Rc::clone(&self.headers),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    headers: Rc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let headers = Rc::clone(&self.headers);
+
+        Box::pin(async move {
+            let http_req = detached_request(&req);
+            let mut res = match service.call(req).await {
+                Ok(res) => res.map_into_left_body(),
+                Err(e) => ServiceResponse::new(http_req, e.error_response()).map_into_right_body(),
+            };
+            add_headers(res.headers_mut(), &headers);
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{cookie::Key, http::{header::{ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, CONTENT_SECURITY_POLICY, ORIGIN, X_CONTENT_TYPE_OPTIONS}, Method, StatusCode}, test};
+
+    use crate::{app_factory::create_app, config::{config::{Config, CorsConfig}, db::DbConfig}, create_db};
+
+    const DB: &str = "file:security_headers_test?mode=memory&cache=shared";
+
+    #[actix_web::test]
+    async fn should_send_security_headers_and_allow_configured_origins() {
+        let _db = create_db(&DbConfig::new(DB));
+        let config = Config {
+            cors: CorsConfig {
+                allowed_origins: vec!["https://app.example.org".to_owned()],
+                allow_credentials: true,
+                ..CorsConfig::default()
+            },
+            ..Config::default()
+        };
+        let app = test::init_service(create_app(Key::generate(), DbConfig::new(DB), Arc::new(config))).await;
+
+        let req = test::TestRequest::get().uri("/api/test").insert_header((ORIGIN, "https://app.example.org")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert!(res.headers().get(CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap().contains("frame-ancestors 'none'"));
+        assert_eq!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://app.example.org");
+        assert_eq!(res.headers().get(ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+
+        let req = test::TestRequest::get().uri("/api/current-user").insert_header((ORIGIN, "https://app.example.org")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers().get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://app.example.org");
+
+        let req = test::TestRequest::default().method(Method::OPTIONS).uri("/api/activities")
+            .insert_header((ORIGIN, "https://evil.example.com"))
+            .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        let res = test::try_call_service(&app, req).await;
+        let status = match res {
+            Ok(res) => res.status(),
+            Err(err) => err.error_response().status(),
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,2 +1,24 @@
 pub mod user_service;
-pub mod auth_service;
\ No newline at end of file
+pub mod auth_service;
+pub mod api_token_service;
+pub mod oidc_service;
+pub mod webauthn_service;
+pub mod webauthn_factor;
+pub mod mail_service;
+pub mod email_code_service;
+pub mod email_code_factor;
+pub mod trusted_device_service;
+pub mod mfa_policy_service;
+pub mod password_policy;
+pub mod password_reset_service;
+pub mod email_change_service;
+pub mod audit_service;
+pub mod notification_service;
+pub mod activity_service;
+pub mod category_service;
+pub mod tag_service;
+pub mod time_entry_service;
+pub mod calendar_service;
+pub mod exchange_service;
+pub mod report_service;
+pub mod goal_service;
\ No newline at end of file
@@ -0,0 +1,950 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{params_from_iter, types::Type, Connection, OptionalExtension, Row, ToSql};
+
+use crate::{config::db::DbConfig, domain::{activity::{Activity, ActivityException, ActivityFilter, ActivitySearch, ActivitySearchHit, ActivitySort, NewActivity, NewActivityException}, activity_api::ActivityApi, activity_import::{ImportIssue, ImportSummary, ImportedActivity}, category::NewCategory, page::{Cursor, Page, PageRequest, SortOrder}, recurrence::{Recurrence, MAX_OCCURRENCES}, time_entry::NewTimeEntry}, error::errors::{ActivityError, FieldError}};
+
+/// Selects from `activities a`, the tags are aggregated into a JSON array
+const ACTIVITY_COLUMNS: &str = "a.id, a.user_id, a.title, a.description, a.start_time, a.end_time, a.category_id, a.created_at, a.updated_at, a.recurrence, \
+    (SELECT json_group_array(t.name ORDER BY t.name) FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id), a.time_zone";
+/// Color of the categories created by imports
+const IMPORTED_CATEGORY_COLOR: &str = "#9e9e9e";
+const EXCEPTION_COLUMNS: &str = "activity_id, occurrence, skipped, title, description, start_time, end_time";
+/// Control characters mark the matches in the snippet, they cannot occur in the escaped text
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+pub struct ActivityService {
+    db_config: Arc<DbConfig>
+}
+
+impl ActivityService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+/// Maps a row selected with [ACTIVITY_COLUMNS]
+fn map_activity(row: &Row) -> rusqlite::Result<Activity> {
+    let tags: String = row.get(10)?;
+    let tags = serde_json::from_str(&tags)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, Type::Text, e.into()))?;
+
+    Ok(Activity {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        start: row.get(4)?,
+        end: row.get(5)?,
+        category_id: row.get(6)?,
+        tags,
+        recurrence: row.get(9)?,
+        time_zone: row.get(11)?,
+        occurrence: None,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+/// Maps a row selected with [EXCEPTION_COLUMNS]
+fn map_exception(row: &Row) -> rusqlite::Result<ActivityException> {
+    Ok(ActivityException {
+        activity_id: row.get(0)?,
+        occurrence: row.get(1)?,
+        skipped: row.get(2)?,
+        title: row.get(3)?,
+        description: row.get(4)?,
+        start: row.get(5)?,
+        end: row.get(6)?,
+    })
+}
+
+fn query_exceptions(conn: &Connection, activity_id: i32) -> rusqlite::Result<Vec<ActivityException>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM activity_exceptions WHERE activity_id = ?1 ORDER BY occurrence", EXCEPTION_COLUMNS))?;
+    let exceptions = stmt.query_map([activity_id], map_exception)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(exceptions)
+}
+
+/// Rules are validated before they are stored
+fn recurrence(activity: &Activity) -> Result<Option<Recurrence>, ActivityError> {
+    activity.recurrence.as_deref()
+        .map(str::parse::<Recurrence>)
+        .transpose()
+        .map_err(ActivityError::Database)
+}
+
+/// The occurrence of a recurring activity of the user starting at `occurrence`
+fn query_occurrence(conn: &Connection, user_id: i32, activity_id: i32, occurrence: DateTime<Utc>) -> Result<Activity, ActivityError> {
+    let activity = query_activity(conn, user_id, activity_id.into())?;
+    match recurrence(&activity)? {
+        Some(rule) if rule.is_occurrence(activity.start, activity.zone(), occurrence) => Ok(activity.occurrence(occurrence)),
+        _ => Err(ActivityError::OccurrenceNotFound),
+    }
+}
+
+/// Occurrences of a recurring activity that overlap the range, with their exceptions applied, in the order of
+/// their original starts and without limit
+fn iter_occurrences<'a>(activity: &'a Activity, rule: &'a Recurrence, exceptions: &'a [ActivityException], from: Option<DateTime<Utc>>, to: DateTime<Utc>)
+    -> impl Iterator<Item = Activity> + 'a {
+    // like single activities, occurrences without an end count as ending at their start
+    let duration = activity.end.map(|end| end - activity.start).unwrap_or_default();
+    let earliest = from.map(|from| from - duration).unwrap_or(activity.start);
+
+    rule.iter_occurrences(activity.start, activity.zone(), earliest, to)
+        .filter_map(|start| {
+            let occurrence = activity.occurrence(start);
+            match exceptions.iter().find(|exception| exception.occurrence == start) {
+                Some(exception) => exception.apply(occurrence),
+                None => Some(occurrence),
+            }
+        })
+        .filter(move |occurrence| from.is_none_or(|from| occurrence.end.unwrap_or(occurrence.start) >= from) && occurrence.start < to)
+}
+
+/// Occurrences of a recurring activity that overlap the range, with their exceptions applied, at most [MAX_OCCURRENCES]
+fn expand(conn: &Connection, activity: Activity, from: Option<DateTime<Utc>>, to: DateTime<Utc>) -> Result<Vec<Activity>, ActivityError> {
+    let Some(rule) = recurrence(&activity)? else {
+        return Ok(vec![activity]);
+    };
+    let exceptions = query_exceptions(conn, activity.id)?;
+
+    Ok(iter_occurrences(&activity, &rule, &exceptions, from, to).take(MAX_OCCURRENCES).collect())
+}
+
+fn query_activity(conn: &Connection, user_id: i32, activity_id: i64) -> rusqlite::Result<Activity> {
+    conn.query_row(
+        &format!("SELECT {} FROM activities a WHERE a.id = ?1 AND a.user_id = ?2", ACTIVITY_COLUMNS),
+        (activity_id, user_id),
+        map_activity
+    )
+}
+
+/// Only categories of the user can be assigned
+fn check_category(conn: &Connection, user_id: i32, category_id: Option<i32>) -> Result<(), ActivityError> {
+    let Some(category_id) = category_id else {
+        return Ok(());
+    };
+
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM categories WHERE id = ?1 AND user_id = ?2", (category_id, user_id), |row| row.get(0))?;
+    if count == 0 {
+        return Err(ActivityError::Invalid(vec![FieldError::new("category_id", "unknown", "Category does not exist")]));
+    }
+
+    Ok(())
+}
+
+/// Replaces the tags of the activity. Missing tags are created, tags without activities removed.
+fn save_tags(conn: &Connection, user_id: i32, activity_id: i64, tags: &[String]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM activity_tags WHERE activity_id = ?1", [activity_id])?;
+    for tag in tags {
+        // names compare case-insensitive, an existing tag keeps its spelling
+        conn.execute("INSERT OR IGNORE INTO tags (user_id, name) values (?1, ?2)", (user_id, tag))?;
+        conn.execute(
+            "INSERT INTO activity_tags (activity_id, tag_id) SELECT ?1, id FROM tags WHERE user_id = ?2 AND name = ?3",
+            (activity_id, user_id, tag)
+        )?;
+    }
+
+    delete_unused_tags(conn, user_id)
+}
+
+fn delete_unused_tags(conn: &Connection, user_id: i32) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM tags WHERE user_id = ?1 AND NOT EXISTS (SELECT 1 FROM activity_tags x WHERE x.tag_id = tags.id)", [user_id])?;
+    Ok(())
+}
+
+/// Inserts a validated activity with its tags and returns its id
+fn insert_activity(conn: &Connection, user_id: i32, activity: &NewActivity, import_uid: Option<&str>) -> rusqlite::Result<i64> {
+    let now = Utc::now();
+    conn.execute(
+        "INSERT INTO activities (user_id, title, description, start_time, end_time, category_id, recurrence, time_zone, import_uid, created_at, updated_at) \
+            values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        (user_id, &activity.title, &activity.description, activity.start, activity.end, activity.category_id, &activity.recurrence, &activity.time_zone, import_uid, now, now)
+    )?;
+    let id = conn.last_insert_rowid();
+    save_tags(conn, user_id, id, &activity.tags)?;
+
+    Ok(id)
+}
+
+/// Updates a validated activity with its tags, returns false if the user has no such activity
+fn update_activity(conn: &Connection, user_id: i32, activity_id: i64, activity: &NewActivity) -> rusqlite::Result<bool> {
+    // exceptions refer to the occurrences of the previous series
+    conn.execute(
+        "DELETE FROM activity_exceptions WHERE activity_id IN (SELECT id FROM activities WHERE id = ?1 AND user_id = ?2 AND (start_time IS NOT ?3 OR recurrence IS NOT ?4 OR time_zone IS NOT ?5))",
+        (activity_id, user_id, activity.start, &activity.recurrence, &activity.time_zone)
+    )?;
+    let updated = conn.execute(
+        "UPDATE activities SET title = ?1, description = ?2, start_time = ?3, end_time = ?4, category_id = ?5, recurrence = ?6, time_zone = ?7, updated_at = ?8 WHERE id = ?9 AND user_id = ?10",
+        (&activity.title, &activity.description, activity.start, activity.end, activity.category_id, &activity.recurrence, &activity.time_zone, Utc::now(), activity_id, user_id)
+    )?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    save_tags(conn, user_id, activity_id, &activity.tags)?;
+
+    Ok(true)
+}
+
+/// Conditions on `activities a` with their parameters, the user id is the first parameter
+fn filter_conditions(user_id: i32, filter: &ActivityFilter) -> (Vec<String>, Vec<Box<dyn ToSql + Send>>) {
+    let mut params: Vec<Box<dyn ToSql + Send>> = vec![Box::new(user_id)];
+    let mut conditions = vec!["a.user_id = ?1".to_owned()];
+    if let Some(from) = filter.from {
+        params.push(Box::new(from));
+        // later occurrences of recurring activities may still match
+        conditions.push(format!("(a.recurrence IS NOT NULL OR COALESCE(a.end_time, a.start_time) >= ?{})", params.len()));
+    }
+    if let Some(to) = filter.to {
+        params.push(Box::new(to));
+        conditions.push(format!("a.start_time < ?{}", params.len()));
+    }
+    if !filter.category_ids.is_empty() {
+        let placeholders = push_params(&mut params, &filter.category_ids);
+        conditions.push(format!("a.category_id IN ({})", placeholders));
+    }
+    // tag names are compared case-insensitive, see the collation of tags.name
+    for tag in &filter.all_tags {
+        params.push(Box::new(tag.clone()));
+        conditions.push(format!("EXISTS (SELECT 1 FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id AND t.name = ?{})", params.len()));
+    }
+    if !filter.any_tags.is_empty() {
+        let placeholders = push_params(&mut params, &filter.any_tags);
+        conditions.push(format!("EXISTS (SELECT 1 FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id AND t.name IN ({}))", placeholders));
+    }
+    match filter.recurring {
+        Some(true) => conditions.push("a.recurrence IS NOT NULL".to_owned()),
+        Some(false) => conditions.push("a.recurrence IS NULL".to_owned()),
+        None => {},
+    }
+
+    (conditions, params)
+}
+
+/// Sort key of an expanded occurrence, the fixed width times compare as text.
+/// Occurrences of the same series share the id, their start breaks the tie.
+/// Titles fold only ASCII letters like `COLLATE NOCASE`, so the keys follow the order of [occurrence_order].
+fn occurrence_key(occurrence: &Activity, sort: ActivitySort) -> String {
+    let time = |time: DateTime<Utc>| time.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string();
+    match sort {
+        ActivitySort::Start => time(occurrence.start),
+        ActivitySort::Title => format!("{}\u{0}{}", occurrence.title.to_ascii_lowercase(), time(occurrence.start)),
+        ActivitySort::UpdatedAt => format!("{}\u{0}{}", time(occurrence.updated_at), time(occurrence.start)),
+    }
+}
+
+/// `ORDER BY` of single activities in the order of [occurrence_key]
+fn occurrence_order(sort: ActivitySort, direction: &str) -> String {
+    match sort {
+        ActivitySort::Start => format!("a.start_time {0}, a.id {0}", direction),
+        ActivitySort::Title => format!("a.title COLLATE NOCASE {0}, a.start_time {0}, a.id {0}", direction),
+        ActivitySort::UpdatedAt => format!("a.updated_at {0}, a.start_time {0}, a.id {0}", direction),
+    }
+}
+
+/// Column and value that the rows at or after a cursor of [occurrence_key] reach, the exact comparison follows in memory
+fn occurrence_bound(sort: ActivitySort, cursor: &Cursor) -> Option<(&'static str, Box<dyn ToSql + Send>)> {
+    let first = cursor.key.split('\u{0}').next().unwrap_or_default();
+    let time = || DateTime::parse_from_rfc3339(first).ok().map(|time| Box::new(time.with_timezone(&Utc)) as Box<dyn ToSql + Send>);
+    match sort {
+        ActivitySort::Start => Some(("a.start_time", time()?)),
+        ActivitySort::Title => Some(("a.title COLLATE NOCASE", Box::new(first.to_owned()))),
+        ActivitySort::UpdatedAt => Some(("a.updated_at", time()?)),
+    }
+}
+
+/// Escapes the snippet for HTML and wraps the marked matches in `<mark>`
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+
+    html
+}
+
+/// Checks all parts of an imported activity, so it is either imported completely or not at all
+fn validate_import(imported: ImportedActivity) -> Result<(NewActivity, Option<NewCategory>, Vec<NewTimeEntry>), String> {
+    let messages = |errors: Vec<FieldError>| errors.into_iter().map(|e| e.message).collect::<Vec<_>>().join(", ");
+
+    let activity = imported.activity.validate().map_err(messages)?;
+    let category = imported.category
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| NewCategory { name, color: IMPORTED_CATEGORY_COLOR.to_owned(), icon: None }.validate())
+        .transpose()
+        .map_err(|errors| format!("Category: {}", messages(errors)))?;
+
+    let mut entries = Vec::new();
+    for (n, entry) in imported.time_entries.into_iter().enumerate() {
+        let entry = entry.validate().map_err(|errors| format!("Time entry {}: {}", n + 1, messages(errors)))?;
+        if entry.end.is_none() {
+            return Err(format!("Time entry {}: End is missing", n + 1));
+        }
+        entries.push(entry);
+    }
+
+    Ok((activity, category, entries))
+}
+
+/// Returns the id of the category with the name, the category is created if it does not exist
+fn import_category(conn: &Connection, user_id: i32, category: &NewCategory) -> rusqlite::Result<i32> {
+    // names compare case-insensitive, an existing category keeps its spelling and color
+    conn.execute(
+        "INSERT OR IGNORE INTO categories (user_id, name, color, icon) values (?1, ?2, ?3, ?4)",
+        (user_id, &category.name, &category.color, &category.icon)
+    )?;
+    conn.query_row("SELECT id FROM categories WHERE user_id = ?1 AND name = ?2", (user_id, &category.name), |row| row.get(0))
+}
+
+/// Tags are compared like their names in the database, ignoring case
+fn is_unchanged(current: &Activity, activity: &NewActivity) -> bool {
+    let lowercase = |tags: &[String]| {
+        let mut tags = tags.iter().map(|tag| tag.to_lowercase()).collect::<Vec<_>>();
+        tags.sort();
+        tags
+    };
+
+    current.title == activity.title
+        && current.description == activity.description
+        && current.start == activity.start
+        && current.end == activity.end
+        && current.recurrence == activity.recurrence
+        && current.time_zone == activity.time_zone
+        && current.category_id == activity.category_id
+        && lowercase(&current.tags) == lowercase(&activity.tags)
+}
+
+/// Adds a placeholder for every value and returns them comma separated
+fn push_params<T: ToSql + Send + Clone + 'static>(params: &mut Vec<Box<dyn ToSql + Send>>, values: &[T]) -> String {
+    values.iter()
+        .map(|value| {
+            params.push(Box::new(value.clone()));
+            format!("?{}", params.len())
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[async_trait]
+impl ActivityApi for ActivityService {
+    async fn create(&self, user_id: i32, activity: NewActivity) -> Result<Activity, ActivityError> {
+        let activity = activity.validate().map_err(ActivityError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+            check_category(&tx, user_id, activity.category_id)?;
+
+            let id = insert_activity(&tx, user_id, &activity, None)?;
+
+            let created = query_activity(&tx, user_id, id)?;
+            tx.commit()?;
+
+            Ok(created)
+        }).await?
+    }
+
+    async fn find_by_id(&self, user_id: i32, activity_id: i32) -> Result<Activity, ActivityError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok(query_activity(&conn, user_id, activity_id.into())?)
+        }).await?
+    }
+
+    async fn find(&self, user_id: i32, filter: &ActivityFilter) -> Result<Vec<Activity>, ActivityError> {
+        let (conditions, params) = filter_conditions(user_id, filter);
+        let sql = format!("SELECT {} FROM activities a WHERE {} ORDER BY a.start_time DESC, a.id DESC", ACTIVITY_COLUMNS, conditions.join(" AND "));
+        let (from, to) = (filter.from, filter.to);
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&sql)?;
+            let activities = stmt.query_map(params_from_iter(params), map_activity)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let Some(to) = to else {
+                return Ok(activities);
+            };
+
+            let mut expanded = Vec::new();
+            for activity in activities {
+                expanded.extend(expand(&conn, activity, from, to)?);
+            }
+            expanded.sort_by(|a, b| b.start.cmp(&a.start).then(b.id.cmp(&a.id)));
+
+            Ok(expanded)
+        }).await?
+    }
+
+    async fn find_page(&self, user_id: i32, filter: &ActivityFilter, page: &PageRequest<ActivitySort>) -> Result<Page<Activity>, ActivityError> {
+        let (mut conditions, mut params) = filter_conditions(user_id, filter);
+        let (from, to, limit) = (filter.from, filter.to, page.limit);
+        let page = page.clone();
+        let db = self.db_config.get_database().to_owned();
+
+        // occurrences are only known after the expansion: single activities are read in the order of their keys
+        // until the page is full, every series contributes its first occurrences after the cursor
+        if let Some(to) = to {
+            let (mut series_conditions, series_params) = filter_conditions(user_id, filter);
+            series_conditions.push("a.recurrence IS NOT NULL".to_owned());
+            conditions.push("a.recurrence IS NULL".to_owned());
+            if let Some((column, value)) = page.cursor.as_ref().and_then(|cursor| occurrence_bound(page.sort, cursor)) {
+                params.push(value);
+                let operator = if page.order == SortOrder::Desc { "<=" } else { ">=" };
+                conditions.push(format!("{} {} ?{}", column, operator, params.len()));
+            }
+            let single_sql = format!(
+                "SELECT {} FROM activities a WHERE {} ORDER BY {}",
+                ACTIVITY_COLUMNS, conditions.join(" AND "), occurrence_order(page.sort, page.direction())
+            );
+            let series_sql = format!("SELECT {} FROM activities a WHERE {}", ACTIVITY_COLUMNS, series_conditions.join(" AND "));
+            return tokio::task::spawn_blocking(move || {
+                let conn = Connection::open(db)?;
+                let keyed = |occurrence: Activity| {
+                    let cursor = Cursor { key: occurrence_key(&occurrence, page.sort), id: occurrence.id };
+                    (occurrence, cursor)
+                };
+                let compare = |a: &Cursor, b: &Cursor| {
+                    let ordering = a.key.cmp(&b.key).then(a.id.cmp(&b.id));
+                    if page.order == SortOrder::Desc { ordering.reverse() } else { ordering }
+                };
+                let is_after_cursor = |cursor: &Cursor| page.cursor.as_ref().is_none_or(|after| compare(cursor, after) == Ordering::Greater);
+
+                let mut items = Vec::new();
+                let mut stmt = conn.prepare(&single_sql)?;
+                let mut rows = stmt.query(params_from_iter(params))?;
+                while items.len() <= limit {
+                    let Some(row) = rows.next()? else {
+                        break;
+                    };
+                    let item = keyed(map_activity(row)?);
+                    if is_after_cursor(&item.1) {
+                        items.push(item);
+                    }
+                }
+
+                let mut stmt = conn.prepare(&series_sql)?;
+                let series = stmt.query_map(params_from_iter(series_params), map_activity)?
+                    .collect::<Result<Vec<_>, _>>()?;
+                for activity in series {
+                    let Some(rule) = recurrence(&activity)? else {
+                        continue;
+                    };
+                    let exceptions = query_exceptions(&conn, activity.id)?;
+
+                    // a series is walked through to the end of the range, only the first occurrences after the cursor are kept
+                    let mut occurrences = Vec::new();
+                    for occurrence in iter_occurrences(&activity, &rule, &exceptions, from, to).map(keyed).filter(|(_, cursor)| is_after_cursor(cursor)) {
+                        occurrences.push(occurrence);
+                        if occurrences.len() > 2 * (limit + 1) {
+                            occurrences.sort_by(|(_, a), (_, b)| compare(a, b));
+                            occurrences.truncate(limit + 1);
+                        }
+                    }
+                    occurrences.sort_by(|(_, a), (_, b)| compare(a, b));
+                    occurrences.truncate(limit + 1);
+                    items.extend(occurrences);
+                }
+                items.sort_by(|(_, a), (_, b)| compare(a, b));
+                items.truncate(limit + 1);
+
+                Ok(Page::new(items, limit))
+            }).await?;
+        }
+
+        let sort = match page.sort {
+            ActivitySort::Start => "a.start_time",
+            ActivitySort::Title => "a.title COLLATE NOCASE",
+            ActivitySort::UpdatedAt => "a.updated_at",
+        };
+        if let Some(cursor) = &page.cursor {
+            params.push(Box::new(cursor.key.clone()));
+            params.push(Box::new(cursor.id));
+            conditions.push(format!("({0} {1} ?{2} OR ({0} = ?{2} AND a.id {1} ?{3}))", sort, page.operator(), params.len() - 1, params.len()));
+        }
+        let sql = format!(
+            "SELECT {0}, CAST({1} AS TEXT) FROM activities a WHERE {2} ORDER BY {1} {3}, a.id {3} LIMIT {4}",
+            ACTIVITY_COLUMNS, sort, conditions.join(" AND "), page.direction(), limit + 1
+        );
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&sql)?;
+            let activities = stmt.query_map(params_from_iter(params), |row| {
+                    let activity = map_activity(row)?;
+                    let cursor = Cursor { key: row.get(12)?, id: activity.id };
+                    Ok((activity, cursor))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Page::new(activities, limit))
+        }).await?
+    }
+
+    async fn search(&self, user_id: i32, search: &ActivitySearch) -> Result<Vec<ActivitySearchHit>, ActivityError> {
+        // quoted prefix terms, words like `and` or `near` are not read as FTS5 operators
+        let query = search.terms.iter().map(|term| format!("\"{}\"*", term)).collect::<Vec<_>>().join(" ");
+        let limit = search.limit as i64;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            // title matches weigh most, then tags, then the description
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {}, snippet(activity_search, -1, ?1, ?2, '…', 16) FROM activity_search JOIN activities a ON a.id = activity_search.rowid \
+                    WHERE activity_search MATCH ?3 AND a.user_id = ?4 ORDER BY bm25(activity_search, 10.0, 1.0, 5.0), a.id DESC LIMIT ?5",
+                ACTIVITY_COLUMNS
+            ))?;
+            let hits = stmt.query_map((MATCH_START.to_string(), MATCH_END.to_string(), query, user_id, limit), |row| {
+                    let snippet: String = row.get(12)?;
+                    Ok(ActivitySearchHit { activity: map_activity(row)?, snippet: highlight(&snippet) })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(hits)
+        }).await?
+    }
+
+    async fn update(&self, user_id: i32, activity_id: i32, activity: NewActivity) -> Result<Activity, ActivityError> {
+        let activity = activity.validate().map_err(ActivityError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+            check_category(&tx, user_id, activity.category_id)?;
+
+            if !update_activity(&tx, user_id, activity_id.into(), &activity)? {
+                return Err(ActivityError::NotFound);
+            }
+
+            let updated = query_activity(&tx, user_id, activity_id.into())?;
+            tx.commit()?;
+
+            Ok(updated)
+        }).await?
+    }
+
+    async fn delete(&self, user_id: i32, activity_id: i32) -> Result<(), ActivityError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+
+            tx.execute("DELETE FROM activity_tags WHERE activity_id IN (SELECT id FROM activities WHERE id = ?1 AND user_id = ?2)", (activity_id, user_id))?;
+            tx.execute("DELETE FROM time_entries WHERE activity_id = ?1 AND user_id = ?2", (activity_id, user_id))?;
+            tx.execute("DELETE FROM activity_exceptions WHERE activity_id IN (SELECT id FROM activities WHERE id = ?1 AND user_id = ?2)", (activity_id, user_id))?;
+            let deleted = tx.execute("DELETE FROM activities WHERE id = ?1 AND user_id = ?2", (activity_id, user_id))?;
+            if deleted == 0 {
+                return Err(ActivityError::NotFound);
+            }
+            delete_unused_tags(&tx, user_id)?;
+            tx.commit()?;
+
+            Ok(())
+        }).await?
+    }
+
+    async fn find_exceptions(&self, user_id: i32, activity_id: i32) -> Result<Vec<ActivityException>, ActivityError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            query_activity(&conn, user_id, activity_id.into())?;
+
+            Ok(query_exceptions(&conn, activity_id)?)
+        }).await?
+    }
+
+    async fn save_exception(&self, user_id: i32, activity_id: i32, occurrence: DateTime<Utc>, exception: NewActivityException)
+        -> Result<ActivityException, ActivityError>
+    {
+        let exception = exception.validate().map_err(ActivityError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let series = query_occurrence(&conn, user_id, activity_id, occurrence)?;
+
+            let exception = ActivityException {
+                activity_id,
+                occurrence,
+                skipped: exception.skipped,
+                title: exception.title,
+                description: exception.description,
+                start: exception.start,
+                end: exception.end,
+            };
+            if exception.apply(series).is_some_and(|changed| changed.end.is_some_and(|end| end < changed.start)) {
+                return Err(ActivityError::Invalid(vec![FieldError::new("end", "before_start", "End must not be before the start")]));
+            }
+
+            conn.execute(
+                "INSERT INTO activity_exceptions (activity_id, occurrence, skipped, title, description, start_time, end_time) values (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+                    ON CONFLICT (activity_id, occurrence) DO UPDATE SET skipped = excluded.skipped, title = excluded.title, description = excluded.description, \
+                    start_time = excluded.start_time, end_time = excluded.end_time",
+                (activity_id, occurrence, exception.skipped, &exception.title, &exception.description, exception.start, exception.end)
+            )?;
+
+            Ok(exception)
+        }).await?
+    }
+
+    async fn import(&self, user_id: i32, activities: Vec<ImportedActivity>) -> Result<ImportSummary, ActivityError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+
+            let mut summary = ImportSummary::default();
+            for imported in activities {
+                let (index, uid) = (imported.index, imported.uid.clone());
+                let (activity, category, entries) = match validate_import(imported) {
+                    Ok(valid) => valid,
+                    Err(message) => {
+                        summary.reject(ImportIssue { index, uid, message });
+                        continue;
+                    },
+                };
+                let category_id = category.map(|category| import_category(&tx, user_id, &category)).transpose()?;
+
+                let existing = match &uid {
+                    Some(uid) => tx.query_row("SELECT id FROM activities WHERE user_id = ?1 AND import_uid = ?2", (user_id, uid), |row| row.get::<_, i64>(0)).optional()?,
+                    None => None,
+                };
+                match existing {
+                    Some(id) => {
+                        let current = query_activity(&tx, user_id, id)?;
+                        let activity = NewActivity { category_id: category_id.or(current.category_id), ..activity };
+                        if is_unchanged(&current, &activity) {
+                            summary.skipped += 1;
+                        } else {
+                            update_activity(&tx, user_id, id, &activity)?;
+                            summary.updated += 1;
+                        }
+                    },
+                    None => {
+                        let activity = NewActivity { category_id, ..activity };
+                        let id = insert_activity(&tx, user_id, &activity, uid.as_deref())?;
+                        for entry in entries {
+                            tx.execute(
+                                "INSERT INTO time_entries (user_id, activity_id, start_time, end_time, note) values (?1, ?2, ?3, ?4, ?5)",
+                                (user_id, id, entry.start, entry.end, entry.note)
+                            )?;
+                        }
+                        summary.created += 1;
+                    },
+                }
+            }
+            tx.commit()?;
+
+            Ok(summary)
+        }).await?
+    }
+
+    async fn delete_exception(&self, user_id: i32, activity_id: i32, occurrence: DateTime<Utc>) -> Result<(), ActivityError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            query_activity(&conn, user_id, activity_id.into())?;
+
+            let deleted = conn.execute("DELETE FROM activity_exceptions WHERE activity_id = ?1 AND occurrence = ?2", (activity_id, occurrence))?;
+            if deleted == 0 {
+                return Err(ActivityError::OccurrenceNotFound);
+            }
+
+            Ok(())
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::{ActivityFilter, ActivitySearch, ActivitySort, NewActivity, NewActivityException}, activity_api::ActivityApi, category::NewCategory, page::{PageRequest, SortOrder}, category_api::CategoryApi, tag_api::TagApi, user::User, user_api::UserApi}, error::errors::ActivityError, service::{category_service::CategoryService, tag_service::TagService, user_service::UserService}};
+
+    use super::ActivityService;
+
+    fn activity(title: &str, day: u32, category_id: Option<i32>, tags: &[&str]) -> NewActivity {
+        NewActivity {
+            title: title.to_owned(),
+            description: None,
+            start: Utc.with_ymd_and_hms(2025, 3, day, 10, 0, 0).unwrap(),
+            end: Some(Utc.with_ymd_and_hms(2025, 3, day, 11, 0, 0).unwrap()),
+            category_id,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            recurrence: None,
+            time_zone: None,
+        }
+    }
+
+    fn titles(activities: Vec<crate::domain::activity::Activity>) -> Vec<String> {
+        activities.into_iter().map(|a| a.title).collect()
+    }
+
+    #[tokio::test]
+    async fn should_filter_by_category_and_tag_combinations() {
+        let db_config = Arc::new(DbConfig::new("file:activity_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = ActivityService::new(Arc::clone(&db_config));
+        let categories = CategoryService::new(Arc::clone(&db_config));
+        let tags = TagService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+
+        let sport = categories.create(user.id, NewCategory { name: "Sport".to_owned(), color: "#00FF00".to_owned(), icon: None }).await.unwrap();
+        let work = categories.create(user.id, NewCategory { name: "Work".to_owned(), color: "#0000ff".to_owned(), icon: Some("briefcase".to_owned()) }).await.unwrap();
+        let foreign = categories.create(other.id, NewCategory { name: "Sport".to_owned(), color: "#00ff00".to_owned(), icon: None }).await.unwrap();
+        assert_eq!(sport.color, "#00ff00");
+
+        let run = activities.create(user.id, activity("Run", 1, Some(sport.id), &["outdoor", "Morning", "morning "])).await.unwrap();
+        activities.create(user.id, activity("Swim", 2, Some(sport.id), &["indoor"])).await.unwrap();
+        activities.create(user.id, activity("Meeting", 3, Some(work.id), &["morning"])).await.unwrap();
+        activities.create(user.id, activity("Read", 4, None, &[])).await.unwrap();
+        assert_eq!(run.tags, vec!["Morning", "outdoor"]);
+
+        let all = activities.find(user.id, &ActivityFilter::default()).await.unwrap();
+        assert_eq!(titles(all), vec!["Read", "Meeting", "Swim", "Run"]);
+
+        let filter = ActivityFilter { category_ids: vec![sport.id], ..ActivityFilter::default() };
+        assert_eq!(titles(activities.find(user.id, &filter).await.unwrap()), vec!["Swim", "Run"]);
+
+        let filter = ActivityFilter { all_tags: vec!["MORNING".to_owned(), "outdoor".to_owned()], ..ActivityFilter::default() };
+        assert_eq!(titles(activities.find(user.id, &filter).await.unwrap()), vec!["Run"]);
+
+        let filter = ActivityFilter { any_tags: vec!["indoor".to_owned(), "morning".to_owned()], ..ActivityFilter::default() };
+        assert_eq!(titles(activities.find(user.id, &filter).await.unwrap()), vec!["Meeting", "Swim", "Run"]);
+
+        let filter = ActivityFilter { category_ids: vec![sport.id, work.id], all_tags: vec!["morning".to_owned()], ..ActivityFilter::default() };
+        assert_eq!(titles(activities.find(user.id, &filter).await.unwrap()), vec!["Meeting", "Run"]);
+
+        let filter = ActivityFilter {
+            from: Some(Utc.with_ymd_and_hms(2025, 3, 2, 10, 30, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2025, 3, 4, 0, 0, 0).unwrap()),
+            ..ActivityFilter::default()
+        };
+        assert_eq!(titles(activities.find(user.id, &filter).await.unwrap()), vec!["Meeting", "Swim"]);
+        assert!(activities.find(other.id, &ActivityFilter::default()).await.unwrap().is_empty());
+
+        // foreign categories and activities are hidden
+        assert!(matches!(activities.create(user.id, activity("Hike", 5, Some(foreign.id), &[])).await, Err(ActivityError::Invalid(_))));
+        assert!(matches!(activities.find_by_id(other.id, run.id).await, Err(ActivityError::NotFound)));
+        assert!(matches!(activities.delete(other.id, run.id).await, Err(ActivityError::NotFound)));
+
+        // tags without activities disappear, deleted categories are removed from their activities
+        activities.update(user.id, run.id, activity("Run", 1, Some(sport.id), &["morning"])).await.unwrap();
+        let names = tags.find_by_user_id(user.id).await.unwrap().into_iter().map(|t| (t.name, t.activity_count)).collect::<Vec<_>>();
+        assert_eq!(names, vec![("indoor".to_owned(), 1), ("Morning".to_owned(), 2)]);
+
+        categories.delete(user.id, sport.id).await.unwrap();
+        assert_eq!(activities.find_by_id(user.id, run.id).await.unwrap().category_id, None);
+
+        activities.delete(user.id, run.id).await.unwrap();
+        assert_eq!(tags.find_by_user_id(user.id).await.unwrap().len(), 2);
+    }
+
+    /// Titles of all pages sorted by title
+    async fn paged_titles(activities: &ActivityService, user_id: i32, filter: &ActivityFilter) -> Vec<String> {
+        let mut titles = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = PageRequest { sort: ActivitySort::Title, order: SortOrder::Asc, cursor, limit: 2 };
+            let page = activities.find_page(user_id, filter, &request).await.unwrap();
+            titles.extend(page.items.into_iter().map(|activity| activity.title));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return titles,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn should_page_occurrences_in_the_order_of_single_activities() {
+        let db_config = Arc::new(DbConfig::new("file:activity_service_page_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = ActivityService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        activities.create(user.id, activity("éclair", 1, None, &[])).await.unwrap();
+        activities.create(user.id, activity("Éclair", 2, None, &[])).await.unwrap();
+        activities.create(user.id, activity("apple", 3, None, &[])).await.unwrap();
+        let mut walk = activity("Walk", 4, None, &[]);
+        walk.recurrence = Some("FREQ=DAILY;COUNT=3".to_owned());
+        activities.create(user.id, walk).await.unwrap();
+
+        // like `COLLATE NOCASE` only ASCII letters are folded, with and without the expansion
+        let all = paged_titles(&activities, user.id, &ActivityFilter::default()).await;
+        assert_eq!(all, vec!["apple", "Walk", "Éclair", "éclair"]);
+        let range = ActivityFilter {
+            from: Some(Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2025, 4, 1, 0, 0, 0).unwrap()),
+            ..ActivityFilter::default()
+        };
+        let expanded = paged_titles(&activities, user.id, &range).await;
+        assert_eq!(expanded, vec!["apple", "Walk", "Walk", "Walk", "Éclair", "éclair"]);
+        let recurring = ActivityFilter { recurring: Some(true), ..range.clone() };
+        assert_eq!(titles(activities.find(user.id, &recurring).await.unwrap()), vec!["Walk", "Walk", "Walk"]);
+    }
+
+    #[tokio::test]
+    async fn should_page_series_with_more_occurrences_than_expanded_at_once() {
+        let db_config = Arc::new(DbConfig::new("file:activity_service_long_series_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = ActivityService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let mut daily = activity("Walk", 1, None, &[]);
+        daily.start = Utc.with_ymd_and_hms(2020, 1, 1, 10, 0, 0).unwrap();
+        daily.end = None;
+        daily.recurrence = Some("FREQ=DAILY".to_owned());
+        activities.create(user.id, daily).await.unwrap();
+        let range = ActivityFilter {
+            from: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()),
+            ..ActivityFilter::default()
+        };
+
+        let newest = PageRequest { sort: ActivitySort::Start, order: SortOrder::Desc, cursor: None, limit: 2 };
+        let page = activities.find_page(user.id, &range, &newest).await.unwrap();
+        assert_eq!(page.items[0].start, Utc.with_ymd_and_hms(2025, 12, 31, 10, 0, 0).unwrap());
+
+        // 2192 days from 2020 to 2025
+        let mut starts = Vec::new();
+        let mut cursor = None;
+        loop {
+            let request = PageRequest { sort: ActivitySort::Start, order: SortOrder::Asc, cursor, limit: 200 };
+            let page = activities.find_page(user.id, &range, &request).await.unwrap();
+            starts.extend(page.items.into_iter().map(|activity| activity.start));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(starts.len(), 2192);
+        assert_eq!(starts.last(), Some(&Utc.with_ymd_and_hms(2025, 12, 31, 10, 0, 0).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn should_expand_occurrences_with_exceptions() {
+        let db_config = Arc::new(DbConfig::new("file:activity_service_recurrence_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = ActivityService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let at = |month: u32, day: u32, hour: u32| Utc.with_ymd_and_hms(2025, month, day, hour, 0, 0).unwrap();
+        let range = |from, to| ActivityFilter { from: Some(from), to: Some(to), ..ActivityFilter::default() };
+        let starts = |activities: Vec<crate::domain::activity::Activity>| activities.into_iter().map(|a| a.start).collect::<Vec<_>>();
+
+        // second tuesday and last friday of every other month
+        let mut monthly = activity("Review", 11, None, &[]);
+        monthly.recurrence = Some("RRULE:FREQ=MONTHLY;INTERVAL=2;BYDAY=2TU,-1FR;UNTIL=20250630".to_owned());
+        let review = activities.create(user.id, monthly).await.unwrap();
+        assert_eq!(review.recurrence.as_deref(), Some("FREQ=MONTHLY;INTERVAL=2;BYDAY=2TU,-1FR;UNTIL=20250630T235959Z"));
+        let all = activities.find(user.id, &range(at(1, 1, 0), at(12, 31, 0))).await.unwrap();
+        assert_eq!(starts(all), vec![at(5, 30, 10), at(5, 13, 10), at(3, 28, 10), at(3, 11, 10)]);
+
+        let mut invalid = activity("Daily", 1, None, &[]);
+        invalid.recurrence = Some("FREQ=DAILY;BYDAY=1MO".to_owned());
+        assert!(matches!(activities.create(user.id, invalid).await, Err(ActivityError::Invalid(_))));
+
+        let mut daily = activity("Stretch", 1, None, &[]);
+        daily.recurrence = Some("FREQ=DAILY;INTERVAL=3".to_owned());
+        let stretch = activities.create(user.id, daily.clone()).await.unwrap();
+
+        // overlapping occurrences from before the range are included
+        let listed = activities.find(user.id, &range(at(3, 7, 10) + chrono::Duration::minutes(30), at(3, 11, 0))).await.unwrap();
+        assert_eq!(starts(listed), vec![at(3, 10, 10), at(3, 7, 10)]);
+
+        let skip = NewActivityException { skipped: true, title: None, description: None, start: None, end: None };
+        activities.save_exception(user.id, stretch.id, at(3, 4, 10), skip.clone()).await.unwrap();
+        let moved = NewActivityException { skipped: false, title: Some("Yoga".to_owned()), description: None, start: Some(at(3, 7, 16)), end: Some(at(3, 7, 17)) };
+        activities.save_exception(user.id, stretch.id, at(3, 7, 10), moved).await.unwrap();
+        assert!(matches!(activities.save_exception(user.id, stretch.id, at(3, 5, 10), skip.clone()).await, Err(ActivityError::OccurrenceNotFound)));
+        let backwards = NewActivityException { skipped: false, title: None, description: None, start: None, end: Some(at(3, 10, 9)) };
+        assert!(matches!(activities.save_exception(user.id, stretch.id, at(3, 10, 10), backwards).await, Err(ActivityError::Invalid(_))));
+
+        let listed = activities.find(user.id, &range(at(3, 1, 0), at(3, 8, 0))).await.unwrap();
+        assert_eq!(listed.iter().map(|a| (a.title.as_str(), a.start, a.occurrence)).collect::<Vec<_>>(), vec![
+            ("Yoga", at(3, 7, 16), Some(at(3, 7, 10))),
+            ("Stretch", at(3, 1, 10), Some(at(3, 1, 10))),
+        ]);
+        assert_eq!(activities.find_exceptions(user.id, stretch.id).await.unwrap().len(), 2);
+
+        activities.delete_exception(user.id, stretch.id, at(3, 4, 10)).await.unwrap();
+        assert!(matches!(activities.delete_exception(user.id, stretch.id, at(3, 4, 10)).await, Err(ActivityError::OccurrenceNotFound)));
+
+        // without a range end series are listed once
+        assert_eq!(activities.find(user.id, &ActivityFilter::default()).await.unwrap().len(), 2);
+
+        // a changed rule drops the exceptions, a changed title keeps them
+        activities.update(user.id, stretch.id, NewActivity { title: "Stretching".to_owned(), ..daily.clone() }).await.unwrap();
+        assert_eq!(activities.find_exceptions(user.id, stretch.id).await.unwrap().len(), 1);
+        activities.update(user.id, stretch.id, NewActivity { recurrence: Some("FREQ=DAILY".to_owned()), ..daily }).await.unwrap();
+        assert!(activities.find_exceptions(user.id, stretch.id).await.unwrap().is_empty());
+
+        activities.save_exception(user.id, stretch.id, at(3, 2, 10), skip).await.unwrap();
+        activities.delete(user.id, stretch.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_search_own_activities_and_keep_index_in_sync() {
+        let db_config = Arc::new(DbConfig::new("file:activity_search_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let service = ActivityService::new(Arc::clone(&db_config));
+        let tags = TagService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+        let search = |text: &str| ActivitySearch::new(text, None).unwrap();
+
+        let mut notes = activity("Notes", 1, None, &[]);
+        notes.description = Some("Planned the <b>river</b> run & swim".to_owned());
+        service.create(user.id, notes).await.unwrap();
+        let run = service.create(user.id, activity("River run", 2, None, &["outdoor"])).await.unwrap();
+        service.create(other.id, activity("River run", 3, None, &[])).await.unwrap();
+
+        let hits = service.search(user.id, &search("riv")).await.unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.activity.title.as_str()).collect::<Vec<_>>(), vec!["River run", "Notes"]);
+        assert_eq!(hits[0].snippet, "<mark>River</mark> run");
+        assert_eq!(hits[1].snippet, "Planned the &lt;b&gt;<mark>river</mark>&lt;/b&gt; run &amp; swim");
+        assert_eq!(service.search(user.id, &search("swim, river!")).await.unwrap().len(), 1);
+        assert!(ActivitySearch::new(" -- ", None).is_err());
+        assert!(ActivitySearch::new("run", Some(0)).is_err());
+
+        service.update(user.id, run.id, activity("Lake swim", 2, None, &["outdoor"])).await.unwrap();
+        assert_eq!(service.search(user.id, &search("river")).await.unwrap().len(), 1);
+        assert_eq!(service.search(user.id, &search("lake")).await.unwrap().len(), 1);
+
+        let outdoor = tags.find_by_user_id(user.id).await.unwrap().into_iter().find(|tag| tag.name == "outdoor").unwrap();
+        tags.rename(user.id, outdoor.id, "nature").await.unwrap();
+        assert!(service.search(user.id, &search("outdoor")).await.unwrap().is_empty());
+        assert_eq!(service.search(user.id, &search("nature")).await.unwrap()[0].activity.id, run.id);
+
+        service.delete(user.id, run.id).await.unwrap();
+        assert!(service.search(user.id, &search("nature")).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::{str::FromStr, sync::Arc};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{types::Type, Connection, Row};
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::{api_token::{ApiToken, CreatedApiToken, TokenScope}, api_token_api::ApiTokenApi}, error::errors::ApiTokenError};
+
+const TOKEN_PREFIX: &str = "mat_";
+const TOKEN_COLUMNS: &str = "id, user_id, name, scopes, created_at, expires_at, last_used_at";
+
+pub struct ApiTokenService {
+    db_config: Arc<DbConfig>
+}
+
+impl ApiTokenService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+
+    fn generate_secret() -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        format!("{}{}", TOKEN_PREFIX, to_hex(&bytes))
+    }
+
+    /// Tokens have enough entropy, so a fast hash is sufficient and allows looking them up by hash
+    fn hash_secret(secret: &str) -> String {
+        to_hex(&Sha256::digest(secret.as_bytes()))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Maps a row selected with [TOKEN_COLUMNS]
+fn map_token(row: &Row) -> rusqlite::Result<ApiToken> {
+    let scopes: String = row.get(3)?;
+    let scopes = scopes.split(',')
+        .filter(|s| !s.is_empty())
+        .map(TokenScope::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+
+    Ok(ApiToken {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        scopes,
+        created_at: row.get(4)?,
+        expires_at: row.get(5)?,
+        last_used_at: row.get(6)?,
+    })
+}
+
+#[async_trait]
+impl ApiTokenApi for ApiTokenService {
+    async fn create_token(&self, user_id: i32, name: &str, scopes: Vec<TokenScope>, expires_at: DateTime<Utc>) -> Result<CreatedApiToken, ApiTokenError> {
+        let db = self.db_config.get_database().to_owned();
+        let secret = ApiTokenService::generate_secret();
+        let hash = ApiTokenService::hash_secret(&secret);
+        let owned_name = name.to_owned();
+        let scopes = scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",");
+
+        let token = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO api_tokens (user_id, name, token_hash, scopes, created_at, expires_at) values (?1, ?2, ?3, ?4, ?5, ?6)",
+                (user_id, owned_name, hash, scopes, Utc::now(), expires_at)
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok::<ApiToken, ApiTokenError>(conn.query_row(&format!("SELECT {} FROM api_tokens WHERE id = ?1", TOKEN_COLUMNS), [id], map_token)?)
+        }).await??;
+
+        Ok(CreatedApiToken {
+            token,
+            secret,
+        })
+    }
+
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<ApiToken>, ApiTokenError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM api_tokens WHERE user_id = ?1 ORDER BY id", TOKEN_COLUMNS))?;
+            let tokens = stmt.query_map([user_id], map_token)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(tokens)
+        }).await?
+    }
+
+    async fn revoke(&self, user_id: i32, token_id: i32) -> Result<(), ApiTokenError> {
+        let db = self.db_config.get_database().to_owned();
+        let deleted = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<usize, ApiTokenError>(conn.execute("DELETE FROM api_tokens WHERE id = ?1 AND user_id = ?2", (token_id, user_id))?)
+        }).await??;
+
+        if deleted == 0 {
+            Err(ApiTokenError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn authenticate(&self, secret: &str) -> Result<ApiToken, ApiTokenError> {
+        if !secret.starts_with(TOKEN_PREFIX) {
+            return Err(ApiTokenError::NotFound);
+        }
+
+        let db = self.db_config.get_database().to_owned();
+        let hash = ApiTokenService::hash_secret(secret);
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut token = conn.query_row(&format!("SELECT {} FROM api_tokens WHERE token_hash = ?1", TOKEN_COLUMNS), [hash], map_token)?;
+            if token.is_expired() {
+                return Err(ApiTokenError::Expired);
+            }
+
+            let now = Utc::now();
+            conn.execute("UPDATE api_tokens SET last_used_at = ?1 WHERE id = ?2", (now, token.id))?;
+            token.last_used_at = Some(now);
+
+            Ok(token)
+        }).await?
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{api_token::TokenScope, api_token_api::ApiTokenApi, user::User, user_api::UserApi}, error::errors::ApiTokenError, service::user_service::UserService};
+
+    use super::ApiTokenService;
+
+    #[tokio::test]
+    async fn should_authenticate_with_secret_until_revoked() {
+        let db_config = Arc::new(DbConfig::new("file:api_token_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let token_service = ApiTokenService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let created = token_service.create_token(user.id, "script", vec![TokenScope::Read], Utc::now() + Duration::days(1)).await.unwrap();
+        let authenticated = token_service.authenticate(&created.secret).await.unwrap();
+        token_service.revoke(user.id, created.token.id).await.unwrap();
+
+        assert_eq!(authenticated.user_id, user.id);
+        assert!(authenticated.last_used_at.is_some());
+        assert!(!authenticated.has_scope(TokenScope::Write));
+        assert!(matches!(token_service.authenticate(&created.secret).await, Err(ApiTokenError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_reject_expired_token() {
+        let db_config = Arc::new(DbConfig::new("file:api_token_service_expired_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let token_service = ApiTokenService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let created = token_service.create_token(user.id, "old", vec![TokenScope::Write], Utc::now() - Duration::seconds(1)).await.unwrap();
+
+        assert!(matches!(token_service.authenticate(&created.secret).await, Err(ApiTokenError::Expired)));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{params_from_iter, types::Type, Connection, Row, ToSql};
+
+use crate::{config::db::DbConfig, domain::{audit_api::AuditApi, audit_event::{AuditEvent, AuditEventType, AuditFilter, AuditOutcome, NewAuditEvent}}, error::errors::AuditError};
+
+const EVENT_COLUMNS: &str = "id, user_id, event_type, outcome, ip, user_agent, detail, created_at";
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+pub struct AuditService {
+    db_config: Arc<DbConfig>
+}
+
+impl AuditService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+/// Records the event and only logs failures. A broken audit log must not block logins.
+pub async fn record_event(audit_api: &dyn AuditApi, event: NewAuditEvent) {
+    let event_type = event.event_type;
+    if let Err(e) = audit_api.record(event).await {
+        log::error!("Cannot record audit event '{}': {}", event_type.as_str(), e);
+    }
+}
+
+/// Maps a row selected with [EVENT_COLUMNS]
+fn map_event(row: &Row) -> rusqlite::Result<AuditEvent> {
+    let event_type: String = row.get(2)?;
+    let event_type = AuditEventType::from_str(&event_type)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, e.into()))?;
+    let outcome: String = row.get(3)?;
+    let outcome = AuditOutcome::from_str(&outcome)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+
+    Ok(AuditEvent {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        event_type,
+        outcome,
+        ip: row.get(4)?,
+        user_agent: row.get(5)?,
+        detail: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+#[async_trait]
+impl AuditApi for AuditService {
+    async fn record(&self, event: NewAuditEvent) -> Result<(), AuditError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO audit_events (user_id, event_type, outcome, ip, user_agent, detail, created_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                (event.user_id, event.event_type.as_str(), event.outcome.as_str(), event.ip, event.user_agent, event.detail, Utc::now())
+            )?;
+            Ok(())
+        }).await?
+    }
+
+    async fn find(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>, AuditError> {
+        let mut conditions = Vec::new();
+        let mut params: Vec<Box<dyn ToSql + Send>> = Vec::new();
+        if let Some(user_id) = filter.user_id {
+            params.push(Box::new(user_id));
+            conditions.push(format!("user_id = ?{}", params.len()));
+        }
+        if let Some(event_type) = filter.event_type {
+            params.push(Box::new(event_type.as_str()));
+            conditions.push(format!("event_type = ?{}", params.len()));
+        }
+        if let Some(outcome) = filter.outcome {
+            params.push(Box::new(outcome.as_str()));
+            conditions.push(format!("outcome = ?{}", params.len()));
+        }
+        // bound in the format rusqlite stores `created_at` with, so they compare as text
+        if let Some(from) = filter.from {
+            params.push(Box::new(from));
+            conditions.push(format!("created_at >= ?{}", params.len()));
+        }
+        if let Some(to) = filter.to {
+            params.push(Box::new(to));
+            conditions.push(format!("created_at < ?{}", params.len()));
+        }
+
+        let mut sql = format!("SELECT {} FROM audit_events", EVENT_COLUMNS);
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        sql.push_str(&format!(" ORDER BY id DESC LIMIT {}", limit));
+
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&sql)?;
+            let events = stmt.query_map(params_from_iter(params), map_event)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(events)
+        }).await?
+    }
+
+    async fn is_new_device(&self, user_id: i32, user_agent: Option<&str>) -> Result<bool, AuditError> {
+        let db = self.db_config.get_database().to_owned();
+        let user_agent = user_agent.map(|ua| ua.to_owned());
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            // IS also matches two unknown user agents
+            let (logins, same_device) = conn.query_row(
+                "SELECT COUNT(*), COUNT(CASE WHEN user_agent IS ?2 THEN 1 END) FROM audit_events
+                WHERE user_id = ?1 AND event_type IN ('login', 'mfa') AND outcome = 'success'",
+                (user_id, user_agent),
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            )?;
+
+            Ok(logins > 0 && same_device == 0)
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditFilter, AuditOutcome, NewAuditEvent}}};
+
+    use super::AuditService;
+
+    #[tokio::test]
+    async fn should_filter_events_and_reject_changes() {
+        let db_config = Arc::new(DbConfig::new("file:audit_service_test?mode=memory&cache=shared"));
+        let db = create_db(&db_config);
+        let audit = AuditService::new(Arc::clone(&db_config));
+
+        audit.record(NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure).detail("unknown@example.org")).await.unwrap();
+        audit.record(NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure).user(1)).await.unwrap();
+        audit.record(NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Success).user(1)).await.unwrap();
+        audit.record(NewAuditEvent::new(AuditEventType::Logout, AuditOutcome::Success).user(1)).await.unwrap();
+
+        let own = audit.find(&AuditFilter { user_id: Some(1), ..Default::default() }).await.unwrap();
+        let failed_logins = audit.find(&AuditFilter { event_type: Some(AuditEventType::Login), outcome: Some(AuditOutcome::Failure), ..Default::default() }).await.unwrap();
+        let latest = audit.find(&AuditFilter { limit: Some(1), ..Default::default() }).await.unwrap();
+
+        assert_eq!(own.len(), 3);
+        assert_eq!(failed_logins.len(), 2);
+        assert_eq!(failed_logins[1].detail.as_deref(), Some("unknown@example.org"));
+        assert_eq!(latest[0].event_type, AuditEventType::Logout);
+        assert!(db.execute("DELETE FROM audit_events", []).is_err());
+    }
+
+    #[tokio::test]
+    async fn should_include_whole_days_of_the_time_range() {
+        let db_config = Arc::new(DbConfig::new("file:audit_service_range_test?mode=memory&cache=shared"));
+        let db = create_db(&db_config);
+        let audit = AuditService::new(Arc::clone(&db_config));
+        let day = Utc.with_ymd_and_hms(2025, 3, 1, 0, 0, 0).unwrap();
+        for (detail, created_at) in [("before", day - Duration::seconds(1)), ("start", day), ("noon", day + Duration::hours(12)), ("end", day + Duration::days(1))] {
+            db.execute("INSERT INTO audit_events (event_type, outcome, detail, created_at) values ('login', 'success', ?1, ?2)", (detail, created_at)).unwrap();
+        }
+
+        let events = audit.find(&AuditFilter { from: Some(day), to: Some(day + Duration::days(1)), ..Default::default() }).await.unwrap();
+
+        let details: Vec<_> = events.iter().map(|e| e.detail.as_deref().unwrap()).collect();
+        assert_eq!(details, vec!["noon", "start"]);
+    }
+}
@@ -1,44 +1,255 @@
-use std::sync::Arc;
+use std::{cell::RefCell, future::Future, sync::Arc, time::{Duration, SystemTime}};
 
-use actix_web::HttpRequest;
+use actix_session::{SessionExt, SessionInsertError};
+use actix_web::{HttpRequest, HttpResponseBuilder};
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use async_trait::async_trait;
-use authfix::{login::LoadUserByCredentials, multifactor::config::{HandleMfaRequest, MfaError}};
-use crate::{domain::{auth_api::AuthenticationApi, user::User, user_api::UserApi}, error::errors::QueryUserError};
+use authfix::{login::{FailureHandler, HandlerError, LoadUserByCredentials, SuccessHandler}, multifactor::config::{HandleMfaRequest, MfaConfig, MfaError}};
+use crate::{domain::{audit_api::AuditApi, audit_event::{AuditEventType, AuditOutcome, NewAuditEvent}, auth_api::AuthenticationApi, notification_api::NotificationApi, security_notification::SecurityNotification, user::User, user_api::UserApi}, error::errors::QueryUserError, service::{audit_service::record_event, notification_service::send_notification, trusted_device_service::TrustedDeviceCookie}};
+
+/// Key under which authfix's `SessionAuthProvider` reads the logged in user from the session.
+/// Needed where a user is authenticated outside of authfix's login flow. authfix keeps the key private,
+/// a test checks that it still matches.
+pub const AUTHFIX_SESSION_KEY_USER: &str = "authfix__user";
+/// Keys of authfix's MFA-pending state, see [ExternalLogin]
+const AUTHFIX_SESSION_KEY_NEED_MFA: &str = "authfix__needs_mfa";
+const AUTHFIX_SESSION_KEY_LOGIN_VALID_UNTIL: &str = "authfix__login_valid_until";
+/// Path of authfix's route that checks the second factor
+const MFA_ROUTE: &str = "/api/login/mfa";
 
 pub struct AuthenticationService<U: UserApi> {
-    user_api: Arc<U>
+    user_api: Arc<U>,
+    audit_api: Option<Arc<dyn AuditApi>>,
+    notification_api: Option<Arc<dyn NotificationApi>>,
 }
 
 impl<U: UserApi> AuthenticationService<U> {
     pub fn new(user_api: Arc<U>) -> Self {
         AuthenticationService {
             user_api,
+            audit_api: None,
+            notification_api: None,
+        }
+    }
+
+    /// Records logins and failed logins through the handlers of this service
+    pub fn with_audit_api(mut self, audit_api: Arc<dyn AuditApi>) -> Self {
+        self.audit_api = Some(audit_api);
+        self
+    }
+
+    /// Mails the user after logins from new devices, needs the audit log to recognize known devices
+    pub fn with_notification_api(mut self, notification_api: Arc<dyn NotificationApi>) -> Self {
+        self.notification_api = Some(notification_api);
+        self
+    }
+
+    /// Creates the [LoginFailureHandler] that reports database failures of this service as server errors
+    pub fn failure_handler(&self) -> LoginFailureHandler {
+        LoginFailureHandler {
+            audit_api: self.audit_api.clone(),
+        }
+    }
+
+    /// Creates the [LoginSuccessHandler]. authfix takes a single success handler, so `next` is called first.
+    pub fn success_handler<N: SuccessHandler<User = User>>(&self, next: N) -> LoginSuccessHandler<N> {
+        LoginSuccessHandler {
+            audit_api: self.audit_api.clone(),
+            notification_api: self.notification_api.clone(),
+            next,
+        }
+    }
+
+    fn outage(&self, e: QueryUserError) -> authfix::login::LoadUserError {
+        log::error!("Login not possible: {}", e);
+        set_login_failure(LoginFailure::Outage(e));
+        authfix::login::LoadUserError::LoginFailed
+    }
+
+    fn rejected(&self, user_id: Option<i32>, detail: String) -> authfix::login::LoadUserError {
+        set_login_failure(LoginFailure::Rejected { user_id, detail });
+        authfix::login::LoadUserError::LoginFailed
+    }
+}
+
+enum LoginFailure {
+    /// The database failed, the login is answered with a server error
+    Outage(QueryUserError),
+    /// Wrong credentials or a locked account
+    Rejected { user_id: Option<i32>, detail: String },
+}
+
+tokio::task_local! {
+    /// Reason of a failed `load_user` in the login request being handled
+    static LOGIN_FAILURE: RefCell<Option<LoginFailure>>;
+}
+
+/// Runs a login request with its own slot for the reason of a failed `load_user`.
+///
+/// authfix's `LoadUserError` can only express wrong credentials and `load_user` gets no request,
+/// so the reason reaches the [LoginFailureHandler] through this slot. It lives as long as the
+/// request's future, concurrent logins on the same worker never see each other's failures.
+pub async fn with_login_failure_slot<F: Future>(login: F) -> F::Output {
+    LOGIN_FAILURE.scope(RefCell::new(None), login).await
+}
+
+fn set_login_failure(failure: LoginFailure) {
+    if LOGIN_FAILURE.try_with(|slot| *slot.borrow_mut() = Some(failure)).is_err() {
+        log::warn!("Login outside of with_login_failure_slot, the failure is reported as wrong credentials");
+    }
+}
+
+fn take_login_failure() -> Option<LoginFailure> {
+    LOGIN_FAILURE.try_with(|slot| slot.borrow_mut().take()).ok().flatten()
+}
+
+/// Records failed logins and second factors and turns failed logins caused by database failures into a 500 instead of a 401
+pub struct LoginFailureHandler {
+    audit_api: Option<Arc<dyn AuditApi>>,
+}
+
+#[async_trait(?Send)]
+impl FailureHandler for LoginFailureHandler {
+    async fn on_failure(&self, req: HttpRequest) -> Result<(), HandlerError> {
+        let failure = take_login_failure();
+
+        if let Some(audit_api) = &self.audit_api {
+            let event = match &failure {
+                Some(LoginFailure::Rejected { user_id, detail }) => {
+                    let mut event = NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure).detail(detail);
+                    event.user_id = *user_id;
+                    event
+                },
+                Some(LoginFailure::Outage(_)) => NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure).detail("database failure"),
+                // without a failure of load_user the second factor was rejected, authfix keeps the user in the session
+                None => {
+                    let event = NewAuditEvent::new(AuditEventType::Mfa, AuditOutcome::Failure);
+                    match req.get_session().get::<User>(AUTHFIX_SESSION_KEY_USER) {
+                        Ok(Some(user)) => event.user(user.id),
+                        _ => event,
+                    }
+                },
+            };
+            record_event(audit_api.as_ref(), event.request(&req)).await;
+        }
+
+        match failure {
+            Some(LoginFailure::Outage(e)) => Err(HandlerError::Unexpected(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Records successful logins and reports new devices.
+/// Called for password logins without a second factor and after the second factor.
+pub struct LoginSuccessHandler<N> {
+    audit_api: Option<Arc<dyn AuditApi>>,
+    notification_api: Option<Arc<dyn NotificationApi>>,
+    next: N,
+}
+
+#[async_trait(?Send)]
+impl<N: SuccessHandler<User = User>> SuccessHandler for LoginSuccessHandler<N> {
+    type User = User;
+
+    async fn on_success(&self, user: &User, req: HttpRequest) -> Result<(), HandlerError> {
+        self.next.on_success(user, req.clone()).await?;
+
+        if let Some(audit_api) = &self.audit_api {
+            let event_type = if req.path() == MFA_ROUTE { AuditEventType::Mfa } else { AuditEventType::Login };
+            let event = NewAuditEvent::new(event_type, AuditOutcome::Success).user(user.id).request(&req);
+
+            let new_device = match audit_api.is_new_device(user.id, event.user_agent.as_deref()).await {
+                Ok(new_device) => new_device,
+                Err(e) => {
+                    log::error!("Cannot check for a new device of user {}: {}", user.id, e);
+                    false
+                },
+            };
+            let notification = SecurityNotification::NewDeviceLogin { user_agent: event.user_agent.clone(), ip: event.ip.clone() };
+            record_event(audit_api.as_ref(), event).await;
+
+            if let (true, Some(notification_api)) = (new_device, &self.notification_api) {
+                send_notification(notification_api.as_ref(), user, notification).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Result of [ExternalLogin::login]
+pub enum ExternalLoginState {
+    Authenticated,
+    /// The client has to send the code of this factor to authfix's MFA route
+    MfaNeeded(String),
+}
+
+fn insert_error(e: SessionInsertError) -> HandlerError {
+    HandlerError::Unexpected(format!("Cannot write login session: {}", e))
+}
+
+/// Logs in users that were authenticated outside of authfix's login flow, e.g. by an OIDC provider.
+///
+/// authfix offers no API for this, so its password login is mirrored on its session keys: the second factor,
+/// trusted devices, the MFA policy and the audit log apply in the same way.
+pub struct ExternalLogin {
+    mfa_config: MfaConfig<User>,
+    success_handler: Box<dyn SuccessHandler<User = User>>,
+}
+
+impl ExternalLogin {
+    /// Takes the same MFA config and success handler as the `SessionLoginAppBuilder`
+    pub fn new(mfa_config: MfaConfig<User>, success_handler: impl SuccessHandler<User = User> + 'static) -> Self {
+        Self {
+            mfa_config,
+            success_handler: Box::new(success_handler),
         }
     }
+
+    pub async fn login(&self, user: &User, req: &HttpRequest) -> Result<ExternalLoginState, HandlerError> {
+        let session = req.get_session();
+        session.renew();
+        session.clear();
+
+        if !(self.mfa_config.is_configured() && self.mfa_config.is_condition_met(user, req.clone()).await) {
+            self.success_handler.on_success(user, req.clone()).await?;
+            session.insert(AUTHFIX_SESSION_KEY_USER, user).map_err(insert_error)?;
+            return Ok(ExternalLoginState::Authenticated);
+        }
+
+        let valid_until = SystemTime::now().checked_add(Duration::from_secs(self.mfa_config.timeout_in_seconds()))
+            .ok_or_else(|| HandlerError::Unexpected("Cannot create login session timeout".to_owned()))?;
+        session.insert(AUTHFIX_SESSION_KEY_LOGIN_VALID_UNTIL, valid_until).map_err(insert_error)?;
+        // factors read the user from the session to generate their code
+        session.insert(AUTHFIX_SESSION_KEY_USER, user).map_err(insert_error)?;
+
+        let challenge = match self.mfa_config.factor_by_user(user).await {
+            Some(factor) => factor.generate_code(req).await
+                .map(|_| factor.unique_id())
+                .map_err(|e| HandlerError::Unexpected(e.to_string())),
+            None => Err(HandlerError::Unexpected(format!("No factor found for user {}", user.id))),
+        };
+        let mfa_id = challenge.inspect_err(|_| session.purge())?;
+        session.insert(AUTHFIX_SESSION_KEY_NEED_MFA, &mfa_id).map_err(insert_error)?;
+
+        Ok(ExternalLoginState::MfaNeeded(mfa_id))
+    }
 }
 
 #[async_trait]
 impl<U: UserApi> AuthenticationApi for AuthenticationService<U> {
-    async fn is_password_correct(&self, user: &User, password: &str) -> bool {
-        println!("Check if password correct!");
-        match self.user_api.find_credentials_by_user_id(user.id).await {
-            Ok(credentials) => {
-                let argon2 = Argon2::default();
-                match PasswordHash::new(&credentials.password)  {
-                    Ok(hash) => {
-                        match argon2.verify_password(password.as_bytes(), &hash) {
-                            Ok(_) => true,
-                            Err(_) => false,
-                        }
-                    },
-                    Err(_) => {
-                        log::error!("Could not create PasswordHash from credentials");
-                        false
-                    },
-                }
+    async fn is_password_correct(&self, user: &User, password: &str) -> Result<bool, QueryUserError> {
+        let credentials = self.user_api.find_credentials_by_user_id(user.id).await?;
+        let argon2 = Argon2::default();
+        match PasswordHash::new(&credentials.password)  {
+            Ok(hash) => {
+                Ok(argon2.verify_password(password.as_bytes(), &hash).is_ok())
+            },
+            Err(_) => {
+                log::error!("Could not create PasswordHash from credentials");
+                Ok(false)
             },
-            Err(_) => false,
         }
     }
 }
@@ -55,13 +266,23 @@ impl<U: UserApi> LoadUserByCredentials for AuthenticationService<U> {
         
         match self.user_api.find_by_email(&email).await {
             Ok(user) => {
-                if self.is_password_correct(&user, &password).await {
-                    Ok(user)
-                } else {
-                    Err(authfix::login::LoadUserError::LoginFailed)
+                match self.is_password_correct(&user, &password).await {
+                    Ok(true) if user.locked => Err(self.rejected(Some(user.id), "account locked".to_owned())),
+                    Ok(true) => {
+                        // a failed upgrade must not block the login, the old hash stays valid
+                        match self.user_api.upgrade_password_hash(user.id, &password).await {
+                            Ok(true) => log::info!("Password hash of user {} upgraded to the current parameters", user.id),
+                            Ok(false) => {},
+                            Err(e) => log::warn!("Cannot upgrade password hash of user {}: {}", user.id, e),
+                        }
+                        Ok(user)
+                    },
+                    Ok(false) => Err(self.rejected(Some(user.id), "wrong password".to_owned())),
+                    Err(e) => Err(self.outage(e)),
                 }
             },
-            Err(_) => Err(authfix::login::LoadUserError::LoginFailed),
+            Err(QueryUserError::NotFound) => Err(self.rejected(None, format!("unknown email address {}", email))),
+            Err(e) => Err(self.outage(e)),
         }
     }
 }
@@ -69,12 +290,29 @@ impl<U: UserApi> LoadUserByCredentials for AuthenticationService<U> {
 
 pub struct HandleMfaRequestImpl<S> {
     user_api: Arc<S>,
+    trusted_device: Option<TrustedDeviceCookie>,
+    audit_api: Option<Arc<dyn AuditApi>>,
 }
 
 impl<S: UserApi> HandleMfaRequestImpl<S> {
-    pub fn new(user_api: Arc<S>) -> Self {
+    /// Without `trusted_device` the second factor is required on every login
+    pub fn new(user_api: Arc<S>, trusted_device: Option<TrustedDeviceCookie>) -> Self {
         Self {
             user_api,
+            trusted_device,
+            audit_api: None,
+        }
+    }
+
+    /// Records when a device becomes trusted and when it skips the second factor
+    pub fn with_audit_api(mut self, audit_api: Arc<dyn AuditApi>) -> Self {
+        self.audit_api = Some(audit_api);
+        self
+    }
+
+    async fn record(&self, event: NewAuditEvent) {
+        if let Some(audit_api) = &self.audit_api {
+            record_event(audit_api.as_ref(), event).await;
         }
     }
 }
@@ -92,12 +330,42 @@ impl<S: UserApi> HandleMfaRequest for HandleMfaRequestImpl<S> {
         }
     }
 
-    #[allow(unused)]
     async fn is_condition_met(&self, user: &Self::User, req: HttpRequest) -> bool {
         match self.user_api.find_credentials_by_user_id(user.id).await {
-            Ok(creds) => creds.mfa_config.is_some(),
-            Err(_) => false,
+            Ok(creds) if creds.mfa_config.is_none() => false,
+            Ok(_) => match &self.trusted_device {
+                Some(trusted_device) if trusted_device.is_trusted(user, &req).await => {
+                    self.record(NewAuditEvent::new(AuditEventType::TrustedDevice, AuditOutcome::Success)
+                        .user(user.id).request(&req).detail("second factor skipped")).await;
+                    false
+                },
+                _ => true,
+            },
+            Err(QueryUserError::NotFound) => false,
+            Err(e) => {
+                // Never skip the second factor because of a database failure.
+                // mfa_id_by_user will fail as well and abort the login.
+                log::error!("Cannot check if MFA is required: {}", e);
+                true
+            },
+        }
+    }
+
+    /// Called after the second factor was checked, marks the browser as trusted
+    async fn handle_success(&self, user: &Self::User, mut res: HttpResponseBuilder) -> HttpResponseBuilder {
+        if let Some(trusted_device) = &self.trusted_device {
+            match trusted_device.issue(user).await {
+                Ok(cookie) => {
+                    res.cookie(cookie);
+                    // authfix does not pass the request here, the next login from this device records it
+                    self.record(NewAuditEvent::new(AuditEventType::TrustedDevice, AuditOutcome::Success)
+                        .user(user.id).detail("device trusted")).await;
+                },
+                Err(e) => log::error!("Cannot trust device of user {}: {}", user.id, e),
+            }
         }
+
+        res
     }
 }
 
@@ -112,9 +380,13 @@ impl From<QueryUserError> for MfaError {
 mod tests {
     use std::sync::Arc;
 
-    use crate::{config::db::DbConfig, create_db, domain::{auth_api::AuthenticationApi, user::User, user_api::UserApi}, service::user_service::UserService};
+    use actix_session::Session;
+    use actix_web::{cookie::Key, http::StatusCode, test, web, HttpResponse};
+    use authfix::{login::{LoadUserByCredentials, LoginToken}, session::{app_builder::SessionLoginAppBuilder, config::Routes}, AuthToken};
 
-    use super::AuthenticationService;
+    use crate::{app_factory::create_test_session_middleware, config::db::DbConfig, create_db, domain::{auth_api::AuthenticationApi, user::User, user_api::UserApi}, service::user_service::UserService};
+
+    use super::{take_login_failure, AUTHFIX_SESSION_KEY_USER, with_login_failure_slot, AuthenticationService, LoginFailure};
 
 
     #[tokio::test]
@@ -128,7 +400,7 @@ mod tests {
         let saved_user = user_service.save_user_with_credentials(user, "test123").await.unwrap();
 
         // Act & Assert 
-        assert!(auth.is_password_correct(&saved_user, "test123").await, "The password should match");
+        assert!(auth.is_password_correct(&saved_user, "test123").await.unwrap(), "The password should match");
     }
 
     #[tokio::test]
@@ -140,7 +412,57 @@ mod tests {
         let user = User::new(0, "test@example.org".to_owned(), "Hans".to_owned());
         let saved_user = user_service.save_user_with_credentials(user, "test123").await.unwrap();
 
-        assert!(!auth.is_password_correct(&saved_user, "some123").await, "Password is not correct. This should return false");
+        assert!(!auth.is_password_correct(&saved_user, "some123").await.unwrap(), "Password is not correct. This should return false");
+    }
+
+    #[tokio::test]
+    async fn should_keep_failure_reasons_of_concurrent_logins_apart() {
+        let db_config = DbConfig::new("file:auth_service_login_failure?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = Arc::new(UserService::new(Arc::new(db_config)));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let auth = AuthenticationService::new(Arc::clone(&user_service));
+
+        let login = |email: &str, password: &str| {
+            let auth = &auth;
+            let token = LoginToken { email: email.to_owned(), password: password.to_owned() };
+            with_login_failure_slot(async move {
+                let result = auth.load_user(&token).await;
+                // let the other login run between load_user and the failure handler
+                tokio::task::yield_now().await;
+                (result.is_err(), take_login_failure())
+            })
+        };
+        let (wrong_password, unknown_email) = tokio::join!(login("test@example.org", "wrong"), login("nobody@example.org", "test123"));
+
+        assert!(matches!(wrong_password, (true, Some(LoginFailure::Rejected { user_id: Some(id), .. })) if id == user.id));
+        assert!(matches!(unknown_email, (true, Some(LoginFailure::Rejected { user_id: None, .. }))));
+        assert!(take_login_failure().is_none());
     }
 
-}
\ No newline at end of file
+    /// Bearer tokens, OIDC and the email change write the user under authfix's private session key
+    #[actix_web::test]
+    async fn authfix_should_read_the_user_from_the_copied_session_key() {
+        let user_service = Arc::new(UserService::new(Arc::new(DbConfig::new("file:auth_service_session_key?mode=memory&cache=shared"))));
+        let app = test::init_service(
+            SessionLoginAppBuilder::create_with_session_middleware(AuthenticationService::new(user_service), create_test_session_middleware(Key::generate()))
+                .set_login_routes_and_public_paths(Routes::default(), vec!["/sign-in"])
+                .build()
+                .route("/sign-in", web::post().to(|session: Session| async move {
+                    session.insert(AUTHFIX_SESSION_KEY_USER, User::new(7, "test@example.org".to_owned(), "Hans".to_owned())).unwrap();
+                    HttpResponse::Ok().finish()
+                }))
+                .route("/me", web::get().to(|token: AuthToken<User>| async move {
+                    HttpResponse::Ok().body(token.authenticated_user().email.clone())
+                }))
+        ).await;
+
+        let res = test::call_service(&app, test::TestRequest::post().uri("/sign-in").to_request()).await;
+        let session = res.response().cookies().find(|c| c.name() == "sessionId").unwrap().into_owned();
+
+        let req = test::TestRequest::get().uri("/me").cookie(session).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "test@example.org");
+    }
+}
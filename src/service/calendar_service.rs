@@ -0,0 +1,697 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::{activity::{Activity, ActivityException, ActivityFilter, NewActivity}, activity_api::ActivityApi, activity_import::{ImportIssue, ImportSummary, ImportedActivity}, calendar_api::CalendarApi, recurrence::local_to_utc}, error::errors::CalendarError};
+
+const PRODUCT_ID: &str = "-//MyActivities//Activities//EN";
+const UID_DOMAIN: &str = "myactivities";
+/// Content lines are folded after this many octets, see RFC 5545 section 3.1
+const MAX_LINE_LENGTH: usize = 75;
+/// Years after the latest activity of a time zone that its `VTIMEZONE` lists the transitions of
+const TIME_ZONE_YEARS: i32 = 10;
+
+pub struct CalendarService {
+    db_config: Arc<DbConfig>,
+    activity_api: Arc<dyn ActivityApi>,
+}
+
+impl CalendarService {
+    pub fn new(db_config: Arc<DbConfig>, activity_api: Arc<dyn ActivityApi>) -> Self {
+        Self {
+            db_config,
+            activity_api,
+        }
+    }
+
+    /// Secrets have enough entropy, so a fast hash is sufficient and allows looking them up by hash
+    fn hash_secret(secret: &str) -> String {
+        format!("{:x}", Sha256::digest(secret.as_bytes()))
+    }
+}
+
+/// Identifies the activity in calendars and exchange files, it becomes the UID when they are imported again
+pub(crate) fn activity_uid(activity_id: i32) -> String {
+    format!("activity-{}@{}", activity_id, UID_DOMAIN)
+}
+
+/// Timestamps and the times of activities without time zone are written in UTC
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// `NAME;TZID=zone:local time` so that clients expand the recurrence rule in the zone of the activity
+fn zoned_property(name: &str, times: &[DateTime<Utc>], zone: Tz) -> String {
+    if zone == Tz::UTC {
+        let times = times.iter().map(|time| format_time(*time)).collect::<Vec<_>>();
+        return format!("{}:{}", name, times.join(","));
+    }
+
+    let times = times.iter()
+        .map(|time| time.with_timezone(&zone).format("%Y%m%dT%H%M%S").to_string())
+        .collect::<Vec<_>>();
+    format!("{};TZID={}:{}", name, zone.name(), times.join(","))
+}
+
+/// `+hhmm`, seconds are only written when there are any
+fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    match seconds % 60 {
+        0 => format!("{}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60),
+        rest => format!("{}{:02}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60, rest),
+    }
+}
+
+/// The first second with a different offset or abbreviation than `before`
+fn find_transition(zone: Tz, mut before: DateTime<Utc>, mut after: DateTime<Utc>) -> DateTime<Utc> {
+    let offset = zone.offset_from_utc_datetime(&before.naive_utc());
+    while after - before > Duration::seconds(1) {
+        let middle = before + (after - before) / 2;
+        if zone.offset_from_utc_datetime(&middle.naive_utc()) == offset {
+            before = middle;
+        } else {
+            after = middle;
+        }
+    }
+    after
+}
+
+/// Appends a `STANDARD` or `DAYLIGHT` component that starts at `time`
+fn push_observance(calendar: &mut String, zone: Tz, time: DateTime<Utc>, offset_from: i32) {
+    let offset = zone.offset_from_utc_datetime(&time.naive_utc());
+    let kind = if offset.dst_offset().is_zero() { "STANDARD" } else { "DAYLIGHT" };
+    let local = time.naive_utc() + Duration::seconds(offset_from.into());
+    push_line(calendar, &format!("BEGIN:{}", kind));
+    push_line(calendar, &format!("DTSTART:{}", local.format("%Y%m%dT%H%M%S")));
+    push_line(calendar, &format!("TZOFFSETFROM:{}", format_offset(offset_from)));
+    push_line(calendar, &format!("TZOFFSETTO:{}", format_offset(offset.fix().local_minus_utc())));
+    if let Some(name) = offset.abbreviation() {
+        push_line(calendar, &format!("TZNAME:{}", escape_text(name)));
+    }
+    push_line(calendar, &format!("END:{}", kind));
+}
+
+/// Lists the transitions of the zone between the first year and some years after the last year,
+/// clients keep the last offset for later times
+fn push_time_zone(calendar: &mut String, zone: Tz, first_year: i32, last_year: i32) {
+    let year_start = |year: i32| Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).single();
+    let (Some(start), Some(end)) = (year_start(first_year), year_start(last_year + TIME_ZONE_YEARS)) else {
+        return;
+    };
+
+    push_line(calendar, "BEGIN:VTIMEZONE");
+    push_line(calendar, &format!("TZID:{}", zone.name()));
+    let mut offset = zone.offset_from_utc_datetime(&start.naive_utc());
+    push_observance(calendar, zone, start, offset.fix().local_minus_utc());
+
+    let mut day = start;
+    while day < end {
+        let next_day = day + Duration::days(1);
+        if zone.offset_from_utc_datetime(&next_day.naive_utc()) != offset {
+            let transition = find_transition(zone, day, next_day);
+            push_observance(calendar, zone, transition, offset.fix().local_minus_utc());
+            offset = zone.offset_from_utc_datetime(&transition.naive_utc());
+        } else {
+            day = next_day;
+        }
+    }
+    push_line(calendar, "END:VTIMEZONE");
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Appends a content line, long lines are folded without splitting characters
+fn push_line(calendar: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            calendar.push_str("\r\n ");
+            length = 1;
+        }
+        calendar.push(c);
+        length += c.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+/// A single activity, the series of a recurring activity or a changed occurrence of it
+fn push_event(calendar: &mut String, activity: &Activity, recurrence_id: Option<DateTime<Utc>>, exdates: &[DateTime<Utc>]) {
+    push_line(calendar, "BEGIN:VEVENT");
+    push_line(calendar, &format!("UID:{}", activity_uid(activity.id)));
+    push_line(calendar, &format!("DTSTAMP:{}", format_time(activity.updated_at)));
+    let zone = activity.zone();
+    if let Some(recurrence_id) = recurrence_id {
+        push_line(calendar, &zoned_property("RECURRENCE-ID", &[recurrence_id], zone));
+    }
+    push_line(calendar, &zoned_property("DTSTART", &[activity.start], zone));
+    if let Some(end) = activity.end {
+        push_line(calendar, &zoned_property("DTEND", &[end], zone));
+    }
+    push_line(calendar, &format!("SUMMARY:{}", escape_text(&activity.title)));
+    if let Some(description) = &activity.description {
+        push_line(calendar, &format!("DESCRIPTION:{}", escape_text(description)));
+    }
+    if !activity.tags.is_empty() {
+        let tags = activity.tags.iter().map(|tag| escape_text(tag)).collect::<Vec<_>>().join(",");
+        push_line(calendar, &format!("CATEGORIES:{}", tags));
+    }
+    if recurrence_id.is_none() {
+        if let Some(rule) = &activity.recurrence {
+            push_line(calendar, &format!("RRULE:{}", rule));
+        }
+        if !exdates.is_empty() {
+            push_line(calendar, &zoned_property("EXDATE", exdates, zone));
+        }
+    }
+    push_line(calendar, &format!("CREATED:{}", format_time(activity.created_at)));
+    push_line(calendar, &format!("LAST-MODIFIED:{}", format_time(activity.updated_at)));
+    push_line(calendar, "END:VEVENT");
+}
+
+/// Skipped occurrences become `EXDATE`s, changed occurrences separate events with a `RECURRENCE-ID`.
+/// Every time zone of the activities gets a `VTIMEZONE` before the events.
+fn write_calendar(activities: &[(Activity, Vec<ActivityException>)]) -> String {
+    let mut calendar = String::new();
+    push_line(&mut calendar, "BEGIN:VCALENDAR");
+    push_line(&mut calendar, "VERSION:2.0");
+    push_line(&mut calendar, &format!("PRODID:{}", PRODUCT_ID));
+    push_line(&mut calendar, "CALSCALE:GREGORIAN");
+    push_line(&mut calendar, "METHOD:PUBLISH");
+    push_line(&mut calendar, "X-WR-CALNAME:MyActivities");
+
+    let mut zones: BTreeMap<&str, (Tz, i32, i32)> = BTreeMap::new();
+    for (activity, _) in activities {
+        let zone = activity.zone();
+        if zone != Tz::UTC {
+            let year = activity.start.year();
+            let (_, first, last) = zones.entry(zone.name()).or_insert((zone, year, Utc::now().year()));
+            *first = (*first).min(year);
+            *last = (*last).max(year);
+        }
+    }
+    for (zone, first_year, last_year) in zones.into_values() {
+        push_time_zone(&mut calendar, zone, first_year, last_year);
+    }
+
+    for (activity, exceptions) in activities {
+        let exdates = exceptions.iter()
+            .filter(|exception| exception.skipped)
+            .map(|exception| exception.occurrence)
+            .collect::<Vec<_>>();
+        push_event(&mut calendar, activity, None, &exdates);
+
+        for exception in exceptions {
+            if let Some(changed) = exception.apply(activity.occurrence(exception.occurrence)) {
+                push_event(&mut calendar, &changed, Some(exception.occurrence), &[]);
+            }
+        }
+    }
+
+    push_line(&mut calendar, "END:VCALENDAR");
+    calendar
+}
+
+/// Joins folded content lines, continuation lines start with a space or a tab
+fn unfold(calendar: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in calendar.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    lines
+}
+
+/// Content line `NAME;PARAM=VALUE:value`
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Property> {
+        // the value starts at the first colon outside of quoted parameter values
+        let mut quoted = false;
+        let (split, _) = line.char_indices().find(|(_, c)| {
+            if *c == '"' {
+                quoted = !quoted;
+            }
+            *c == ':' && !quoted
+        })?;
+
+        let mut parts = line[..split].split(';');
+        let name = parts.next()?.trim().to_ascii_uppercase();
+        let params = parts
+            .filter_map(|param| param.split_once('='))
+            .map(|(name, value)| (name.to_ascii_uppercase(), value.trim_matches('"').to_owned()))
+            .collect();
+
+        Some(Property { name, params, value: line[split + 1..].to_owned() })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(param, _)| param == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The IANA zone of the `TZID`, the `VTIMEZONE` definitions of the calendar are not evaluated
+    fn zone(&self) -> Result<Option<Tz>, String> {
+        self.param("TZID")
+            .map(|id| id.trim_start_matches('/').parse::<Tz>()
+                .map_err(|_| format!("Unknown time zone '{}' of {}", id, self.name)))
+            .transpose()
+    }
+
+    /// Times with a `TZID` are local times of that zone, other times without `Z` are read as UTC
+    fn time(&self) -> Result<DateTime<Utc>, String> {
+        let value = self.value.trim();
+        let invalid = || format!("Invalid {} value '{}'", self.name, value);
+        let time = if self.param("VALUE") == Some("DATE") || value.len() == 8 {
+            NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| date.and_time(NaiveTime::MIN))
+        } else {
+            NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+        }.map_err(|_| invalid())?;
+
+        match self.zone()? {
+            Some(zone) if !value.ends_with('Z') => local_to_utc(zone, time).ok_or_else(invalid),
+            _ => Ok(Utc.from_utc_datetime(&time)),
+        }
+    }
+
+    fn text(&self) -> String {
+        split_text(&self.value).join(",")
+    }
+}
+
+/// Splits at unescaped commas and unescapes the parts
+fn split_text(value: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts are never empty");
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => part.push('\n'),
+                Some(escaped) => part.push(escaped),
+                None => {},
+            },
+            ',' => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+
+    parts
+}
+
+/// Durations like `PT1H30M` or `P1D`, negative durations and durations out of range are rejected
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim().strip_prefix('+').unwrap_or(value.trim()).strip_prefix('P')?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() => {},
+            unit => {
+                let amount: i64 = number.parse().ok()?;
+                number.clear();
+                let part = match unit {
+                    'W' => Duration::try_weeks(amount),
+                    'D' => Duration::try_days(amount),
+                    'H' => Duration::try_hours(amount),
+                    'M' => Duration::try_minutes(amount),
+                    'S' => Duration::try_seconds(amount),
+                    _ => None,
+                }?;
+                duration = duration.checked_add(&part)?;
+            },
+        }
+    }
+
+    number.is_empty().then_some(duration)
+}
+
+/// Changed occurrences and excluded dates of recurring events are not imported
+fn parse_event(index: usize, properties: &[Property]) -> Result<ImportedActivity, ImportIssue> {
+    let find = |name: &str| properties.iter().find(|property| property.name == name);
+    let uid = find("UID").map(|uid| uid.value.trim().to_owned()).filter(|uid| !uid.is_empty());
+    let issue = |message: String| ImportIssue { index, uid: uid.clone(), message };
+
+    if find("RECURRENCE-ID").is_some() {
+        return Err(issue("Changed occurrences of recurring events are not supported".to_owned()));
+    }
+
+    let dtstart = find("DTSTART").ok_or_else(|| issue("DTSTART is missing".to_owned()))?;
+    let start = dtstart.time().map_err(issue)?;
+    let time_zone = dtstart.zone().map_err(issue)?.map(|zone| zone.name().to_owned());
+    let end = match (find("DTEND"), find("DURATION")) {
+        (Some(end), _) => Some(end.time().map_err(issue)?),
+        (None, Some(duration)) => Some(parse_duration(&duration.value)
+            .and_then(|duration| start.checked_add_signed(duration))
+            .ok_or_else(|| issue(format!("Invalid DURATION value '{}'", duration.value)))?),
+        (None, None) => None,
+    };
+    let tags = properties.iter()
+        .filter(|property| property.name == "CATEGORIES")
+        .flat_map(|property| split_text(&property.value))
+        .collect();
+
+    let activity = NewActivity {
+        title: find("SUMMARY").map(Property::text).unwrap_or_default(),
+        description: find("DESCRIPTION").map(Property::text),
+        start,
+        end,
+        category_id: None,
+        tags,
+        recurrence: find("RRULE").map(|rule| rule.value.clone()),
+        time_zone,
+    };
+
+    Ok(ImportedActivity { index, uid, activity, category: None, time_entries: Vec::new() })
+}
+
+/// Events are numbered in the order of the calendar, components inside events like alarms are ignored
+fn parse_calendar(calendar: &str) -> Result<(Vec<ImportedActivity>, Vec<ImportIssue>), CalendarError> {
+    let lines = unfold(calendar);
+    if !lines.iter().any(|line| line.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR")) {
+        return Err(CalendarError::Invalid("The upload is no iCalendar file".to_owned()));
+    }
+
+    let mut events = Vec::new();
+    let mut issues = Vec::new();
+    let mut event: Option<Vec<Property>> = None;
+    let mut nested = 0;
+    for property in lines.iter().filter_map(|line| Property::parse(line)) {
+        let Some(properties) = event.as_mut() else {
+            if property.name == "BEGIN" && property.value.trim().eq_ignore_ascii_case("VEVENT") {
+                event = Some(Vec::new());
+            }
+            continue;
+        };
+
+        match property.name.as_str() {
+            "BEGIN" => nested += 1,
+            "END" if nested > 0 => nested -= 1,
+            "END" => {
+                let index = events.len() + issues.len() + 1;
+                match parse_event(index, properties) {
+                    Ok(imported) => events.push(imported),
+                    Err(issue) => issues.push(issue),
+                }
+                event = None;
+            },
+            _ if nested == 0 => properties.push(property),
+            _ => {},
+        }
+    }
+
+    Ok((events, issues))
+}
+
+#[async_trait]
+impl CalendarApi for CalendarService {
+    async fn export(&self, user_id: i32, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Result<String, CalendarError> {
+        // without a range end recurring activities are not expanded
+        let filter = ActivityFilter { from, ..ActivityFilter::default() };
+        let mut activities = Vec::new();
+        for activity in self.activity_api.find(user_id, &filter).await? {
+            if to.is_some_and(|to| activity.start >= to) {
+                continue;
+            }
+
+            let exceptions = match activity.recurrence {
+                Some(_) => self.activity_api.find_exceptions(user_id, activity.id).await?,
+                None => Vec::new(),
+            };
+            activities.push((activity, exceptions));
+        }
+
+        Ok(write_calendar(&activities))
+    }
+
+    async fn import(&self, user_id: i32, calendar: String) -> Result<ImportSummary, CalendarError> {
+        // large calendars must not block the async workers
+        let (events, issues) = tokio::task::spawn_blocking(move || parse_calendar(&calendar)).await??;
+
+        let mut summary = self.activity_api.import(user_id, events).await?;
+        for issue in issues {
+            summary.reject(issue);
+        }
+        summary.errors.sort_by_key(|issue| issue.index);
+
+        Ok(summary)
+    }
+
+    async fn create_feed(&self, user_id: i32) -> Result<String, CalendarError> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let hash = CalendarService::hash_secret(&secret);
+
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO calendar_feeds (user_id, secret_hash, created_at) values (?1, ?2, ?3) \
+                    ON CONFLICT (user_id) DO UPDATE SET secret_hash = excluded.secret_hash, created_at = excluded.created_at",
+                (user_id, hash, Utc::now())
+            )?;
+            Ok::<(), CalendarError>(())
+        }).await??;
+
+        Ok(secret)
+    }
+
+    async fn delete_feed(&self, user_id: i32) -> Result<(), CalendarError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let deleted = conn.execute("DELETE FROM calendar_feeds WHERE user_id = ?1", [user_id])?;
+            if deleted == 0 {
+                return Err(CalendarError::FeedNotFound);
+            }
+
+            Ok(())
+        }).await?
+    }
+
+    async fn find_feed_user_id(&self, secret: &str) -> Result<i32, CalendarError> {
+        let db = self.db_config.get_database().to_owned();
+        let hash = CalendarService::hash_secret(secret);
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok(conn.query_row("SELECT user_id FROM calendar_feeds WHERE secret_hash = ?1", [hash], |row| row.get(0))?)
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::{ActivityFilter, NewActivity, NewActivityException}, activity_api::ActivityApi, calendar_api::CalendarApi, user::User, user_api::UserApi}, error::errors::CalendarError, service::{activity_service::ActivityService, user_service::UserService}};
+
+    use super::CalendarService;
+
+    #[tokio::test]
+    async fn should_export_events_with_rules_and_exceptions() {
+        let db_config = Arc::new(DbConfig::new("file:calendar_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let calendar = CalendarService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap();
+
+        let swim = activities.create(user.id, NewActivity {
+            title: "Swim, then sauna; relax".to_owned(),
+            description: Some(format!("Bring towels\n{}", "ü".repeat(60))),
+            start: at(3, 18),
+            end: Some(at(3, 19)),
+            category_id: None,
+            tags: vec!["sport".to_owned()],
+            recurrence: Some("FREQ=WEEKLY".to_owned()),
+            time_zone: None,
+        }).await.unwrap();
+        let skip = NewActivityException { skipped: true, title: None, description: None, start: None, end: None };
+        activities.save_exception(user.id, swim.id, at(10, 18), skip).await.unwrap();
+        let later = NewActivityException { skipped: false, title: None, description: None, start: Some(at(17, 20)), end: None };
+        activities.save_exception(user.id, swim.id, at(17, 18), later).await.unwrap();
+        activities.create(user.id, NewActivity {
+            title: "Dentist".to_owned(),
+            description: None,
+            start: at(20, 9),
+            end: None,
+            category_id: None,
+            tags: vec![],
+            recurrence: None,
+            time_zone: None,
+        }).await.unwrap();
+
+        let ics = calendar.export(user.id, None, None).await.unwrap();
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.lines().all(|line| line.len() <= 76));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert!(ics.contains("SUMMARY:Swim\\, then sauna\\; relax\r\n"));
+        assert!(ics.contains("DESCRIPTION:Bring towels\\nü"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY\r\nEXDATE:20250310T180000Z\r\n"));
+        assert!(ics.contains("RECURRENCE-ID:20250317T180000Z\r\nDTSTART:20250317T200000Z\r\nDTEND:20250317T210000Z\r\n"));
+        assert!(ics.contains("DTSTART:20250320T090000Z\r\nSUMMARY:Dentist\r\n"));
+
+        let ics = calendar.export(user.id, Some(at(1, 0)), Some(at(15, 0))).await.unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+
+        let secret = calendar.create_feed(user.id).await.unwrap();
+        assert_eq!(calendar.find_feed_user_id(&secret).await.unwrap(), user.id);
+        let rotated = calendar.create_feed(user.id).await.unwrap();
+        assert!(matches!(calendar.find_feed_user_id(&secret).await, Err(CalendarError::FeedNotFound)));
+        calendar.delete_feed(user.id).await.unwrap();
+        assert!(matches!(calendar.find_feed_user_id(&rotated).await, Err(CalendarError::FeedNotFound)));
+        assert!(matches!(calendar.delete_feed(user.id).await, Err(CalendarError::FeedNotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_export_series_in_their_time_zone() {
+        let db_config = Arc::new(DbConfig::new("file:calendar_service_zone_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let calendar = CalendarService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        // 18:00 in Berlin, the clocks change on March 30
+        let swim = activities.create(user.id, NewActivity {
+            title: "Swim".to_owned(),
+            description: None,
+            start: Utc.with_ymd_and_hms(2025, 3, 24, 17, 0, 0).unwrap(),
+            end: Some(Utc.with_ymd_and_hms(2025, 3, 24, 18, 0, 0).unwrap()),
+            category_id: None,
+            tags: vec![],
+            recurrence: Some("FREQ=WEEKLY".to_owned()),
+            time_zone: Some("Europe/Berlin".to_owned()),
+        }).await.unwrap();
+        let skip = NewActivityException { skipped: true, title: None, description: None, start: None, end: None };
+        activities.save_exception(user.id, swim.id, Utc.with_ymd_and_hms(2025, 4, 7, 16, 0, 0).unwrap(), skip).await.unwrap();
+
+        let filter = ActivityFilter {
+            from: Some(Utc.with_ymd_and_hms(2025, 3, 30, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2025, 4, 1, 0, 0, 0).unwrap()),
+            ..ActivityFilter::default()
+        };
+        let occurrences = activities.find(user.id, &filter).await.unwrap();
+        assert_eq!(occurrences[0].start, Utc.with_ymd_and_hms(2025, 3, 31, 16, 0, 0).unwrap());
+
+        let ics = calendar.export(user.id, None, None).await.unwrap();
+        assert!(ics.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/Berlin\r\nBEGIN:STANDARD\r\nDTSTART:20250101T010000\r\n"));
+        assert!(ics.contains("BEGIN:DAYLIGHT\r\nDTSTART:20250330T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nTZNAME:CEST\r\nEND:DAYLIGHT\r\n"));
+        assert!(ics.contains("BEGIN:STANDARD\r\nDTSTART:20251026T030000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\nTZNAME:CET\r\nEND:STANDARD\r\n"));
+        assert!(ics.contains("DTSTART;TZID=Europe/Berlin:20250324T180000\r\nDTEND;TZID=Europe/Berlin:20250324T190000\r\n"));
+        assert!(ics.contains("EXDATE;TZID=Europe/Berlin:20250407T180000\r\n"));
+        assert!(ics.find("END:VTIMEZONE").unwrap() < ics.find("BEGIN:VEVENT").unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_import_events_once_by_uid() {
+        let db_config = Arc::new(DbConfig::new("file:calendar_service_import_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let calendar = CalendarService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VEVENT",
+            "UID:swim@other",
+            "DTSTART;TZID=Europe/Berlin:20250303T180000",
+            "DTEND;TZID=Europe/Berlin:20250303T190000",
+            "SUMMARY:Swim\\, then sauna with a title that is long enough to be folded by the",
+            "  exporting tool",
+            "CATEGORIES:sport,water",
+            "RRULE:FREQ=WEEKLY;BYDAY=MO",
+            "BEGIN:VALARM",
+            "ACTION:DISPLAY",
+            "DESCRIPTION:Reminder",
+            "END:VALARM",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:swim@other",
+            "RECURRENCE-ID:20250310T180000Z",
+            "DTSTART:20250310T200000Z",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:holiday@other",
+            "DTSTART;VALUE=DATE:20250401",
+            "DTEND;VALUE=DATE:20250403",
+            "SUMMARY:Holiday",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:broken@other",
+            "SUMMARY:No start",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "DTSTART:20250305T120000Z",
+            "DURATION:PT1H30M",
+            "SUMMARY:Lunch",
+            "DESCRIPTION:Line one\\nLine two",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:moon@other",
+            "DTSTART;TZID=Moon/Base:20250306T120000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:forever@other",
+            "DTSTART:20250307T120000Z",
+            "DURATION:P99999999999999W",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:eternal@other",
+            "DTSTART:20250307T120000Z",
+            "DURATION:P1000000000W",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ].join("\r\n");
+
+        let summary = calendar.import(user.id, ics.clone()).await.unwrap();
+        assert_eq!((summary.created, summary.updated, summary.skipped), (3, 0, 5));
+        assert_eq!(summary.errors.iter().map(|e| (e.index, e.uid.as_deref())).collect::<Vec<_>>(), vec![
+            (2, Some("swim@other")), (4, Some("broken@other")), (6, Some("moon@other")), (7, Some("forever@other")), (8, Some("eternal@other")),
+        ]);
+        assert_eq!(summary.errors[2].message, "Unknown time zone 'Moon/Base' of DTSTART");
+
+        let imported = activities.find(user.id, &ActivityFilter::default()).await.unwrap();
+        let swim = imported.iter().find(|a| a.recurrence.is_some()).unwrap();
+        assert_eq!(swim.title, "Swim, then sauna with a title that is long enough to be folded by the exporting tool");
+        assert_eq!(swim.start, Utc.with_ymd_and_hms(2025, 3, 3, 17, 0, 0).unwrap());
+        assert_eq!(swim.time_zone.as_deref(), Some("Europe/Berlin"));
+        assert_eq!(swim.tags, vec!["sport", "water"]);
+        let lunch = imported.iter().find(|a| a.title == "Lunch").unwrap();
+        assert_eq!(lunch.description.as_deref(), Some("Line one\nLine two"));
+        assert_eq!(lunch.end, Some(Utc.with_ymd_and_hms(2025, 3, 5, 13, 30, 0).unwrap()));
+
+        // events without a UID are created again, changed events update the activity
+        let summary = calendar.import(user.id, ics.replace("SUMMARY:Holiday", "SUMMARY:Vacation")).await.unwrap();
+        assert_eq!((summary.created, summary.updated, summary.skipped), (1, 1, 6));
+        assert_eq!(activities.find(user.id, &ActivityFilter::default()).await.unwrap().len(), 4);
+
+        assert!(matches!(calendar.import(user.id, "BEGIN:VCARD".to_owned()).await, Err(CalendarError::Invalid(_))));
+    }
+}
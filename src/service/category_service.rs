@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rusqlite::{Connection, Row};
+
+use crate::{config::db::DbConfig, domain::{category::{Category, NewCategory}, category_api::CategoryApi}, error::errors::CategoryError};
+
+const CATEGORY_COLUMNS: &str = "id, user_id, name, color, icon";
+
+pub struct CategoryService {
+    db_config: Arc<DbConfig>
+}
+
+impl CategoryService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+/// Maps a row selected with [CATEGORY_COLUMNS]
+fn map_category(row: &Row) -> rusqlite::Result<Category> {
+    Ok(Category {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        color: row.get(3)?,
+        icon: row.get(4)?,
+    })
+}
+
+#[async_trait]
+impl CategoryApi for CategoryService {
+    async fn create(&self, user_id: i32, category: NewCategory) -> Result<Category, CategoryError> {
+        let category = category.validate().map_err(CategoryError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO categories (user_id, name, color, icon) values (?1, ?2, ?3, ?4)",
+                (user_id, category.name, category.color, category.icon)
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok(conn.query_row(&format!("SELECT {} FROM categories WHERE id = ?1", CATEGORY_COLUMNS), [id], map_category)?)
+        }).await?
+    }
+
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<Category>, CategoryError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM categories WHERE user_id = ?1 ORDER BY name", CATEGORY_COLUMNS))?;
+            let categories = stmt.query_map([user_id], map_category)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(categories)
+        }).await?
+    }
+
+    async fn update(&self, user_id: i32, category_id: i32, category: NewCategory) -> Result<Category, CategoryError> {
+        let category = category.validate().map_err(CategoryError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let updated = conn.execute(
+                "UPDATE categories SET name = ?1, color = ?2, icon = ?3 WHERE id = ?4 AND user_id = ?5",
+                (category.name, category.color, category.icon, category_id, user_id)
+            )?;
+            if updated == 0 {
+                return Err(CategoryError::NotFound);
+            }
+
+            Ok(conn.query_row(&format!("SELECT {} FROM categories WHERE id = ?1", CATEGORY_COLUMNS), [category_id], map_category)?)
+        }).await?
+    }
+
+    async fn delete(&self, user_id: i32, category_id: i32) -> Result<(), CategoryError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+
+            tx.execute("UPDATE activities SET category_id = NULL WHERE category_id = ?1 AND user_id = ?2", (category_id, user_id))?;
+            tx.execute("DELETE FROM goals WHERE category_id = ?1 AND user_id = ?2", (category_id, user_id))?;
+            let deleted = tx.execute("DELETE FROM categories WHERE id = ?1 AND user_id = ?2", (category_id, user_id))?;
+            if deleted == 0 {
+                return Err(CategoryError::NotFound);
+            }
+            tx.commit()?;
+
+            Ok(())
+        }).await?
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::email_change_api::EmailChangeApi, error::errors::EmailChangeError};
+
+pub struct EmailChangeService {
+    db_config: Arc<DbConfig>
+}
+
+impl EmailChangeService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+
+    fn hash_token(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+}
+
+#[async_trait]
+impl EmailChangeApi for EmailChangeService {
+    async fn create_token(&self, user_id: i32, email: &str, expires_at: DateTime<Utc>) -> Result<String, EmailChangeError> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let hash = EmailChangeService::hash_token(&token);
+
+        let db = self.db_config.get_database().to_owned();
+        let email = email.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("DELETE FROM email_changes WHERE user_id = ?1 OR expires_at <= ?2", (user_id, Utc::now()))?;
+            conn.execute("INSERT INTO email_changes (user_id, email, token_hash, expires_at) values (?1, ?2, ?3, ?4)", (user_id, email, hash, expires_at))?;
+            Ok::<(), EmailChangeError>(())
+        }).await??;
+
+        Ok(token)
+    }
+
+    async fn take_email(&self, user_id: i32, token: &str) -> Result<String, EmailChangeError> {
+        let db = self.db_config.get_database().to_owned();
+        let hash = EmailChangeService::hash_token(token.trim());
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+
+            let tx = conn.transaction()?;
+            let email: String = tx.query_row(
+                "SELECT email FROM email_changes WHERE user_id = ?1 AND token_hash = ?2 AND expires_at > ?3",
+                (user_id, &hash, Utc::now()),
+                |row| row.get(0)
+            )?;
+            tx.execute("DELETE FROM email_changes WHERE user_id = ?1", [user_id])?;
+            tx.commit()?;
+
+            Ok(email)
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{email_change_api::EmailChangeApi, user::User, user_api::UserApi}, error::errors::EmailChangeError, service::user_service::UserService};
+
+    use super::EmailChangeService;
+
+    #[tokio::test]
+    async fn should_confirm_only_the_latest_change_once() {
+        let db_config = Arc::new(DbConfig::new("file:email_change_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user = UserService::new(Arc::clone(&db_config)).save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let service = EmailChangeService::new(Arc::clone(&db_config));
+
+        let replaced = service.create_token(user.id, "old@example.org", Utc::now() + Duration::minutes(5)).await.unwrap();
+        let token = service.create_token(user.id, "new@example.org", Utc::now() + Duration::minutes(5)).await.unwrap();
+
+        assert!(matches!(service.take_email(user.id, &replaced).await, Err(EmailChangeError::InvalidToken)));
+        assert!(matches!(service.take_email(user.id + 1, &token).await, Err(EmailChangeError::InvalidToken)));
+        assert_eq!(service.take_email(user.id, &token).await.unwrap(), "new@example.org");
+        assert!(matches!(service.take_email(user.id, &token).await, Err(EmailChangeError::InvalidToken)));
+    }
+}
@@ -0,0 +1,65 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use authfix::multifactor::factor::{CheckCodeError, Factor, GenerateCodeError};
+
+use crate::{domain::{email_code::MFA_ID_EMAIL_CODE, email_code_api::EmailCodeApi, user::User}, error::errors::EmailCodeError, service::auth_service::AUTHFIX_SESSION_KEY_USER};
+
+/// Sends a short-lived numeric code to the users email address
+pub struct EmailCodeFactor {
+    email_code_api: Arc<dyn EmailCodeApi>,
+}
+
+impl EmailCodeFactor {
+    pub fn new(email_code_api: Arc<dyn EmailCodeApi>) -> Self {
+        Self {
+            email_code_api,
+        }
+    }
+}
+
+fn session_user(req: &HttpRequest) -> Result<Option<User>, String> {
+    req.get_session().get::<User>(AUTHFIX_SESSION_KEY_USER)
+        .map_err(|e| e.to_string())
+}
+
+impl Factor for EmailCodeFactor {
+    fn generate_code(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = Result<(), GenerateCodeError>>>> {
+        let user = session_user(req);
+        let email_code_api = Arc::clone(&self.email_code_api);
+
+        Box::pin(async move {
+            let user = user
+                .map_err(|e| GenerateCodeError::new(&format!("Cannot read user from session: {}", e)))?
+                .ok_or_else(|| GenerateCodeError::new("No user in session"))?;
+
+            email_code_api.send_code(&user).await
+                .map_err(|e| GenerateCodeError::new_with_cause("Cannot send email code", e))
+        })
+    }
+
+    fn unique_id(&self) -> String {
+        MFA_ID_EMAIL_CODE.to_owned()
+    }
+
+    fn check_code(&self, code: &str, req: &HttpRequest) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let user = session_user(req);
+        let email_code_api = Arc::clone(&self.email_code_api);
+        let code = code.to_owned();
+
+        Box::pin(async move {
+            let user = user
+                .map_err(CheckCodeError::UnknownError)?
+                .ok_or_else(|| CheckCodeError::UnknownError("No user in session".to_owned()))?;
+
+            email_code_api.verify_code(user.id, &code).await
+                .map_err(|e| match e {
+                    EmailCodeError::Invalid => CheckCodeError::InvalidCode,
+                    EmailCodeError::Expired => CheckCodeError::TimeIsUp(e.to_string()),
+                    EmailCodeError::NotFound | EmailCodeError::TooManyAttempts => CheckCodeError::FinallyRejected,
+                    _ => CheckCodeError::UnknownError(e.to_string()),
+                })
+        })
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::{email_code_api::EmailCodeApi, mail::Mail, mail_api::MailSender, user::User}, error::errors::EmailCodeError};
+
+const CODE_DIGITS: u32 = 6;
+const CODE_VALIDITY_MINUTES: i64 = 10;
+const MAX_ATTEMPTS: u32 = 5;
+
+pub struct EmailCodeService {
+    db_config: Arc<DbConfig>,
+    mail_sender: Arc<dyn MailSender>,
+}
+
+impl EmailCodeService {
+    pub fn new(db_config: Arc<DbConfig>, mail_sender: Arc<dyn MailSender>) -> Self {
+        Self {
+            db_config,
+            mail_sender,
+        }
+    }
+
+    /// Uniformly distributed numeric code with leading zeros
+    fn generate_code() -> String {
+        let modulus = 10u32.pow(CODE_DIGITS);
+        // reject values above the largest multiple of the modulus to avoid a bias
+        let limit = u32::MAX - u32::MAX % modulus;
+        loop {
+            let value = OsRng.next_u32();
+            if value < limit {
+                return format!("{:0width$}", value % modulus, width = CODE_DIGITS as usize);
+            }
+        }
+    }
+
+    fn hash_code(salt: &str, code: &str) -> String {
+        format!("{:x}", Sha256::digest(format!("{}{}", salt, code).as_bytes()))
+    }
+}
+
+#[async_trait]
+impl EmailCodeApi for EmailCodeService {
+    async fn send_code(&self, user: &User) -> Result<(), EmailCodeError> {
+        let code = EmailCodeService::generate_code();
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let salt = salt.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        let hash = EmailCodeService::hash_code(&salt, &code);
+        let expires_at = Utc::now() + Duration::minutes(CODE_VALIDITY_MINUTES);
+
+        let db = self.db_config.get_database().to_owned();
+        let user_id = user.id;
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO email_codes (user_id, code_hash, salt, expires_at, attempts) values (?1, ?2, ?3, ?4, 0)",
+                (user_id, hash, salt, expires_at)
+            )?;
+            Ok::<(), EmailCodeError>(())
+        }).await??;
+
+        let body = format!(
+            "Hello {},\n\nyour login code is {}. It is valid for {} minutes.\n\nIf you did not try to log in, change your password.",
+            user.name, code, CODE_VALIDITY_MINUTES
+        );
+        self.mail_sender.send(Mail::new(&user.email, "Your MyActivities login code", &body)).await?;
+
+        Ok(())
+    }
+
+    async fn verify_code(&self, user_id: i32, code: &str) -> Result<(), EmailCodeError> {
+        let db = self.db_config.get_database().to_owned();
+        let code = code.trim().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let (hash, salt, expires_at, attempts) = conn.query_row(
+                "SELECT code_hash, salt, expires_at, attempts FROM email_codes WHERE user_id = ?1",
+                [user_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, DateTime<Utc>>(2)?, row.get::<_, u32>(3)?))
+            )?;
+
+            if expires_at <= Utc::now() {
+                conn.execute("DELETE FROM email_codes WHERE user_id = ?1", [user_id])?;
+                return Err(EmailCodeError::Expired);
+            }
+
+            if EmailCodeService::hash_code(&salt, &code) == hash {
+                conn.execute("DELETE FROM email_codes WHERE user_id = ?1", [user_id])?;
+                return Ok(());
+            }
+
+            if attempts + 1 >= MAX_ATTEMPTS {
+                conn.execute("DELETE FROM email_codes WHERE user_id = ?1", [user_id])?;
+                Err(EmailCodeError::TooManyAttempts)
+            } else {
+                conn.execute("UPDATE email_codes SET attempts = attempts + 1 WHERE user_id = ?1", [user_id])?;
+                Err(EmailCodeError::Invalid)
+            }
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{config::db::DbConfig, create_db, domain::{email_code_api::EmailCodeApi, user::User, user_api::UserApi}, error::errors::EmailCodeError, service::{mail_service::recording::RecordingMailSender, user_service::UserService}};
+
+    use super::{EmailCodeService, MAX_ATTEMPTS};
+
+    fn code_from_mail(body: &str) -> String {
+        body.split_whitespace()
+            .map(|word| word.trim_end_matches('.'))
+            .find(|word| word.len() == 6 && word.chars().all(|c| c.is_ascii_digit()))
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn should_accept_mailed_code_only_once() {
+        let db_config = Arc::new(DbConfig::new("file:email_code_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let mail_sender = Arc::new(RecordingMailSender::default());
+        let email_codes = EmailCodeService::new(Arc::clone(&db_config), mail_sender.clone());
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        email_codes.send_code(&user).await.unwrap();
+        let mails = mail_sender.mails();
+        let code = code_from_mail(&mails[0].body);
+
+        assert_eq!(mails[0].to, "test@example.org");
+        assert!(matches!(email_codes.verify_code(user.id, "not-the-code").await, Err(EmailCodeError::Invalid)));
+        email_codes.verify_code(user.id, &code).await.unwrap();
+        assert!(matches!(email_codes.verify_code(user.id, &code).await, Err(EmailCodeError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_discard_code_after_too_many_attempts() {
+        let db_config = Arc::new(DbConfig::new("file:email_code_service_attempts_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let mail_sender = Arc::new(RecordingMailSender::default());
+        let email_codes = EmailCodeService::new(Arc::clone(&db_config), mail_sender.clone());
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        email_codes.send_code(&user).await.unwrap();
+        let code = code_from_mail(&mail_sender.mails()[0].body);
+        for _ in 1..MAX_ATTEMPTS {
+            assert!(matches!(email_codes.verify_code(user.id, "wrong").await, Err(EmailCodeError::Invalid)));
+        }
+
+        assert!(matches!(email_codes.verify_code(user.id, "wrong").await, Err(EmailCodeError::TooManyAttempts)));
+        assert!(matches!(email_codes.verify_code(user.id, &code).await, Err(EmailCodeError::NotFound)));
+    }
+}
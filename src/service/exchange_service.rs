@@ -0,0 +1,439 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{stream::{self, BoxStream}, StreamExt};
+use rusqlite::{types::Type, Connection};
+use tokio::sync::mpsc::{self, Sender};
+
+use crate::{config::db::DbConfig, domain::{activity::NewActivity, activity_api::ActivityApi, activity_import::{ImportIssue, ImportSummary, ImportedActivity}, activity_record::{ActivityRecord, TimeEntryRecord}, exchange_api::{ExchangeApi, ExchangeFormat}}, error::errors::ExchangeError, service::calendar_service::activity_uid};
+
+const CSV_COLUMNS: [&str; 10] = ["uid", "title", "description", "start", "end", "category", "tags", "recurrence", "time_zone", "time_entries"];
+/// Separates the tags and the legacy time entries inside a CSV field
+const LIST_SEPARATOR: char = ';';
+/// Leading characters that make spreadsheets evaluate a value as formula
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+/// Exported records that wait for a slow client, the export pauses when the buffer is full
+const EXPORT_BUFFER: usize = 64;
+/// Selects the activities of a user with the name of their category and their tags as JSON array
+const RECORD_QUERY: &str = "SELECT a.id, a.title, a.description, a.start_time, a.end_time, c.name, a.recurrence, \
+    (SELECT json_group_array(t.name ORDER BY t.name) FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id), a.time_zone, a.import_uid \
+    FROM activities a LEFT JOIN categories c ON c.id = a.category_id WHERE a.user_id = ?1 ORDER BY a.start_time, a.id";
+
+pub struct ExchangeService {
+    db_config: Arc<DbConfig>,
+    activity_api: Arc<dyn ActivityApi>,
+}
+
+impl ExchangeService {
+    pub fn new(db_config: Arc<DbConfig>, activity_api: Arc<dyn ActivityApi>) -> Self {
+        Self {
+            db_config,
+            activity_api,
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Values with a formula prefix, also behind apostrophes, get a leading apostrophe
+fn needs_formula_guard(value: &str) -> bool {
+    value.starts_with(FORMULA_PREFIXES) || value.strip_prefix('\'').is_some_and(needs_formula_guard)
+}
+
+/// Values that spreadsheets would evaluate as formulas get a leading apostrophe, it is removed again on import
+fn csv_field(value: &str) -> String {
+    let value = if needs_formula_guard(value) {
+        format!("'{}", value)
+    } else {
+        value.to_owned()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Quoted fields and CRLF line break of RFC 4180
+pub(crate) fn csv_line(fields: &[String]) -> String {
+    let fields = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+    format!("{}\r\n", fields.join(","))
+}
+
+fn csv_record(record: &ActivityRecord) -> Result<String, ExchangeError> {
+    let entries = if record.time_entries.is_empty() {
+        String::new()
+    } else {
+        serde_json::to_string(&record.time_entries).map_err(|e| ExchangeError::Database(e.to_string()))?
+    };
+
+    Ok(csv_line(&[
+        record.uid.clone().unwrap_or_default(),
+        record.title.clone(),
+        record.description.clone().unwrap_or_default(),
+        format_time(record.start),
+        record.end.map(format_time).unwrap_or_default(),
+        record.category.clone().unwrap_or_default(),
+        record.tags.join(&LIST_SEPARATOR.to_string()),
+        record.recurrence.clone().unwrap_or_default(),
+        record.time_zone.clone().unwrap_or_default(),
+        entries,
+    ]))
+}
+
+/// Sends the records of the user oldest first, stops early when the receiver is dropped
+fn write_export(db: &str, user_id: i32, format: ExchangeFormat, sender: &Sender<Result<String, ExchangeError>>) -> Result<(), ExchangeError> {
+    let send = |chunk: String| sender.blocking_send(Ok(chunk)).is_ok();
+    let conn = Connection::open(db)?;
+    let mut activities = conn.prepare(RECORD_QUERY)?;
+    let mut entries = conn.prepare("SELECT start_time, end_time, note FROM time_entries WHERE activity_id = ?1 ORDER BY start_time, id")?;
+
+    let header = match format {
+        ExchangeFormat::Csv => csv_line(&CSV_COLUMNS.map(str::to_owned)),
+        ExchangeFormat::Json => "[".to_owned(),
+    };
+    if !send(header) {
+        return Ok(());
+    }
+
+    let mut rows = activities.query([user_id])?;
+    let mut first = true;
+    while let Some(row) = rows.next()? {
+        let id: i32 = row.get(0)?;
+        let tags: String = row.get(7)?;
+        let tags = serde_json::from_str(&tags)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(7, Type::Text, e.into()))?;
+        let time_entries = entries
+            .query_map([id], |entry| Ok(TimeEntryRecord { start: entry.get(0)?, end: entry.get(1)?, note: entry.get(2)? }))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let uid: Option<String> = row.get(9)?;
+        let record = ActivityRecord {
+            uid: Some(uid.unwrap_or_else(|| activity_uid(id))),
+            title: row.get(1)?,
+            description: row.get(2)?,
+            start: row.get(3)?,
+            end: row.get(4)?,
+            category: row.get(5)?,
+            tags,
+            recurrence: row.get(6)?,
+            time_zone: row.get(8)?,
+            time_entries,
+        };
+        let chunk = match format {
+            ExchangeFormat::Csv => csv_record(&record)?,
+            ExchangeFormat::Json => {
+                let json = serde_json::to_string(&record).map_err(|e| ExchangeError::Database(e.to_string()))?;
+                if first { json } else { format!(",{}", json) }
+            },
+        };
+        first = false;
+        if !send(chunk) {
+            return Ok(());
+        }
+    }
+
+    if format == ExchangeFormat::Json {
+        send("]".to_owned());
+    }
+    Ok(())
+}
+
+/// Records with their fields and the line they start at, quoted fields may contain separators and line breaks.
+/// Blank lines are ignored.
+fn parse_csv(content: &str) -> Result<Vec<(usize, Vec<String>)>, ExchangeError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let (mut line, mut record_line) = (1, 1);
+
+    // spreadsheets often start the file with a byte order mark
+    let mut chars = content.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' if quoted => quoted = false,
+            c if quoted => {
+                if c == '\n' || (c == '\r' && chars.peek() != Some(&'\n')) {
+                    line += 1;
+                }
+                field.push(c);
+            },
+            '"' if field.is_empty() => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\r' | '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut record)));
+                line += 1;
+                record_line = line;
+            },
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(ExchangeError::Invalid("A quoted CSV field is not closed".to_owned()));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((record_line, record));
+    }
+
+    records.retain(|(_, record)| record.len() > 1 || record.first().is_some_and(|field| !field.trim().is_empty()));
+    Ok(records)
+}
+
+fn parse_time(field: &str, value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid {} '{}'", field, value))
+}
+
+/// A CSV row with the columns of the header, unknown columns are ignored
+fn csv_to_record(columns: &HashMap<String, usize>, row: &[String]) -> Result<ActivityRecord, String> {
+    let value = |name: &str| {
+        columns.get(name)
+            .and_then(|index| row.get(*index))
+            .map(|value| match value.strip_prefix('\'') {
+                Some(guarded) if needs_formula_guard(guarded) => guarded,
+                _ => value.as_str(),
+            })
+            .filter(|value| !value.trim().is_empty())
+    };
+    let list = |name: &str| {
+        value(name).into_iter()
+            .flat_map(|value| value.split(LIST_SEPARATOR))
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    // older exports list the entries as `start/end` without notes
+    let time_entries = match value("time_entries") {
+        Some(entries) if entries.trim_start().starts_with('[') => serde_json::from_str(entries)
+            .map_err(|e| format!("Invalid time entries: {}", e))?,
+        _ => list("time_entries").into_iter()
+            .map(|entry| {
+                let (start, end) = entry.split_once('/').unwrap_or((entry, ""));
+                Ok(TimeEntryRecord {
+                    start: parse_time("time entry start", start)?,
+                    end: Some(end).filter(|end| !end.trim().is_empty()).map(|end| parse_time("time entry end", end)).transpose()?,
+                    note: None,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?,
+    };
+
+    Ok(ActivityRecord {
+        uid: value("uid").map(|uid| uid.trim().to_owned()),
+        title: value("title").unwrap_or_default().to_owned(),
+        description: value("description").map(str::to_owned),
+        start: parse_time("start", value("start").ok_or("Start is missing")?)?,
+        end: value("end").map(|end| parse_time("end", end)).transpose()?,
+        category: value("category").map(str::to_owned),
+        tags: list("tags").into_iter().map(str::to_owned).collect(),
+        recurrence: value("recurrence").map(str::to_owned),
+        time_zone: value("time_zone").map(str::to_owned),
+        time_entries,
+    })
+}
+
+/// Line of a CSV record or position in the JSON array, starting with 1, and the record or why it is invalid
+type ParsedRecord = (usize, Result<ActivityRecord, String>);
+
+/// Records in the order of the file
+fn parse_records(format: ExchangeFormat, content: &str) -> Result<Vec<ParsedRecord>, ExchangeError> {
+    match format {
+        ExchangeFormat::Csv => {
+            let mut rows = parse_csv(content)?.into_iter();
+            let (_, header) = rows.next().ok_or_else(|| ExchangeError::Invalid("The CSV file has no header".to_owned()))?;
+            let columns: HashMap<String, usize> = header.iter()
+                .enumerate()
+                .map(|(index, name)| (name.trim().to_lowercase(), index))
+                .collect();
+            if !columns.contains_key("title") || !columns.contains_key("start") {
+                return Err(ExchangeError::Invalid("The CSV header needs the columns title and start".to_owned()));
+            }
+
+            Ok(rows.map(|(line, row)| (line, csv_to_record(&columns, &row))).collect())
+        },
+        ExchangeFormat::Json => {
+            let values: Vec<serde_json::Value> = serde_json::from_str(content)
+                .map_err(|e| ExchangeError::Invalid(format!("The JSON file must contain an array of activities: {}", e)))?;
+
+            Ok(values.into_iter()
+                .enumerate()
+                .map(|(index, value)| (index + 1, serde_json::from_value(value).map_err(|e| e.to_string())))
+                .collect())
+        },
+    }
+}
+
+#[async_trait]
+impl ExchangeApi for ExchangeService {
+    fn export(&self, user_id: i32, format: ExchangeFormat) -> BoxStream<'static, Result<String, ExchangeError>> {
+        let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = write_export(&db, user_id, format, &sender) {
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+
+        stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }).boxed()
+    }
+
+    async fn import(&self, user_id: i32, format: ExchangeFormat, content: String) -> Result<ImportSummary, ExchangeError> {
+        // large files must not block the async workers
+        let records = tokio::task::spawn_blocking(move || parse_records(format, &content)).await??;
+
+        let mut activities = Vec::new();
+        let mut issues = Vec::new();
+        for (index, record) in records {
+            match record {
+                Ok(record) => activities.push(ImportedActivity {
+                    index,
+                    uid: record.uid.filter(|uid| !uid.trim().is_empty()),
+                    activity: NewActivity {
+                        title: record.title,
+                        description: record.description,
+                        start: record.start,
+                        end: record.end,
+                        category_id: None,
+                        tags: record.tags,
+                        recurrence: record.recurrence,
+                        time_zone: record.time_zone,
+                    },
+                    category: record.category,
+                    time_entries: record.time_entries.into_iter().map(Into::into).collect(),
+                }),
+                Err(message) => issues.push(ImportIssue { index, uid: None, message }),
+            }
+        }
+
+        let mut summary = self.activity_api.import(user_id, activities).await?;
+        for issue in issues {
+            summary.reject(issue);
+        }
+        summary.errors.sort_by_key(|issue| issue.index);
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+    use futures::TryStreamExt;
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::{ActivityFilter, NewActivity}, activity_api::ActivityApi, category::NewCategory, category_api::CategoryApi, exchange_api::{ExchangeApi, ExchangeFormat}, time_entry::NewTimeEntry, time_entry_api::TimeEntryApi, user::User, user_api::UserApi}, service::{activity_service::ActivityService, category_service::CategoryService, time_entry_service::TimeEntryService, user_service::UserService}};
+
+    use super::{csv_field, ExchangeService};
+
+    #[tokio::test]
+    async fn should_round_trip_activities_and_report_invalid_rows() {
+        let db_config = Arc::new(DbConfig::new("file:exchange_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let categories = CategoryService::new(Arc::clone(&db_config));
+        let entries = TimeEntryService::new(Arc::clone(&db_config));
+        let exchange = ExchangeService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap();
+
+        let sport = categories.create(user.id, NewCategory { name: "Sport".to_owned(), color: "#00aa00".to_owned(), icon: None }).await.unwrap();
+        let run = activities.create(user.id, NewActivity {
+            title: "=Run, \"fast\"".to_owned(),
+            description: Some("Along the river\nand back".to_owned()),
+            start: at(1, 7),
+            end: Some(at(1, 8)),
+            category_id: Some(sport.id),
+            tags: vec!["outdoor".to_owned(), "morning".to_owned()],
+            recurrence: None,
+            time_zone: None,
+        }).await.unwrap();
+        entries.create(user.id, run.id, NewTimeEntry { start: at(1, 7), end: Some(at(1, 8)), note: Some("warm".to_owned()) }).await.unwrap();
+        let read = activities.create(user.id, NewActivity {
+            title: "Read".to_owned(),
+            description: Some("'@home".to_owned()),
+            start: at(2, 20),
+            end: None,
+            category_id: None,
+            tags: vec![],
+            recurrence: Some("FREQ=DAILY;COUNT=3".to_owned()),
+            time_zone: Some("Europe/Berlin".to_owned()),
+        }).await.unwrap();
+
+        let csv: Vec<String> = exchange.export(user.id, ExchangeFormat::Csv).try_collect().await.unwrap();
+        let csv = csv.concat();
+        assert_eq!(csv, format!("uid,title,description,start,end,category,tags,recurrence,time_zone,time_entries\r\n\
+            activity-{}@myactivities,\"'=Run, \"\"fast\"\"\",\"Along the river\nand back\",2025-03-01T07:00:00Z,2025-03-01T08:00:00Z,Sport,morning;outdoor,,,\
+            \"[{{\"\"start\"\":\"\"2025-03-01T07:00:00Z\"\",\"\"end\"\":\"\"2025-03-01T08:00:00Z\"\",\"\"note\"\":\"\"warm\"\"}}]\"\r\n\
+            activity-{}@myactivities,Read,''@home,2025-03-02T20:00:00Z,,,,FREQ=DAILY;COUNT=3,Europe/Berlin,\r\n", run.id, read.id));
+
+        let summary = exchange.import(other.id, ExchangeFormat::Csv, csv.clone()).await.unwrap();
+        assert_eq!((summary.created, summary.skipped), (2, 0));
+        // the uids match the imported activities again
+        let summary = exchange.import(other.id, ExchangeFormat::Csv, csv.clone()).await.unwrap();
+        assert_eq!((summary.created, summary.skipped), (0, 2));
+        // a changed category alone updates the activity
+        let summary = exchange.import(other.id, ExchangeFormat::Csv, csv.replace(",Sport,", ",Training,")).await.unwrap();
+        assert_eq!((summary.updated, summary.skipped), (1, 1));
+        let summary = exchange.import(other.id, ExchangeFormat::Csv, csv).await.unwrap();
+        assert_eq!((summary.updated, summary.skipped), (1, 1));
+        let imported = activities.find(other.id, &ActivityFilter::default()).await.unwrap();
+        assert_eq!(imported[1].title, "=Run, \"fast\"");
+        assert_eq!(imported[1].description.as_deref(), Some("Along the river\nand back"));
+        assert_eq!(imported[1].tags, vec!["morning", "outdoor"]);
+        assert_eq!(imported[0].description.as_deref(), Some("'@home"));
+        assert_eq!(imported[0].time_zone.as_deref(), Some("Europe/Berlin"));
+        let imported_category = categories.find_by_user_id(other.id).await.unwrap();
+        assert_eq!(imported_category[0].name, "Sport");
+        let imported_entries = entries.find_by_activity(other.id, imported[1].id).await.unwrap();
+        assert_eq!(imported_entries.total_seconds, 3600);
+        assert_eq!(imported_entries.entries[0].entry.note.as_deref(), Some("warm"));
+
+        let json: Vec<String> = exchange.export(user.id, ExchangeFormat::Json).try_collect().await.unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json.concat()).unwrap();
+        assert_eq!(json[0]["category"], "Sport");
+        assert_eq!(json[0]["time_entries"][0]["note"], "warm");
+        assert_eq!(json[1]["recurrence"], "FREQ=DAILY;COUNT=3");
+
+        // errors refer to the line a record starts at
+        let csv = "Title,Start,End,Category,Time_Entries\n\
+            \"Swim\nlong\",2025-03-05T18:00:00Z,2025-03-05T19:00:00Z,sport,\n\
+            \n\
+            Nap,yesterday,,,\n\
+            ,2025-03-06T12:00:00Z,,,\n\
+            Walk,2025-03-07T12:00:00Z,,,2025-03-07T12:00:00Z/\n";
+        let summary = exchange.import(other.id, ExchangeFormat::Csv, csv.to_owned()).await.unwrap();
+        assert_eq!((summary.created, summary.skipped), (1, 3));
+        assert_eq!(summary.errors.iter().map(|e| e.index).collect::<Vec<_>>(), vec![5, 6, 7]);
+        assert_eq!(summary.errors[0].message, "Invalid start 'yesterday'");
+        assert_eq!(summary.errors[2].message, "Time entry 1: End is missing");
+        assert_eq!(categories.find_by_user_id(other.id).await.unwrap().len(), 2);
+
+        let json = r#"[{ "title": "Yoga", "start": "2025-03-08T18:00:00Z" }, { "title": "Gym" }, 42]"#;
+        let summary = exchange.import(other.id, ExchangeFormat::Json, json.to_owned()).await.unwrap();
+        assert_eq!((summary.created, summary.skipped), (1, 2));
+        assert!(exchange.import(other.id, ExchangeFormat::Json, "{}".to_owned()).await.is_err());
+        assert!(exchange.import(other.id, ExchangeFormat::Csv, "name\nRun".to_owned()).await.is_err());
+
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\r=cmd"), "\"'\r=cmd\"");
+    }
+}
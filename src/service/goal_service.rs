@@ -0,0 +1,361 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+use rusqlite::{params_from_iter, types::Type, Connection, Row, ToSql};
+
+use crate::{config::db::DbConfig, domain::{activity::ActivityFilter, activity_api::ActivityApi, goal::{Goal, GoalMetric, GoalProgress, NewGoal}, goal_api::GoalApi, period::Period}, error::errors::{FieldError, GoalError}, service::report_service::period_start};
+
+const GOAL_COLUMNS: &str = "id, user_id, title, category_id, tag, period, metric, target, created_at";
+
+pub struct GoalService {
+    db_config: Arc<DbConfig>,
+    activity_api: Arc<dyn ActivityApi>,
+}
+
+impl GoalService {
+    pub fn new(db_config: Arc<DbConfig>, activity_api: Arc<dyn ActivityApi>) -> Self {
+        Self {
+            db_config,
+            activity_api,
+        }
+    }
+
+    /// Starts of the occurrences of the recurring activities counted by the goal before `end`. The series are
+    /// expanded a year at a time, which keeps even daily ones below [crate::domain::recurrence::MAX_OCCURRENCES].
+    async fn occurrences(&self, goal: &Goal, end: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, GoalError> {
+        let filter = ActivityFilter {
+            category_ids: goal.category_id.into_iter().collect(),
+            all_tags: if goal.category_id.is_none() { goal.tag.clone().into_iter().collect() } else { vec![] },
+            recurring: Some(true),
+            ..ActivityFilter::default()
+        };
+        let Some(first) = self.activity_api.find(goal.user_id, &filter).await?.into_iter().map(|series| series.start).min() else {
+            return Ok(vec![]);
+        };
+
+        let mut starts = Vec::new();
+        let mut from = first;
+        while from < end {
+            let to = from.checked_add_months(Months::new(12)).unwrap_or(end).min(end);
+            let window = ActivityFilter { from: Some(from), to: Some(to), ..filter.clone() };
+            // occurrences that started in the previous window are counted there
+            starts.extend(self.activity_api.find(goal.user_id, &window).await?.into_iter()
+                .map(|occurrence| occurrence.start)
+                .filter(|start| *start >= from));
+            from = to;
+        }
+
+        Ok(starts)
+    }
+
+    async fn progress(&self, goal: Goal) -> Result<GoalProgress, GoalError> {
+        let now = Utc::now();
+        let occurrences = match goal.metric {
+            GoalMetric::Count => self.occurrences(&goal, period_end(goal.period, now)).await?,
+            GoalMetric::Duration => vec![],
+        };
+
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            query_progress(&conn, goal, now, &occurrences)
+        }).await?
+    }
+}
+
+/// End of the period containing `now`, later activities do not count yet
+fn period_end(period: Period, now: DateTime<Utc>) -> DateTime<Utc> {
+    period.next(period.start_of(now.date_naive())).and_time(NaiveTime::MIN).and_utc()
+}
+
+fn conversion_error(index: usize, value: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, format!("Unknown value '{}'", value).into())
+}
+
+/// Maps a row selected with [GOAL_COLUMNS]
+fn map_goal(row: &Row) -> rusqlite::Result<Goal> {
+    let period: String = row.get(5)?;
+    let metric: String = row.get(6)?;
+
+    Ok(Goal {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        title: row.get(2)?,
+        category_id: row.get(3)?,
+        tag: row.get(4)?,
+        period: Period::from_str(&period).map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, e.into()))?,
+        metric: GoalMetric::parse(&metric).ok_or_else(|| conversion_error(6, &metric))?,
+        target: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+/// Computes the values of all periods with matching activities, running time entries count up to now.
+/// The occurrences of recurring activities are expanded before and counted with the single activities.
+fn query_progress(conn: &Connection, goal: Goal, now: DateTime<Utc>, occurrences: &[DateTime<Utc>]) -> Result<GoalProgress, GoalError> {
+    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(goal.user_id)];
+    let condition = match (goal.category_id, &goal.tag) {
+        (Some(category_id), _) => {
+            params.push(Box::new(category_id));
+            "a.category_id = ?2"
+        },
+        (None, Some(tag)) => {
+            params.push(Box::new(tag.clone()));
+            "EXISTS (SELECT 1 FROM activity_tags x JOIN tags t ON t.id = x.tag_id WHERE x.activity_id = a.id AND t.name = ?2)"
+        },
+        (None, None) => return Err(GoalError::Database(format!("Goal {} has neither category nor tag", goal.id))),
+    };
+    let sql = match goal.metric {
+        GoalMetric::Count => format!(
+            "SELECT {} AS period, COUNT(*) FROM activities a WHERE a.user_id = ?1 AND {} AND a.recurrence IS NULL AND a.start_time < ?3 GROUP BY period",
+            period_start(goal.period, "a.start_time"), condition
+        ),
+        GoalMetric::Duration => format!(
+            "SELECT {} AS period, CAST(ROUND(SUM((julianday(COALESCE(e.end_time, ?3)) - julianday(e.start_time)) * 86400)) AS INTEGER) \
+                FROM time_entries e JOIN activities a ON a.id = e.activity_id WHERE a.user_id = ?1 AND {} GROUP BY period",
+            period_start(goal.period, "e.start_time"), condition
+        ),
+    };
+
+    params.push(Box::new(match goal.metric {
+        GoalMetric::Count => period_end(goal.period, now),
+        GoalMetric::Duration => now,
+    }));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut values = stmt.query_map(params_from_iter(params), |row| Ok((row.get::<_, NaiveDate>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for occurrence in occurrences {
+        let start = goal.period.start_of(occurrence.date_naive());
+        match values.iter_mut().find(|(period, _)| *period == start) {
+            Some((_, value)) => *value += 1,
+            None => values.push((start, 1)),
+        }
+    }
+
+    Ok(GoalProgress::new(goal, &values, now.date_naive()))
+}
+
+fn query_goal(conn: &Connection, user_id: i32, goal_id: i64) -> rusqlite::Result<Goal> {
+    conn.query_row(&format!("SELECT {} FROM goals WHERE id = ?1 AND user_id = ?2", GOAL_COLUMNS), (goal_id, user_id), map_goal)
+}
+
+/// Foreign categories are reported as invalid like unknown ones
+fn check_category(conn: &Connection, user_id: i32, category_id: Option<i32>) -> Result<(), GoalError> {
+    let Some(category_id) = category_id else {
+        return Ok(());
+    };
+
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM categories WHERE id = ?1 AND user_id = ?2", (category_id, user_id), |row| row.get(0))?;
+    if count == 0 {
+        return Err(GoalError::Invalid(vec![FieldError::new("category_id", "unknown", "Category does not exist")]));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl GoalApi for GoalService {
+    async fn create(&self, user_id: i32, goal: NewGoal) -> Result<GoalProgress, GoalError> {
+        let goal = goal.validate().map_err(GoalError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        let created = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_category(&conn, user_id, goal.category_id)?;
+
+            conn.execute(
+                "INSERT INTO goals (user_id, title, category_id, tag, period, metric, target, created_at) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                (user_id, &goal.title, goal.category_id, &goal.tag, goal.period.as_str(), goal.metric.as_str(), goal.target, Utc::now())
+            )?;
+
+            Ok::<Goal, GoalError>(query_goal(&conn, user_id, conn.last_insert_rowid())?)
+        }).await??;
+
+        self.progress(created).await
+    }
+
+    async fn find_by_id(&self, user_id: i32, goal_id: i32) -> Result<GoalProgress, GoalError> {
+        let db = self.db_config.get_database().to_owned();
+        let goal = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            query_goal(&conn, user_id, goal_id.into())
+        }).await??;
+
+        self.progress(goal).await
+    }
+
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<GoalProgress>, GoalError> {
+        let db = self.db_config.get_database().to_owned();
+        let goals = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM goals WHERE user_id = ?1 ORDER BY id", GOAL_COLUMNS))?;
+            let goals = stmt.query_map([user_id], map_goal)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok::<Vec<Goal>, GoalError>(goals)
+        }).await??;
+
+        let mut progress = Vec::with_capacity(goals.len());
+        for goal in goals {
+            progress.push(self.progress(goal).await?);
+        }
+
+        Ok(progress)
+    }
+
+    async fn update(&self, user_id: i32, goal_id: i32, goal: NewGoal) -> Result<GoalProgress, GoalError> {
+        let goal = goal.validate().map_err(GoalError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        let updated = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_category(&conn, user_id, goal.category_id)?;
+
+            let updated = conn.execute(
+                "UPDATE goals SET title = ?1, category_id = ?2, tag = ?3, period = ?4, metric = ?5, target = ?6 WHERE id = ?7 AND user_id = ?8",
+                (&goal.title, goal.category_id, &goal.tag, goal.period.as_str(), goal.metric.as_str(), goal.target, goal_id, user_id)
+            )?;
+            if updated == 0 {
+                return Err(GoalError::NotFound);
+            }
+
+            Ok(query_goal(&conn, user_id, goal_id.into())?)
+        }).await??;
+
+        self.progress(updated).await
+    }
+
+    async fn delete(&self, user_id: i32, goal_id: i32) -> Result<(), GoalError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let deleted = conn.execute("DELETE FROM goals WHERE id = ?1 AND user_id = ?2", (goal_id, user_id))?;
+            if deleted == 0 {
+                return Err(GoalError::NotFound);
+            }
+
+            Ok(())
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Days, Duration, NaiveTime, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::{NewActivity, NewActivityException}, activity_api::ActivityApi, category::NewCategory, category_api::CategoryApi, goal::{GoalMetric, NewGoal}, goal_api::GoalApi, period::Period, tag_api::TagApi, time_entry::NewTimeEntry, time_entry_api::TimeEntryApi, user::User, user_api::UserApi}, error::errors::GoalError, service::{activity_service::ActivityService, category_service::CategoryService, tag_service::TagService, time_entry_service::TimeEntryService, user_service::UserService}};
+
+    use super::GoalService;
+
+    #[tokio::test]
+    async fn should_compute_progress_and_streaks() {
+        let db_config = Arc::new(DbConfig::new("file:goal_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let categories = CategoryService::new(Arc::clone(&db_config));
+        let entries = TimeEntryService::new(Arc::clone(&db_config));
+        let tags = TagService::new(Arc::clone(&db_config));
+        let goals = GoalService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+
+        // Monday of the current week at 06:00 UTC, activities may lie in the future of the week
+        let monday = Period::Week.start_of(Utc::now().date_naive()).and_time(NaiveTime::from_hms_opt(6, 0, 0).unwrap()).and_utc();
+        let weeks_ago = |weeks: u64| monday - Days::new(7 * weeks);
+        let new_activity = |start, category_id: Option<i32>, tags: &[&str]| NewActivity {
+            title: "Run".to_owned(),
+            description: None,
+            start,
+            end: None,
+            category_id,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            recurrence: None,
+            time_zone: None,
+        };
+        let new_goal = |category_id: Option<i32>, tag: Option<&str>, period, metric, target| NewGoal {
+            title: "Exercise".to_owned(),
+            category_id,
+            tag: tag.map(str::to_owned),
+            period,
+            metric,
+            target,
+        };
+
+        // two per week in this and the last week and in weeks 3 to 5 ago, only one 2 weeks ago
+        for weeks in [0, 0, 1, 1, 2, 3, 3, 4, 4, 5, 5] {
+            activities.create(user.id, new_activity(weeks_ago(weeks), None, &["Sport"])).await.unwrap();
+        }
+        activities.create(other.id, new_activity(monday, None, &["sport"])).await.unwrap();
+
+        let goal = goals.create(user.id, new_goal(None, Some(" sport "), Period::Week, GoalMetric::Count, 2)).await.unwrap();
+        assert_eq!(goal.goal.tag.as_deref(), Some("sport"));
+        assert_eq!(goal.period_start, monday.date_naive());
+        assert_eq!((goal.current, goal.achieved, goal.current_streak, goal.longest_streak), (2, true, 2, 3));
+
+        let goal = goals.update(user.id, goal.goal.id, new_goal(None, Some("sport"), Period::Week, GoalMetric::Count, 3)).await.unwrap();
+        assert_eq!((goal.current, goal.achieved, goal.current_streak, goal.longest_streak), (2, false, 0, 0));
+
+        let sport = tags.find_by_user_id(user.id).await.unwrap().into_iter().find(|tag| tag.name == "Sport").unwrap();
+        tags.rename(user.id, sport.id, "Training").await.unwrap();
+        let goal = goals.find_by_id(user.id, goal.goal.id).await.unwrap();
+        assert_eq!((goal.goal.tag.as_deref(), goal.current), (Some("Training"), 2));
+
+        let reading = categories.create(user.id, NewCategory { name: "Reading".to_owned(), color: "#0000aa".to_owned(), icon: None }).await.unwrap();
+        let book = activities.create(user.id, new_activity(monday, Some(reading.id), &[])).await.unwrap();
+        entries.create(user.id, book.id, NewTimeEntry { start: monday, end: Some(monday + Duration::minutes(90)), note: None }).await.unwrap();
+        let earlier = weeks_ago(1);
+        entries.create(user.id, book.id, NewTimeEntry { start: earlier, end: Some(earlier + Duration::hours(1)), note: None }).await.unwrap();
+
+        let duration = goals.create(user.id, new_goal(Some(reading.id), None, Period::Week, GoalMetric::Duration, 3600)).await.unwrap();
+        assert_eq!((duration.current, duration.achieved, duration.current_streak), (5400, true, 2));
+
+        assert!(matches!(goals.create(other.id, new_goal(Some(reading.id), None, Period::Day, GoalMetric::Count, 1)).await, Err(GoalError::Invalid(_))));
+        assert!(matches!(goals.find_by_id(other.id, duration.goal.id).await, Err(GoalError::NotFound)));
+        assert_eq!(goals.find_by_user_id(user.id).await.unwrap().iter().map(|goal| goal.goal.id).collect::<Vec<_>>(), vec![goal.goal.id, duration.goal.id]);
+
+        categories.delete(user.id, reading.id).await.unwrap();
+        assert!(matches!(goals.find_by_id(user.id, duration.goal.id).await, Err(GoalError::NotFound)));
+        goals.delete(user.id, goal.goal.id).await.unwrap();
+        assert!(goals.find_by_user_id(user.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_count_every_occurrence_of_recurring_activities() {
+        let db_config = Arc::new(DbConfig::new("file:goal_service_recurrence_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let goals = GoalService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        // daily from three days ago, yesterday is skipped
+        let today = Utc::now().date_naive().and_time(NaiveTime::from_hms_opt(6, 0, 0).unwrap()).and_utc();
+        let series = activities.create(user.id, NewActivity {
+            title: "Stretch".to_owned(),
+            description: None,
+            start: today - Days::new(3),
+            end: None,
+            category_id: None,
+            tags: vec!["Sport".to_owned()],
+            recurrence: Some("FREQ=DAILY".to_owned()),
+            time_zone: None,
+        }).await.unwrap();
+        let skip = NewActivityException { skipped: true, title: None, description: None, start: None, end: None };
+        activities.save_exception(user.id, series.id, today - Days::new(1), skip).await.unwrap();
+
+        let goal = NewGoal { title: "Stretch daily".to_owned(), category_id: None, tag: Some("sport".to_owned()), period: Period::Day, metric: GoalMetric::Count, target: 1 };
+        let daily = goals.create(user.id, goal.clone()).await.unwrap();
+        assert_eq!((daily.current, daily.achieved, daily.current_streak, daily.longest_streak), (1, true, 1, 2));
+
+        let monthly = goals.create(user.id, NewGoal { period: Period::Month, target: 40, ..goal }).await.unwrap();
+        // like planned single activities, the occurrences in the rest of the month count
+        let first = (today - Days::new(3)).date_naive().max(monthly.period_start);
+        let skipped = i64::from((today - Days::new(1)).date_naive() >= monthly.period_start);
+        assert_eq!(monthly.current, (Period::Month.next(monthly.period_start) - first).num_days() - skipped);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{path::PathBuf, sync::atomic::{AtomicU64, Ordering}};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::{config::config::MailConfig, domain::{mail::Mail, mail_api::MailSender}, error::errors::MailError};
+
+/// Logs mails instead of delivering them. Used until a real mail transport is configured.
+/// The body is left out, it carries codes and reset links; set `MA_MAIL_FILE_DIR` to read it locally.
+pub struct LogMailSender {
+    from: String,
+}
+
+impl LogMailSender {
+    pub fn new(config: &MailConfig) -> Self {
+        Self {
+            from: config.from.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl MailSender for LogMailSender {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        if mail.to.trim().is_empty() {
+            return Err(MailError::Send("Mail has no recipient".to_owned()));
+        }
+
+        log::info!("Mail from {} to {}: {}", self.from, mail.to, mail.subject);
+        Ok(())
+    }
+}
+
+/// Stores every mail as `.eml` file in a directory, a stand-in for an SMTP server during local testing.
+/// The files can be opened with any mail client.
+pub struct FileMailSender {
+    from: String,
+    dir: PathBuf,
+    counter: AtomicU64,
+}
+
+impl FileMailSender {
+    /// Panics if the directory cannot be created, it comes from the configuration
+    pub fn new(dir: &str, config: &MailConfig) -> Self {
+        std::fs::create_dir_all(dir)
+            .unwrap_or_else(|e| panic!("Cannot create mail directory '{}': {}", dir, e));
+
+        Self {
+            from: config.from.clone(),
+            dir: PathBuf::from(dir),
+            counter: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl MailSender for FileMailSender {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        if mail.to.trim().is_empty() {
+            return Err(MailError::Send("Mail has no recipient".to_owned()));
+        }
+
+        let now = Utc::now();
+        let number = self.counter.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}-{}.eml", now.format("%Y%m%dT%H%M%S%.6f"), number));
+        let content = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from, mail.to, mail.subject, now.to_rfc2822(), mail.body.replace('\n', "\r\n")
+        );
+
+        tokio::task::spawn_blocking(move || std::fs::write(&path, content))
+            .await
+            .map_err(|e| MailError::Send(e.to_string()))?
+            .map_err(|e| MailError::Send(format!("Cannot write mail file: {}", e)))
+    }
+}
+
+/// Keeps all sent mails in memory so tests can read them
+#[cfg(test)]
+pub mod recording {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use crate::{domain::{mail::Mail, mail_api::MailSender}, error::errors::MailError};
+
+    #[derive(Default)]
+    pub struct RecordingMailSender {
+        mails: Mutex<Vec<Mail>>,
+    }
+
+    impl RecordingMailSender {
+        pub fn mails(&self) -> Vec<Mail> {
+            self.mails.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl MailSender for RecordingMailSender {
+        async fn send(&self, mail: Mail) -> Result<(), MailError> {
+            self.mails.lock().unwrap().push(mail);
+            Ok(())
+        }
+    }
+}
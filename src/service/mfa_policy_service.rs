@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use authfix::login::{HandlerError, SuccessHandler};
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{config::db::DbConfig, domain::{mfa_policy::MfaPolicy, mfa_policy_api::MfaPolicyApi, user::User}, error::errors::MfaPolicyError};
+
+/// Set after a login if the user has to set up a second factor first, see [crate::middleware::mfa_enrollment]
+pub const SESSION_KEY_MFA_ENROLLMENT_REQUIRED: &str = "mfa_enrollment_required";
+const SETTINGS_KEY_MFA_POLICY: &str = "mfa_policy";
+
+pub struct MfaPolicyService {
+    db_config: Arc<DbConfig>
+}
+
+impl MfaPolicyService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+#[async_trait]
+impl MfaPolicyApi for MfaPolicyService {
+    async fn find_policy(&self) -> Result<MfaPolicy, MfaPolicyError> {
+        let db = self.db_config.get_database().to_owned();
+        let value = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<Option<String>, MfaPolicyError>(conn.query_row("SELECT value FROM settings WHERE key = ?1", [SETTINGS_KEY_MFA_POLICY], |row| row.get(0)).optional()?)
+        }).await??;
+
+        match value {
+            Some(value) => serde_json::from_str(&value)
+                .map_err(|e| MfaPolicyError::Database(format!("Stored MFA policy is invalid: {}", e))),
+            None => Ok(MfaPolicy::default()),
+        }
+    }
+
+    async fn save_policy(&self, policy: MfaPolicy) -> Result<MfaPolicy, MfaPolicyError> {
+        let db = self.db_config.get_database().to_owned();
+        let value = serde_json::to_string(&policy)
+            .map_err(|e| MfaPolicyError::Invalid(e.to_string()))?;
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("INSERT OR REPLACE INTO settings (key, value) values (?1, ?2)", (SETTINGS_KEY_MFA_POLICY, value))?;
+            Ok::<(), MfaPolicyError>(())
+        }).await??;
+
+        Ok(policy)
+    }
+
+    async fn requires_enrollment(&self, user: &User) -> Result<bool, MfaPolicyError> {
+        if !self.find_policy().await?.applies_to(user.role) {
+            return Ok(false);
+        }
+
+        let db = self.db_config.get_database().to_owned();
+        let user_id = user.id;
+        let mfa_id = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<Option<Option<String>>, MfaPolicyError>(conn.query_row("SELECT mfa_id FROM credentials WHERE user_id = ?1", [user_id], |row| row.get(0)).optional()?)
+        }).await??;
+
+        Ok(mfa_id.flatten().is_none())
+    }
+}
+
+/// Puts users that have to set up a second factor into the enrollment-only state after the login.
+///
+/// Database failures abort the login, the policy must not be skipped silently.
+pub struct MfaEnrollmentCheck {
+    mfa_policy_api: Arc<dyn MfaPolicyApi>,
+}
+
+impl MfaEnrollmentCheck {
+    pub fn new(mfa_policy_api: Arc<dyn MfaPolicyApi>) -> Self {
+        Self {
+            mfa_policy_api,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl SuccessHandler for MfaEnrollmentCheck {
+    type User = User;
+
+    async fn on_success(&self, user: &User, req: HttpRequest) -> Result<(), HandlerError> {
+        let required = self.mfa_policy_api.requires_enrollment(user).await
+            .map_err(|e| HandlerError::Unexpected(e.to_string()))?;
+
+        let session = req.get_session();
+        if required {
+            session.insert(SESSION_KEY_MFA_ENROLLMENT_REQUIRED, true)
+                .map_err(|e| HandlerError::Unexpected(e.to_string()))?;
+        } else {
+            session.remove(SESSION_KEY_MFA_ENROLLMENT_REQUIRED);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{config::db::DbConfig, create_db, domain::{mfa_policy::MfaPolicy, mfa_policy_api::MfaPolicyApi, user::{Mfa, Role, User}, user_api::UserApi}, service::user_service::UserService};
+
+    use super::MfaPolicyService;
+
+    #[tokio::test]
+    async fn should_require_enrollment_for_roles_of_the_policy_without_mfa() {
+        let db_config = Arc::new(DbConfig::new("file:mfa_policy_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let policy_service = MfaPolicyService::new(Arc::clone(&db_config));
+        let admin = user_service.save_user_with_credentials(User::new(0, "admin@example.org".to_owned(), "Linda".to_owned()).with_role(Role::Admin), "test123").await.unwrap();
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        assert_eq!(policy_service.find_policy().await.unwrap(), MfaPolicy::default());
+        assert!(!policy_service.requires_enrollment(&admin).await.unwrap());
+
+        policy_service.save_policy(MfaPolicy { required_for_all: false, required_roles: vec![Role::Admin] }).await.unwrap();
+        assert!(policy_service.requires_enrollment(&admin).await.unwrap());
+        assert!(!policy_service.requires_enrollment(&user).await.unwrap());
+
+        let mut creds = user_service.find_credentials_by_user_id(admin.id).await.unwrap();
+        creds.set_mfa(Mfa::with_secret("TOTP_MFA", "secret"));
+        user_service.save_credentials(creds).await.unwrap();
+        assert!(!policy_service.requires_enrollment(&admin).await.unwrap());
+    }
+}
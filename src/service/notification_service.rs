@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::{config::config::MailConfig, domain::{mail::Mail, mail_api::MailSender, notification_api::NotificationApi, security_notification::SecurityNotification, user::User}, error::errors::MailError};
+
+/// Renders security notifications as plain text mails
+pub struct NotificationService {
+    mail_sender: Arc<dyn MailSender>,
+    public_url: String,
+}
+
+impl NotificationService {
+    pub fn new(mail_sender: Arc<dyn MailSender>, config: &MailConfig) -> Self {
+        Self {
+            mail_sender,
+            public_url: config.public_url.clone(),
+        }
+    }
+
+    fn render(&self, user: &User, notification: &SecurityNotification) -> Mail {
+        let (subject, what) = match notification {
+            SecurityNotification::NewDeviceLogin { user_agent, ip } => (
+                "New login to your MyActivities account",
+                format!(
+                    "your account was used to log in from a new device.\n\nBrowser: {}\nIP address: {}",
+                    user_agent.as_deref().unwrap_or("unknown"),
+                    ip.as_deref().unwrap_or("unknown")
+                ),
+            ),
+            SecurityNotification::MfaEnrolled { mfa_id } => (
+                "Two-factor authentication was set up",
+                format!("the second factor of your account was set to {}.", mfa_id),
+            ),
+            SecurityNotification::MfaDisabled => (
+                "Two-factor authentication was disabled",
+                "your account no longer requires a second factor to log in.".to_owned(),
+            ),
+            SecurityNotification::PasswordChanged => (
+                "Your MyActivities password was changed",
+                "the password of your account was changed.".to_owned(),
+            ),
+            SecurityNotification::EmailChanged { new_email } => (
+                "Your MyActivities email address was changed",
+                format!("the email address of your account was changed to {}. Mails are no longer sent to this address.", new_email),
+            ),
+        };
+
+        let body = format!(
+            "Hello {},\n\n{}\n\nTime: {}\n\nIf this was not you, reset your password at {}/web/index.html#reset-password and review your security settings.",
+            user.name, what, Utc::now().format("%Y-%m-%d %H:%M UTC"), self.public_url
+        );
+
+        Mail::new(&user.email, subject, &body)
+    }
+}
+
+/// Sends the notification and only logs failures, the change itself already happened
+pub async fn send_notification(notification_api: &dyn NotificationApi, user: &User, notification: SecurityNotification) {
+    if let Err(e) = notification_api.notify(user, notification).await {
+        log::error!("Cannot notify user {}: {}", user.id, e);
+    }
+}
+
+#[async_trait]
+impl NotificationApi for NotificationService {
+    async fn notify(&self, user: &User, notification: SecurityNotification) -> Result<(), MailError> {
+        self.mail_sender.send(self.render(user, &notification)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{config::config::MailConfig, domain::{notification_api::NotificationApi, security_notification::SecurityNotification, user::User}, service::mail_service::{recording::RecordingMailSender, FileMailSender}};
+
+    use super::NotificationService;
+
+    #[tokio::test]
+    async fn should_render_notification_for_recipient() {
+        let mail_sender = Arc::new(RecordingMailSender::default());
+        let notifications = NotificationService::new(mail_sender.clone(), &MailConfig::default());
+        let user = User::new(1, "hans@example.org".to_owned(), "Hans".to_owned());
+
+        notifications.notify(&user, SecurityNotification::NewDeviceLogin { user_agent: Some("Firefox".to_owned()), ip: None }).await.unwrap();
+
+        let mail = &mail_sender.mails()[0];
+        assert_eq!(mail.to, "hans@example.org");
+        assert!(mail.body.starts_with("Hello Hans,"));
+        assert!(mail.body.contains("Browser: Firefox\nIP address: unknown"));
+        assert!(mail.body.contains("http://localhost:5665/web/index.html#reset-password"));
+    }
+
+    #[tokio::test]
+    async fn should_write_mails_to_directory() {
+        let dir = std::env::temp_dir().join(format!("ma-mails-{}", std::process::id()));
+        let notifications = NotificationService::new(Arc::new(FileMailSender::new(dir.to_str().unwrap(), &MailConfig::default())), &MailConfig::default());
+        let user = User::new(1, "hans@example.org".to_owned(), "Hans".to_owned());
+
+        notifications.notify(&user, SecurityNotification::PasswordChanged).await.unwrap();
+
+        let files = std::fs::read_dir(&dir).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let content = std::fs::read_to_string(files[0].path()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(content.contains("To: hans@example.org\r\nSubject: Your MyActivities password was changed\r\n"));
+    }
+}
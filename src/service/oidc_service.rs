@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::{config::config::OidcConfig, domain::{user::User, user_api::UserApi}, error::errors::{OidcError, QueryUserError}};
+
+/// The subset of the provider metadata (OpenID Connect Discovery) that is needed for the code flow
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::Single(aud) => aud == client_id,
+            Audience::Multiple(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmailVerified {
+    Bool(bool),
+    // some providers send "true" as string
+    Text(String),
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<EmailVerified>,
+}
+
+/// Values of one login attempt that are kept in the session until the provider redirects back
+#[derive(Serialize, Deserialize)]
+pub struct OidcFlowState {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// Login via OpenID Connect authorization code flow with PKCE.
+///
+/// The ID token is taken directly from the token endpoint over TLS, so its signature is not checked
+/// (OpenID Connect Core 3.1.3.7). Issuer, audience, expiry and nonce are validated.
+/// External identities are linked to existing users by verified email on the first login.
+pub struct OidcService {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    user_api: Arc<dyn UserApi>,
+}
+
+fn random_url_safe() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+impl OidcService {
+    pub fn new(config: OidcConfig, user_api: Arc<dyn UserApi>) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+            metadata: OnceCell::new(),
+            user_api,
+        }
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.metadata.get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+            let metadata: ProviderMetadata = self.http.get(url).send().await?
+                .error_for_status()?
+                .json().await?;
+
+            if metadata.issuer.trim_end_matches('/') != self.config.issuer {
+                return Err(OidcError::Provider(format!("Issuer mismatch in discovery document: {}", metadata.issuer)));
+            }
+
+            Ok(metadata)
+        }).await
+    }
+
+    /// Returns the URL of the provider's login page and the state that must be kept until the callback
+    pub async fn start_login(&self) -> Result<(Url, OidcFlowState), OidcError> {
+        let metadata = self.metadata().await?;
+        let flow = OidcFlowState {
+            state: random_url_safe(),
+            nonce: random_url_safe(),
+            code_verifier: random_url_safe(),
+        };
+
+        let url = Url::parse_with_params(&metadata.authorization_endpoint, &[
+            ("response_type", "code"),
+            ("client_id", &self.config.client_id),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("scope", &self.config.scopes),
+            ("state", &flow.state),
+            ("nonce", &flow.nonce),
+            ("code_challenge", &code_challenge(&flow.code_verifier)),
+            ("code_challenge_method", "S256"),
+        ]).map_err(|e| OidcError::Provider(format!("Invalid authorization endpoint: {}", e)))?;
+
+        Ok((url, flow))
+    }
+
+    /// Exchanges the code and returns the linked user
+    pub async fn finish_login(&self, code: &str, flow: &OidcFlowState) -> Result<User, OidcError> {
+        let claims = self.exchange_code(code, flow).await?;
+
+        let user = match self.user_api.find_by_external_identity(&claims.iss, &claims.sub).await {
+            Ok(user) => user,
+            Err(QueryUserError::NotFound) => self.link_by_email(&claims).await?,
+            Err(e) => return Err(e.into()),
+        };
+
+        if user.locked {
+            return Err(OidcError::AccountLocked);
+        }
+
+        Ok(user)
+    }
+
+    async fn exchange_code(&self, code: &str, flow: &OidcFlowState) -> Result<IdTokenClaims, OidcError> {
+        let metadata = self.metadata().await?;
+
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &flow.code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            params.push(("client_secret", secret));
+        }
+
+        let token: TokenResponse = self.http.post(&metadata.token_endpoint)
+            .form(&params)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        let claims = decode_claims(&token.id_token)?;
+        self.validate(&claims, metadata, flow)?;
+
+        Ok(claims)
+    }
+
+    fn validate(&self, claims: &IdTokenClaims, metadata: &ProviderMetadata, flow: &OidcFlowState) -> Result<(), OidcError> {
+        if claims.iss != metadata.issuer {
+            return Err(OidcError::InvalidIdToken(format!("Unexpected issuer: {}", claims.iss)));
+        }
+
+        if !claims.aud.contains(&self.config.client_id) {
+            return Err(OidcError::InvalidIdToken("Token was not issued for this client".to_owned()));
+        }
+
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(OidcError::InvalidIdToken("Token expired".to_owned()));
+        }
+
+        if claims.nonce.as_deref() != Some(flow.nonce.as_str()) {
+            return Err(OidcError::InvalidIdToken("Nonce does not match".to_owned()));
+        }
+
+        Ok(())
+    }
+
+    async fn link_by_email(&self, claims: &IdTokenClaims) -> Result<User, OidcError> {
+        let verified = match &claims.email_verified {
+            Some(EmailVerified::Bool(verified)) => *verified,
+            Some(EmailVerified::Text(verified)) => verified == "true",
+            None => false,
+        };
+
+        let email = match &claims.email {
+            Some(email) if verified => email,
+            _ => return Err(OidcError::NoAccount),
+        };
+
+        let user = match self.user_api.find_by_email(email).await {
+            Ok(user) => user,
+            Err(QueryUserError::NotFound) => return Err(OidcError::NoAccount),
+            Err(e) => return Err(e.into()),
+        };
+
+        self.user_api.link_external_identity(user.id, &claims.iss, &claims.sub).await?;
+        log::info!("Linked external identity of '{}' to user id = {}", claims.iss, user.id);
+
+        Ok(user)
+    }
+}
+
+fn decode_claims(id_token: &str) -> Result<IdTokenClaims, OidcError> {
+    let payload = id_token.split('.').nth(1)
+        .ok_or_else(|| OidcError::InvalidIdToken("Not a JWT".to_owned()))?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('='))
+        .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+    serde_json::from_slice(&payload)
+        .map_err(|e| OidcError::InvalidIdToken(e.to_string()))
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{net::TcpListener, sync::{Arc, Mutex}};
+
+    use actix_web::{get, post, web::Data, App, HttpResponse, HttpServer, Responder};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::{config::{config::OidcConfig, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi}, error::errors::OidcError, service::user_service::UserService};
+
+    use super::OidcService;
+
+    const CLIENT_ID: &str = "my-activities";
+
+    /// OIDC provider that issues unsigned ID tokens for one email address, the nonce is set by the test
+    pub(crate) struct MockIssuer {
+        pub(crate) issuer: String,
+        pub(crate) nonce: Mutex<String>,
+        email: String,
+    }
+
+    #[get("/.well-known/openid-configuration")]
+    async fn discovery(mock: Data<MockIssuer>) -> impl Responder {
+        HttpResponse::Ok().json(json!({
+            "issuer": mock.issuer,
+            "authorization_endpoint": format!("{}/authorize", mock.issuer),
+            "token_endpoint": format!("{}/token", mock.issuer),
+        }))
+    }
+
+    #[post("/token")]
+    async fn token(mock: Data<MockIssuer>) -> impl Responder {
+        let claims = json!({
+            "iss": mock.issuer,
+            "sub": "external-42",
+            "aud": CLIENT_ID,
+            "exp": Utc::now().timestamp() + 60,
+            "nonce": *mock.nonce.lock().unwrap(),
+            "email": mock.email,
+            "email_verified": true,
+        });
+        let id_token = format!("eyJhbGciOiJub25lIn0.{}.", URL_SAFE_NO_PAD.encode(claims.to_string()));
+
+        HttpResponse::Ok().json(json!({ "access_token": "at", "token_type": "Bearer", "id_token": id_token }))
+    }
+
+    pub(crate) fn start_mock_issuer(email: &str) -> Data<MockIssuer> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mock = Data::new(MockIssuer {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            nonce: Mutex::new(String::new()),
+            email: email.to_owned(),
+        });
+
+        let data = mock.clone();
+        let server = HttpServer::new(move || App::new().app_data(data.clone()).service(discovery).service(token))
+            .workers(1)
+            .listen(listener).unwrap()
+            .run();
+        actix_web::rt::spawn(server);
+
+        mock
+    }
+
+    pub(crate) fn oidc_config(issuer: &str) -> OidcConfig {
+        OidcConfig {
+            issuer: issuer.to_owned(),
+            client_id: CLIENT_ID.to_owned(),
+            client_secret: None,
+            redirect_uri: "http://localhost/api/oidc/callback".to_owned(),
+            scopes: "openid email".to_owned(),
+            post_login_redirect: "/".to_owned(),
+            mfa_redirect: "/login".to_owned(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn should_link_external_identity_by_verified_email() {
+        let db_config = Arc::new(DbConfig::new("file:oidc_service_link_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = Arc::new(UserService::new(Arc::clone(&db_config)));
+        let user = user_service.save_user_with_credentials(User::new(0, "hans@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let mock = start_mock_issuer("hans@example.org");
+        let oidc = OidcService::new(oidc_config(&mock.issuer), user_service.clone());
+
+        let (url, flow) = oidc.start_login().await.unwrap();
+        *mock.nonce.lock().unwrap() = flow.nonce.clone();
+        let logged_in = oidc.finish_login("code", &flow).await.unwrap();
+
+        assert!(url.as_str().contains("code_challenge_method=S256"));
+        assert_eq!(logged_in.id, user.id);
+        let linked = user_service.find_by_external_identity(&mock.issuer, "external-42").await.unwrap();
+        assert_eq!(linked.id, user.id);
+    }
+
+    #[actix_web::test]
+    async fn should_reject_wrong_nonce_and_unknown_email() {
+        let db_config = Arc::new(DbConfig::new("file:oidc_service_reject_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = Arc::new(UserService::new(Arc::clone(&db_config)));
+        let mock = start_mock_issuer("unknown@example.org");
+        let oidc = OidcService::new(oidc_config(&mock.issuer), user_service);
+
+        let (_, flow) = oidc.start_login().await.unwrap();
+        *mock.nonce.lock().unwrap() = "replayed".to_owned();
+        assert!(matches!(oidc.finish_login("code", &flow).await, Err(OidcError::InvalidIdToken(_))));
+
+        *mock.nonce.lock().unwrap() = flow.nonce.clone();
+        assert!(matches!(oidc.finish_login("code", &flow).await, Err(OidcError::NoAccount)));
+    }
+}
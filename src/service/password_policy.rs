@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use crate::{config::config::PasswordPolicyConfig, error::errors::FieldError};
+
+const BUNDLED_BLOCKLIST: &str = include_str!("../../resources/common-passwords.txt");
+/// argon2 hashes any length, the limit only prevents abuse with huge request bodies
+const MAX_LENGTH: usize = 128;
+/// Shorter parts of names and email addresses are too common to reject passwords containing them
+const MIN_PERSONAL_INFO_LENGTH: usize = 3;
+
+/// Checks new passwords on registration, reset and change
+pub struct PasswordPolicy {
+    min_length: usize,
+    disallow_personal_info: bool,
+    blocklist: HashSet<String>,
+}
+
+fn parse_blocklist(content: &str) -> impl Iterator<Item = String> + '_ {
+    content.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_lowercase())
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &PasswordPolicyConfig) -> Self {
+        let mut blocklist: HashSet<String> = parse_blocklist(BUNDLED_BLOCKLIST).collect();
+        if let Some(file) = &config.blocklist_file {
+            let content = std::fs::read_to_string(file)
+                .unwrap_or_else(|e| panic!("Cannot read password blocklist '{}': {}", file, e));
+            blocklist.extend(parse_blocklist(&content));
+        }
+
+        Self {
+            min_length: config.min_length,
+            disallow_personal_info: config.disallow_personal_info,
+            blocklist,
+        }
+    }
+
+    /// Returns all violations at once, so the user can fix them in one go
+    pub fn validate(&self, field: &str, password: &str, email: &str, name: &str) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        let length = password.chars().count();
+        let lowercase = password.to_lowercase();
+
+        if length < self.min_length {
+            errors.push(FieldError::new(field, "too_short", &format!("Password must have at least {} characters.", self.min_length)));
+        }
+
+        if length > MAX_LENGTH {
+            errors.push(FieldError::new(field, "too_long", &format!("Password must not have more than {} characters.", MAX_LENGTH)));
+        }
+
+        if self.blocklist.contains(&lowercase) {
+            errors.push(FieldError::new(field, "common", "Password is too common or appeared in a data breach."));
+        }
+
+        if self.disallow_personal_info {
+            let email = email.trim().to_lowercase();
+            let local_part = email.split('@').next().unwrap_or_default();
+            let contains_personal_info = [email.as_str(), local_part, name.trim().to_lowercase().as_str()]
+                .iter()
+                .any(|info| info.chars().count() >= MIN_PERSONAL_INFO_LENGTH && lowercase.contains(info));
+
+            if contains_personal_info {
+                errors.push(FieldError::new(field, "contains_personal_info", "Password must not contain your name or email address."));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::config::PasswordPolicyConfig;
+
+    use super::PasswordPolicy;
+
+    fn codes(policy: &PasswordPolicy, password: &str) -> Vec<String> {
+        policy.validate("password", password, "hans.meier@example.org", "Hans")
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.code)
+            .collect()
+    }
+
+    #[test]
+    fn should_report_all_violations() {
+        let policy = PasswordPolicy::new(&PasswordPolicyConfig::default());
+
+        assert_eq!(codes(&policy, ""), vec!["too_short"]);
+        assert_eq!(codes(&policy, "Password123"), vec!["common"]);
+        assert_eq!(codes(&policy, "hans"), vec!["too_short", "contains_personal_info"]);
+        assert_eq!(codes(&policy, "my-HANS.MEIER-secret"), vec!["contains_personal_info"]);
+        assert!(codes(&policy, "correct horse battery staple").is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::password_reset_api::PasswordResetApi, error::errors::PasswordResetError};
+
+pub struct PasswordResetService {
+    db_config: Arc<DbConfig>
+}
+
+impl PasswordResetService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+
+    fn hash_token(token: &str) -> String {
+        format!("{:x}", Sha256::digest(token.as_bytes()))
+    }
+}
+
+#[async_trait]
+impl PasswordResetApi for PasswordResetService {
+    async fn create_token(&self, user_id: i32, expires_at: DateTime<Utc>) -> Result<String, PasswordResetError> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let hash = PasswordResetService::hash_token(&token);
+
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("DELETE FROM password_resets WHERE expires_at <= ?1", [Utc::now()])?;
+            conn.execute("INSERT INTO password_resets (user_id, token_hash, expires_at) values (?1, ?2, ?3)", (user_id, hash, expires_at))?;
+            Ok::<(), PasswordResetError>(())
+        }).await??;
+
+        Ok(token)
+    }
+
+    async fn find_user_id(&self, token: &str) -> Result<i32, PasswordResetError> {
+        let db = self.db_config.get_database().to_owned();
+        let hash = PasswordResetService::hash_token(token.trim());
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok(conn.query_row(
+                "SELECT user_id FROM password_resets WHERE token_hash = ?1 AND expires_at > ?2",
+                (hash, Utc::now()),
+                |row| row.get(0)
+            )?)
+        }).await?
+    }
+
+    async fn delete_tokens(&self, user_id: i32) -> Result<(), PasswordResetError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("DELETE FROM password_resets WHERE user_id = ?1", [user_id])?;
+            Ok(())
+        }).await?
+    }
+}
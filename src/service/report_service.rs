@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::Connection;
+
+use crate::{config::db::DbConfig, domain::{activity::ActivityFilter, activity_api::ActivityApi, period::Period, report::{ReportGroup, ReportQuery, ReportRow, ReportSummary, ReportTotal}, report_api::ReportApi}, error::errors::ReportError, service::exchange_service::csv_line};
+
+/// Activities and occurrences started and time entries tracked in the range, one row per activity, occurrence or entry.
+/// The occurrences of recurring activities are expanded into `temp.occurrences` before.
+/// Parameters: user id, from, to and now as end of running entries.
+const REPORT_FACTS: &str = "WITH facts AS ( \
+        SELECT a.id AS activity_id, a.start_time AS at, 1 AS activities, 0.0 AS seconds \
+            FROM activities a WHERE a.user_id = ?1 AND a.recurrence IS NULL AND a.start_time >= ?2 AND a.start_time < ?3 \
+        UNION ALL \
+        SELECT o.activity_id, o.start_time, 1, 0.0 FROM temp.occurrences o \
+        UNION ALL \
+        SELECT e.activity_id, e.start_time, 0, (julianday(COALESCE(e.end_time, ?4)) - julianday(e.start_time)) * 86400 \
+            FROM time_entries e WHERE e.user_id = ?1 AND e.start_time >= ?2 AND e.start_time < ?3 \
+    )";
+
+pub struct ReportService {
+    db_config: Arc<DbConfig>,
+    activity_api: Arc<dyn ActivityApi>,
+}
+
+impl ReportService {
+    pub fn new(db_config: Arc<DbConfig>, activity_api: Arc<dyn ActivityApi>) -> Self {
+        Self {
+            db_config,
+            activity_api,
+        }
+    }
+}
+
+/// First day of the period of a time column in UTC, weeks start on Monday
+pub(crate) fn period_start(period: Period, column: &str) -> String {
+    match period {
+        Period::Day => format!("date({})", column),
+        // the next Sunday or the day itself, then back to Monday
+        Period::Week => format!("date({}, 'weekday 0', '-6 days')", column),
+        Period::Month => format!("date({}, 'start of month')", column),
+    }
+}
+
+/// Groups `facts f` joined with `activities a`
+fn grouping(period: Option<Period>, group: Option<ReportGroup>) -> (String, &'static str, &'static str) {
+    let period = period.map(|period| period_start(period, "f.at")).unwrap_or_else(|| "NULL".to_owned());
+    let (group, join) = match group {
+        None => ("NULL", ""),
+        Some(ReportGroup::Category) => ("c.name", "LEFT JOIN categories c ON c.id = a.category_id"),
+        Some(ReportGroup::Tag) => ("t.name", "LEFT JOIN activity_tags x ON x.activity_id = a.id LEFT JOIN tags t ON t.id = x.tag_id"),
+    };
+
+    (period, group, join)
+}
+
+/// Columns period, category or tag, activities and tracked seconds
+pub fn summary_csv(summary: &ReportSummary) -> String {
+    let group = match summary.group {
+        Some(ReportGroup::Category) => "category",
+        Some(ReportGroup::Tag) => "tag",
+        None => "group",
+    };
+    let mut csv = csv_line(&["period".to_owned(), group.to_owned(), "activities".to_owned(), "tracked_seconds".to_owned()]);
+    for row in &summary.rows {
+        csv.push_str(&csv_line(&[
+            row.period.clone().unwrap_or_default(),
+            row.group.clone().unwrap_or_default(),
+            row.activities.to_string(),
+            row.tracked_seconds.to_string(),
+        ]));
+    }
+
+    csv
+}
+
+#[async_trait]
+impl ReportApi for ReportService {
+    async fn summary(&self, user_id: i32, query: ReportQuery) -> Result<ReportSummary, ReportError> {
+        let query = query.validate().map_err(ReportError::Invalid)?;
+        let (period, group, join) = grouping(query.period, query.group);
+        let rows_sql = format!(
+            "{} SELECT {1} AS period, {2} AS report_group, SUM(f.activities), CAST(ROUND(SUM(f.seconds)) AS INTEGER) \
+                FROM facts f JOIN activities a ON a.id = f.activity_id {3} \
+                GROUP BY period, report_group ORDER BY period, report_group COLLATE NOCASE",
+            REPORT_FACTS, period, group, join
+        );
+        let total_sql = format!("{} SELECT COALESCE(SUM(activities), 0), CAST(ROUND(COALESCE(SUM(seconds), 0)) AS INTEGER) FROM facts", REPORT_FACTS);
+        let params = (user_id, query.from, query.to, Utc::now());
+
+        // single activities are counted in SQL, occurrences that started before the range but still last in it are not counted
+        let filter = ActivityFilter { from: Some(query.from), to: Some(query.to), recurring: Some(true), ..ActivityFilter::default() };
+        let occurrences = self.activity_api.find(user_id, &filter).await?.into_iter()
+            .filter(|occurrence| occurrence.start >= query.from)
+            .map(|occurrence| (occurrence.id, occurrence.start))
+            .collect::<Vec<_>>();
+
+        let db = self.db_config.get_database().to_owned();
+        let (rows, total) = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("CREATE TEMP TABLE occurrences (activity_id INTEGER NOT NULL, start_time TEXT NOT NULL)", [])?;
+            let mut insert = conn.prepare("INSERT INTO temp.occurrences (activity_id, start_time) VALUES (?1, ?2)")?;
+            for occurrence in occurrences {
+                insert.execute(occurrence)?;
+            }
+
+            let mut stmt = conn.prepare(&rows_sql)?;
+            let rows = stmt.query_map(params, |row| Ok(ReportRow {
+                period: row.get(0)?,
+                group: row.get(1)?,
+                activities: row.get(2)?,
+                tracked_seconds: row.get(3)?,
+            }))?.collect::<Result<Vec<_>, _>>()?;
+            let total = conn.query_row(&total_sql, params, |row| Ok(ReportTotal {
+                activities: row.get(0)?,
+                tracked_seconds: row.get(1)?,
+            }))?;
+
+            Ok::<(Vec<ReportRow>, ReportTotal), ReportError>((rows, total))
+        }).await??;
+
+        Ok(ReportSummary {
+            from: query.from,
+            to: query.to,
+            period: query.period,
+            group: query.group,
+            rows,
+            total,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::{NewActivity, NewActivityException}, activity_api::ActivityApi, category::NewCategory, category_api::CategoryApi, period::Period, report::{ReportGroup, ReportQuery, ReportRow}, report_api::ReportApi, time_entry::NewTimeEntry, time_entry_api::TimeEntryApi, user::User, user_api::UserApi}, service::{activity_service::ActivityService, category_service::CategoryService, time_entry_service::TimeEntryService, user_service::UserService}};
+
+    use super::{summary_csv, ReportService};
+
+    fn row(period: Option<&str>, group: Option<&str>, activities: i64, tracked_seconds: i64) -> ReportRow {
+        ReportRow { period: period.map(str::to_owned), group: group.map(str::to_owned), activities, tracked_seconds }
+    }
+
+    #[tokio::test]
+    async fn should_aggregate_activities_and_tracked_time() {
+        let db_config = Arc::new(DbConfig::new("file:report_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let categories = CategoryService::new(Arc::clone(&db_config));
+        let entries = TimeEntryService::new(Arc::clone(&db_config));
+        let reports = ReportService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap();
+        let new_activity = |title: &str, day: u32, category_id: Option<i32>, tags: &[&str]| NewActivity {
+            title: title.to_owned(),
+            description: None,
+            start: at(day, 7),
+            end: None,
+            category_id,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            recurrence: None,
+            time_zone: None,
+        };
+
+        let sport = categories.create(user.id, NewCategory { name: "Sport".to_owned(), color: "#00aa00".to_owned(), icon: None }).await.unwrap();
+        // Monday and Sunday of the same week, then the next Monday
+        let run = activities.create(user.id, new_activity("Run", 3, Some(sport.id), &["outdoor", "morning"])).await.unwrap();
+        let swim = activities.create(user.id, new_activity("Swim", 9, Some(sport.id), &["outdoor"])).await.unwrap();
+        let read = activities.create(user.id, new_activity("Read", 10, None, &[])).await.unwrap();
+        activities.create(user.id, new_activity("Old", 1, None, &[])).await.unwrap();
+        activities.create(other.id, new_activity("Run", 3, None, &[])).await.unwrap();
+        entries.create(user.id, run.id, NewTimeEntry { start: at(3, 7), end: Some(at(3, 8)), note: None }).await.unwrap();
+        entries.create(user.id, swim.id, NewTimeEntry { start: at(9, 7), end: Some(at(9, 9)), note: None }).await.unwrap();
+        entries.create(user.id, read.id, NewTimeEntry { start: at(10, 20), end: Some(at(10, 21)), note: None }).await.unwrap();
+        let query = |period, group| ReportQuery { from: at(2, 0), to: at(31, 0), period, group };
+
+        let summary = reports.summary(user.id, query(Some(Period::Week), Some(ReportGroup::Category))).await.unwrap();
+        assert_eq!(summary.rows, vec![
+            row(Some("2025-03-03"), Some("Sport"), 2, 3 * 3600),
+            row(Some("2025-03-10"), None, 1, 3600),
+        ]);
+        assert_eq!((summary.total.activities, summary.total.tracked_seconds), (3, 4 * 3600));
+
+        let summary = reports.summary(user.id, query(None, Some(ReportGroup::Tag))).await.unwrap();
+        assert_eq!(summary.rows, vec![
+            row(None, None, 1, 3600),
+            row(None, Some("morning"), 1, 3600),
+            row(None, Some("outdoor"), 2, 3 * 3600),
+        ]);
+        assert_eq!(summary.total.activities, 3);
+
+        let summary = reports.summary(user.id, query(Some(Period::Month), None)).await.unwrap();
+        assert_eq!(summary.rows, vec![row(Some("2025-03-01"), None, 3, 4 * 3600)]);
+        assert_eq!(summary_csv(&summary), "period,group,activities,tracked_seconds\r\n2025-03-01,,3,14400\r\n");
+
+        assert!(reports.summary(user.id, ReportQuery { from: at(3, 0), to: at(3, 0), period: None, group: None }).await.is_err());
+    }
+    #[tokio::test]
+    async fn should_count_every_occurrence_of_recurring_activities() {
+        let db_config = Arc::new(DbConfig::new("file:report_service_recurrence_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = Arc::new(ActivityService::new(Arc::clone(&db_config)));
+        let reports = ReportService::new(Arc::clone(&db_config), Arc::clone(&activities) as Arc<dyn ActivityApi>);
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let at = |month: u32, day: u32, hour: u32| Utc.with_ymd_and_hms(2025, month, day, hour, 0, 0).unwrap();
+
+        // Tuesdays from February 25, the one of March 11 is skipped
+        let yoga = activities.create(user.id, NewActivity {
+            title: "Yoga".to_owned(),
+            description: None,
+            start: at(2, 25, 18),
+            end: Some(at(2, 25, 19)),
+            category_id: None,
+            tags: vec![],
+            recurrence: Some("FREQ=WEEKLY;COUNT=5".to_owned()),
+            time_zone: None,
+        }).await.unwrap();
+        let skip = NewActivityException { skipped: true, title: None, description: None, start: None, end: None };
+        activities.save_exception(user.id, yoga.id, at(3, 11, 18), skip).await.unwrap();
+
+        let query = ReportQuery { from: at(3, 2, 0), to: at(3, 31, 0), period: Some(Period::Week), group: None };
+        let summary = reports.summary(user.id, query).await.unwrap();
+        assert_eq!(summary.rows, vec![
+            row(Some("2025-03-03"), None, 1, 0),
+            row(Some("2025-03-17"), None, 1, 0),
+            row(Some("2025-03-24"), None, 1, 0),
+        ]);
+        assert_eq!(summary.total.activities, 3);
+    }
+}
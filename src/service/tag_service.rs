@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rusqlite::{Connection, Row};
+
+use crate::{config::db::DbConfig, domain::{tag::{normalize_tag, Tag}, tag_api::TagApi}, error::errors::TagError};
+
+/// Selects from `tags t` with the number of activities
+const TAG_COLUMNS: &str = "t.id, t.name, (SELECT COUNT(*) FROM activity_tags x WHERE x.tag_id = t.id)";
+
+pub struct TagService {
+    db_config: Arc<DbConfig>
+}
+
+impl TagService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+/// Maps a row selected with [TAG_COLUMNS]
+fn map_tag(row: &Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        activity_count: row.get(2)?,
+    })
+}
+
+#[async_trait]
+impl TagApi for TagService {
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<Tag>, TagError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM tags t WHERE t.user_id = ?1 ORDER BY t.name", TAG_COLUMNS))?;
+            let tags = stmt.query_map([user_id], map_tag)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(tags)
+        }).await?
+    }
+
+    async fn rename(&self, user_id: i32, tag_id: i32, name: &str) -> Result<Tag, TagError> {
+        let name = normalize_tag(name).map_err(TagError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+            // goals refer to the tag by name
+            tx.execute("UPDATE goals SET tag = ?1 WHERE user_id = ?3 AND tag = (SELECT name FROM tags WHERE id = ?2 AND user_id = ?3)", (&name, tag_id, user_id))?;
+            let updated = tx.execute("UPDATE tags SET name = ?1 WHERE id = ?2 AND user_id = ?3", (&name, tag_id, user_id))?;
+            if updated == 0 {
+                return Err(TagError::NotFound);
+            }
+
+            let tag = tx.query_row(&format!("SELECT {} FROM tags t WHERE t.id = ?1", TAG_COLUMNS), [tag_id], map_tag)?;
+            tx.commit()?;
+
+            Ok(tag)
+        }).await?
+    }
+
+    async fn delete(&self, user_id: i32, tag_id: i32) -> Result<(), TagError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = Connection::open(db)?;
+            let tx = conn.transaction()?;
+
+            tx.execute("DELETE FROM activity_tags WHERE tag_id IN (SELECT id FROM tags WHERE id = ?1 AND user_id = ?2)", (tag_id, user_id))?;
+            let deleted = tx.execute("DELETE FROM tags WHERE id = ?1 AND user_id = ?2", (tag_id, user_id))?;
+            if deleted == 0 {
+                return Err(TagError::NotFound);
+            }
+            tx.commit()?;
+
+            Ok(())
+        }).await?
+    }
+}
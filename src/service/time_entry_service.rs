@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{Connection, Row};
+
+use crate::{config::db::DbConfig, domain::{time_entry::{NewTimeEntry, TimeEntries, TimeEntry}, time_entry_api::TimeEntryApi}, error::errors::TimeEntryError};
+
+const ENTRY_COLUMNS: &str = "id, activity_id, user_id, start_time, end_time, note";
+
+pub struct TimeEntryService {
+    db_config: Arc<DbConfig>
+}
+
+impl TimeEntryService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+}
+
+/// Maps a row selected with [ENTRY_COLUMNS]
+fn map_entry(row: &Row) -> rusqlite::Result<TimeEntry> {
+    Ok(TimeEntry {
+        id: row.get(0)?,
+        activity_id: row.get(1)?,
+        user_id: row.get(2)?,
+        start: row.get(3)?,
+        end: row.get(4)?,
+        note: row.get(5)?,
+    })
+}
+
+fn query_entry(conn: &Connection, entry_id: i64) -> rusqlite::Result<TimeEntry> {
+    conn.query_row(&format!("SELECT {} FROM time_entries WHERE id = ?1", ENTRY_COLUMNS), [entry_id], map_entry)
+}
+
+/// Foreign activities are reported as not found
+fn check_activity(conn: &Connection, user_id: i32, activity_id: i32) -> Result<(), TimeEntryError> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM activities WHERE id = ?1 AND user_id = ?2", (activity_id, user_id), |row| row.get(0))?;
+    if count == 0 {
+        return Err(TimeEntryError::ActivityNotFound);
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl TimeEntryApi for TimeEntryService {
+    async fn start(&self, user_id: i32, activity_id: i32, note: Option<String>) -> Result<TimeEntry, TimeEntryError> {
+        let entry = NewTimeEntry { start: Utc::now(), end: None, note }.validate().map_err(TimeEntryError::Invalid)?;
+        self.create(user_id, activity_id, entry).await
+    }
+
+    async fn stop(&self, user_id: i32, activity_id: i32) -> Result<TimeEntry, TimeEntryError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_activity(&conn, user_id, activity_id)?;
+
+            let entry_id: i64 = conn.query_row(
+                "UPDATE time_entries SET end_time = ?1 WHERE activity_id = ?2 AND user_id = ?3 AND end_time IS NULL RETURNING id",
+                (Utc::now(), activity_id, user_id),
+                |row| row.get(0)
+            ).map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => TimeEntryError::NotFound,
+                e => e.into(),
+            })?;
+
+            Ok(query_entry(&conn, entry_id)?)
+        }).await?
+    }
+
+    async fn create(&self, user_id: i32, activity_id: i32, entry: NewTimeEntry) -> Result<TimeEntry, TimeEntryError> {
+        let entry = entry.validate().map_err(TimeEntryError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_activity(&conn, user_id, activity_id)?;
+
+            conn.execute(
+                "INSERT INTO time_entries (user_id, activity_id, start_time, end_time, note) values (?1, ?2, ?3, ?4, ?5)",
+                (user_id, activity_id, entry.start, entry.end, entry.note)
+            )?;
+
+            Ok(query_entry(&conn, conn.last_insert_rowid())?)
+        }).await?
+    }
+
+    async fn update(&self, user_id: i32, activity_id: i32, entry_id: i32, entry: NewTimeEntry) -> Result<TimeEntry, TimeEntryError> {
+        let entry = entry.validate().map_err(TimeEntryError::Invalid)?;
+        let db = self.db_config.get_database().to_owned();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_activity(&conn, user_id, activity_id)?;
+
+            let updated = conn.execute(
+                "UPDATE time_entries SET start_time = ?1, end_time = ?2, note = ?3 WHERE id = ?4 AND activity_id = ?5 AND user_id = ?6",
+                (entry.start, entry.end, entry.note, entry_id, activity_id, user_id)
+            )?;
+            if updated == 0 {
+                return Err(TimeEntryError::NotFound);
+            }
+
+            Ok(query_entry(&conn, entry_id.into())?)
+        }).await?
+    }
+
+    async fn delete(&self, user_id: i32, activity_id: i32, entry_id: i32) -> Result<(), TimeEntryError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_activity(&conn, user_id, activity_id)?;
+
+            let deleted = conn.execute("DELETE FROM time_entries WHERE id = ?1 AND activity_id = ?2 AND user_id = ?3", (entry_id, activity_id, user_id))?;
+            if deleted == 0 {
+                return Err(TimeEntryError::NotFound);
+            }
+
+            Ok(())
+        }).await?
+    }
+
+    async fn find_by_activity(&self, user_id: i32, activity_id: i32) -> Result<TimeEntries, TimeEntryError> {
+        let db = self.db_config.get_database().to_owned();
+        let entries = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            check_activity(&conn, user_id, activity_id)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM time_entries WHERE activity_id = ?1 AND user_id = ?2 ORDER BY start_time DESC, id DESC", ENTRY_COLUMNS))?;
+            let entries = stmt.query_map((activity_id, user_id), map_entry)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok::<Vec<TimeEntry>, TimeEntryError>(entries)
+        }).await??;
+
+        Ok(TimeEntries::new(entries, Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{activity::NewActivity, activity_api::ActivityApi, time_entry::NewTimeEntry, time_entry_api::TimeEntryApi, user::User, user_api::UserApi}, error::errors::TimeEntryError, service::{activity_service::ActivityService, user_service::UserService}};
+
+    use super::TimeEntryService;
+
+    fn new_activity(title: &str) -> NewActivity {
+        NewActivity { title: title.to_owned(), description: None, start: Utc::now(), end: None, category_id: None, tags: vec![], recurrence: None, time_zone: None }
+    }
+
+    #[tokio::test]
+    async fn should_allow_one_running_timer_per_user_and_sum_entries() {
+        let db_config = Arc::new(DbConfig::new("file:time_entry_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let activities = ActivityService::new(Arc::clone(&db_config));
+        let entries = TimeEntryService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Linda".to_owned()), "test123").await.unwrap();
+        let write = activities.create(user.id, new_activity("Write")).await.unwrap();
+        let read = activities.create(user.id, new_activity("Read")).await.unwrap();
+        let foreign = activities.create(other.id, new_activity("Run")).await.unwrap();
+
+        let running = entries.start(user.id, write.id, None).await.unwrap();
+        assert!(running.end.is_none());
+        assert!(matches!(entries.start(user.id, read.id, None).await, Err(TimeEntryError::TimerRunning)));
+        assert!(matches!(entries.start(user.id, foreign.id, None).await, Err(TimeEntryError::ActivityNotFound)));
+        entries.start(other.id, foreign.id, None).await.unwrap();
+
+        let now = Utc::now();
+        let manual = NewTimeEntry { start: now - Duration::hours(3), end: Some(now - Duration::hours(2)), note: Some(" draft ".to_owned()) };
+        let created = entries.create(user.id, write.id, manual).await.unwrap();
+        assert_eq!(created.note.as_deref(), Some("draft"));
+        let reopen = NewTimeEntry { start: now - Duration::hours(3), end: None, note: None };
+        assert!(matches!(entries.update(user.id, write.id, created.id, reopen).await, Err(TimeEntryError::TimerRunning)));
+        let backwards = NewTimeEntry { start: now - Duration::hours(1), end: Some(now - Duration::hours(2)), note: None };
+        assert!(matches!(entries.create(user.id, write.id, backwards).await, Err(TimeEntryError::Invalid(_))));
+
+        let stopped = entries.stop(user.id, write.id).await.unwrap();
+        assert_eq!(stopped.id, running.id);
+        assert!(matches!(entries.stop(user.id, write.id).await, Err(TimeEntryError::NotFound)));
+        entries.start(user.id, read.id, None).await.unwrap();
+
+        let totals = entries.find_by_activity(user.id, write.id).await.unwrap();
+        assert_eq!(totals.entries.len(), 2);
+        assert!(totals.total_seconds >= 3600 && totals.total_seconds < 3610);
+
+        entries.delete(user.id, write.id, created.id).await.unwrap();
+        assert!(matches!(entries.delete(user.id, write.id, created.id).await, Err(TimeEntryError::NotFound)));
+
+        // entries are removed with their activity
+        activities.delete(user.id, write.id).await.unwrap();
+        assert!(matches!(entries.find_by_activity(user.id, write.id).await, Err(TimeEntryError::ActivityNotFound)));
+    }
+}
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use actix_web::{cookie::{time, Cookie, CookieJar, Key, SameSite}, http::header::USER_AGENT, HttpRequest};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, Row};
+use sha2::{Digest, Sha256};
+
+use crate::{config::db::DbConfig, domain::{trusted_device::{CreatedTrustedDevice, TrustedDevice}, trusted_device_api::TrustedDeviceApi, user::User}, error::errors::TrustedDeviceError};
+
+pub const TRUSTED_DEVICE_COOKIE: &str = "trusted_device";
+const DEVICE_COLUMNS: &str = "id, user_agent, created_at, expires_at, last_used_at";
+/// Longer user agents are cut, they are only shown to the user
+const MAX_USER_AGENT_LENGTH: usize = 255;
+
+pub struct TrustedDeviceService {
+    db_config: Arc<DbConfig>
+}
+
+impl TrustedDeviceService {
+    pub fn new(db_config: Arc<DbConfig>) -> Self {
+        Self {
+            db_config
+        }
+    }
+
+    fn generate_secret() -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn hash_secret(secret: &str) -> String {
+        format!("{:x}", Sha256::digest(secret.as_bytes()))
+    }
+}
+
+/// Maps a row selected with [DEVICE_COLUMNS]
+fn map_device(row: &Row) -> rusqlite::Result<TrustedDevice> {
+    Ok(TrustedDevice {
+        id: row.get(0)?,
+        user_agent: row.get(1)?,
+        created_at: row.get(2)?,
+        expires_at: row.get(3)?,
+        last_used_at: row.get(4)?,
+    })
+}
+
+#[async_trait]
+impl TrustedDeviceApi for TrustedDeviceService {
+    async fn create(&self, user_id: i32, expires_at: DateTime<Utc>) -> Result<CreatedTrustedDevice, TrustedDeviceError> {
+        let db = self.db_config.get_database().to_owned();
+        let secret = TrustedDeviceService::generate_secret();
+        let hash = TrustedDeviceService::hash_secret(&secret);
+
+        let device = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO trusted_devices (user_id, token_hash, created_at, expires_at) values (?1, ?2, ?3, ?4)",
+                (user_id, hash, Utc::now(), expires_at)
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok::<TrustedDevice, TrustedDeviceError>(conn.query_row(&format!("SELECT {} FROM trusted_devices WHERE id = ?1", DEVICE_COLUMNS), [id], map_device)?)
+        }).await??;
+
+        Ok(CreatedTrustedDevice {
+            device,
+            secret,
+        })
+    }
+
+    async fn authenticate(&self, user_id: i32, secret: &str, user_agent: Option<&str>) -> Result<TrustedDevice, TrustedDeviceError> {
+        let db = self.db_config.get_database().to_owned();
+        let hash = TrustedDeviceService::hash_secret(secret);
+        let user_agent = user_agent.map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect::<String>());
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut device = conn.query_row(
+                &format!("SELECT {} FROM trusted_devices WHERE token_hash = ?1 AND user_id = ?2", DEVICE_COLUMNS),
+                (hash, user_id),
+                map_device
+            )?;
+            if device.expires_at <= Utc::now() {
+                conn.execute("DELETE FROM trusted_devices WHERE id = ?1", [device.id])?;
+                return Err(TrustedDeviceError::Expired);
+            }
+
+            let now = Utc::now();
+            conn.execute("UPDATE trusted_devices SET last_used_at = ?1, user_agent = ?2 WHERE id = ?3", (now, &user_agent, device.id))?;
+            device.last_used_at = Some(now);
+            device.user_agent = user_agent;
+
+            Ok(device)
+        }).await?
+    }
+
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<TrustedDevice>, TrustedDeviceError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM trusted_devices WHERE user_id = ?1 AND expires_at > ?2 ORDER BY id", DEVICE_COLUMNS))?;
+            let devices = stmt.query_map((user_id, Utc::now()), map_device)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(devices)
+        }).await?
+    }
+
+    async fn revoke(&self, user_id: i32, device_id: i32) -> Result<(), TrustedDeviceError> {
+        let db = self.db_config.get_database().to_owned();
+        let deleted = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<usize, TrustedDeviceError>(conn.execute("DELETE FROM trusted_devices WHERE id = ?1 AND user_id = ?2", (device_id, user_id))?)
+        }).await??;
+
+        if deleted == 0 {
+            Err(TrustedDeviceError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn revoke_all(&self, user_id: i32) -> Result<(), TrustedDeviceError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("DELETE FROM trusted_devices WHERE user_id = ?1", [user_id])?;
+            Ok(())
+        }).await?
+    }
+}
+
+/// Issues and checks the signed trusted-device cookie.
+///
+/// The cookie holds `<user id>:<secret>` signed with a key derived from the cookie key of the app.
+/// The signature rejects tampered cookies without a database lookup, revocation works through the stored hash.
+pub struct TrustedDeviceCookie {
+    trusted_device_api: Arc<dyn TrustedDeviceApi>,
+    key: Key,
+    days: u32,
+}
+
+impl TrustedDeviceCookie {
+    pub fn new(trusted_device_api: Arc<dyn TrustedDeviceApi>, cookie_key: &Key, days: u32) -> Self {
+        Self {
+            trusted_device_api,
+            key: Key::derive_from(cookie_key.master()),
+            days,
+        }
+    }
+
+    /// Returns true if the request carries a valid, not revoked cookie of this user
+    pub async fn is_trusted(&self, user: &User, req: &HttpRequest) -> bool {
+        let cookie = match req.cookie(TRUSTED_DEVICE_COOKIE) {
+            Some(cookie) => cookie,
+            None => return false,
+        };
+
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie);
+        let value = match jar.signed(&self.key).get(TRUSTED_DEVICE_COOKIE) {
+            Some(verified) => verified.value().to_owned(),
+            None => {
+                log::warn!("Trusted device cookie with invalid signature for user {}", user.id);
+                return false;
+            },
+        };
+
+        let secret = match value.split_once(':') {
+            Some((user_id, secret)) if user_id == user.id.to_string() => secret,
+            _ => return false,
+        };
+
+        let user_agent = req.headers().get(USER_AGENT).and_then(|ua| ua.to_str().ok());
+        match self.trusted_device_api.authenticate(user.id, secret, user_agent).await {
+            Ok(_) => true,
+            Err(TrustedDeviceError::NotFound) | Err(TrustedDeviceError::Expired) => false,
+            Err(e) => {
+                log::error!("Cannot check trusted device: {}", e);
+                false
+            },
+        }
+    }
+
+    /// Creates a new trusted device for the user and returns its cookie
+    pub async fn issue(&self, user: &User) -> Result<Cookie<'static>, TrustedDeviceError> {
+        let expires_at = Utc::now() + Duration::days(self.days.into());
+        let created = self.trusted_device_api.create(user.id, expires_at).await?;
+        log::info!("Trusted device {} created for user {}", created.device.id, user.id);
+
+        let cookie = Cookie::build(TRUSTED_DEVICE_COOKIE, format!("{}:{}", user.id, created.secret))
+            .path("/api/login")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Strict)
+            .max_age(time::Duration::days(self.days.into()))
+            .finish();
+
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&self.key).add(cookie);
+
+        jar.get(TRUSTED_DEVICE_COOKIE)
+            .cloned()
+            .ok_or_else(|| TrustedDeviceError::Database("Cannot sign trusted device cookie".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use crate::{config::db::DbConfig, create_db, domain::{trusted_device_api::TrustedDeviceApi, user::User, user_api::UserApi}, error::errors::TrustedDeviceError, service::user_service::UserService};
+
+    use super::TrustedDeviceService;
+
+    #[tokio::test]
+    async fn should_authenticate_device_until_revoked_or_expired() {
+        let db_config = Arc::new(DbConfig::new("file:trusted_device_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let devices = TrustedDeviceService::new(Arc::clone(&db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+
+        let created = devices.create(user.id, Utc::now() + Duration::days(1)).await.unwrap();
+        let used = devices.authenticate(user.id, &created.secret, Some("Firefox")).await.unwrap();
+        let expired = devices.create(user.id, Utc::now() - Duration::seconds(1)).await.unwrap();
+
+        assert_eq!(used.user_agent.as_deref(), Some("Firefox"));
+        assert!(matches!(devices.authenticate(user.id + 1, &created.secret, None).await, Err(TrustedDeviceError::NotFound)));
+        assert!(matches!(devices.authenticate(user.id, &expired.secret, None).await, Err(TrustedDeviceError::Expired)));
+        assert_eq!(devices.find_by_user_id(user.id).await.unwrap().len(), 1);
+
+        devices.revoke(user.id, created.device.id).await.unwrap();
+        assert!(matches!(devices.authenticate(user.id, &created.secret, None).await, Err(TrustedDeviceError::NotFound)));
+    }
+}
@@ -1,34 +1,74 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
-use argon2::{password_hash::{rand_core::OsRng, SaltString}, Argon2, PasswordHasher};
+use argon2::{password_hash::{rand_core::OsRng, SaltString}, Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
 use async_trait::async_trait;
 use authfix::multifactor::factor_impl::authenticator::{GetTotpSecretError, TotpSecretRepository};
-use rusqlite::Connection;
+use rusqlite::{params_from_iter, types::{Type, Value}, Connection, Row};
 
-use crate::{config::db::DbConfig, domain::{user::{Credentials, Mfa, User}, user_api::UserApi}, error::errors::{QueryUserError, UserUpdateError}};
+use crate::{config::{config::PasswordHashConfig, db::DbConfig}, domain::{page::{Cursor, Page, PageRequest}, user::{Credentials, Mfa, PasswordHashReport, Role, User, UserOverview, UserSort}, user_api::UserApi}, error::errors::{QueryUserError, UserUpdateError}};
 
 pub struct UserService {
-    db_config: Arc<DbConfig>
+    db_config: Arc<DbConfig>,
+    hash_params: Params,
 }
 
 impl UserService {
+    /// Hashes new passwords with the argon2 default parameters
     pub fn new(db_config: Arc<DbConfig>) -> Self {
         Self {
-            db_config
+            db_config,
+            hash_params: Params::default(),
         }
     }
 
+    /// Panics if the parameters are out of the range argon2 accepts, they come from the configuration
+    pub fn with_password_hash_config(mut self, config: &PasswordHashConfig) -> Self {
+        self.hash_params = Params::new(config.memory_kib, config.iterations, config.parallelism, None)
+            .unwrap_or_else(|e| panic!("Invalid argon2 parameters: {}", e));
+        self
+    }
+
     /// Utility method for password hashing
-    pub fn hash_password(password: &str) -> Result<String, UserUpdateError> {
+    pub fn hash_password(params: &Params, password: &str) -> Result<String, UserUpdateError> {
         let salt = SaltString::generate(&mut OsRng);
 
-        Ok(Argon2::default()
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
             .hash_password(password.as_bytes(), &salt)
-            .map_err(|_| UserUpdateError::new("Cannot hash password"))?
+            .map_err(|e| UserUpdateError::Hashing(e.to_string()))?
             .to_string())
     }
 }
 
+/// True if the hash is not argon2id or uses weaker parameters than `params`.
+/// Unreadable hashes count as outdated, they are replaced once the password is known.
+fn is_outdated_hash(hash: &str, params: &Params) -> bool {
+    let hash = match PasswordHash::new(hash) {
+        Ok(hash) => hash,
+        Err(_) => return true,
+    };
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+
+    match Params::try_from(&hash) {
+        Ok(used) => used.m_cost() < params.m_cost() || used.t_cost() < params.t_cost() || used.p_cost() < params.p_cost(),
+        Err(_) => true,
+    }
+}
+
+const USER_COLUMNS: &str = "id, name, email, role, locked";
+
+/// Maps a row selected with [USER_COLUMNS]
+fn map_user(row: &Row) -> rusqlite::Result<User> {
+    let role: String = row.get(3)?;
+    let role = Role::from_str(&role)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+
+    let mut user = User::new(row.get(0)?, row.get(2)?, row.get(1)?).with_role(role);
+    user.locked = row.get(4)?;
+    Ok(user)
+}
+
 impl From<QueryUserError> for GetTotpSecretError {
     fn from(value: QueryUserError) -> Self {
         GetTotpSecretError::new(&format!("Query user error: {}", value))
@@ -59,13 +99,11 @@ impl TotpSecretRepository for UserService {
 impl UserApi for UserService {
     async fn find_by_email(&self, email: &str) -> Result<User, QueryUserError> {
         let db = self.db_config.get_database().to_owned();
-        let owned_email = email.to_owned();
+        let owned_email = email.trim().to_owned();
         tokio::task::spawn_blocking(move || {
             let conn = Connection::open(db)?;
 
-            Ok(conn.query_row("SELECT id, name, email FROM users WHERE email = ?1", [owned_email], |row| {
-                Ok(User::new(row.get(0)?, row.get(1)?, row.get(2)?))
-            })?)
+            Ok(conn.query_row(&format!("SELECT {} FROM users WHERE email = ?1 COLLATE NOCASE", USER_COLUMNS), [owned_email], map_user)?)
         }).await?
     }
 
@@ -74,34 +112,37 @@ impl UserApi for UserService {
         tokio::task::spawn_blocking(move || {
             let conn = Connection::open(db)?;
 
-            Ok(conn.query_row("SELECT id, name, email FROM users WHERE id = ?1", [user_id], |row| {
-                Ok(User::new(row.get(0)?, row.get(1)?, row.get(2)?))
-            })?)
+            Ok(conn.query_row(&format!("SELECT {} FROM users WHERE id = ?1", USER_COLUMNS), [user_id], map_user)?)
         }).await?
     
     }
 
     /// Takes in plain text password
     async fn save_user_with_credentials(&self, user: User, password: &str) -> Result<User, UserUpdateError> {
+        if password.is_empty() {
+            return Err(UserUpdateError::Invalid("Password must not be empty".to_owned()));
+        }
+
         let db = self.db_config.get_database().to_owned();
         let owned_pass = password.to_owned();
         
+        let params = self.hash_params.clone();
         let user_id = tokio::task::spawn_blocking(move || {           
-            let hashed_password = UserService::hash_password(&owned_pass)?;
+            let hashed_password = UserService::hash_password(&params, &owned_pass)?;
             let mut conn = Connection::open(db)?;
 
             let tx = conn.transaction()?;
 
             let mut user_id = user.id;
             if user_id > 0 {
-                let update_user = "UPDATE users SET name = ?1, email =?2 WHERE id = ?3";
+                let update_user = "UPDATE users SET name = ?1, email =?2, role = ?3, locked = ?4 WHERE id = ?5";
                 let update_creds = "UPDATE credentials SET password = ?1 WHERE user_id = ?2";
-                tx.execute(update_user, (user.name, user.email, user.id))?;
+                tx.execute(update_user, (user.name, user.email, user.role.as_str(), user.locked, user.id))?;
                 tx.execute(update_creds, (hashed_password.to_string(), user.id))?;
             } else {
-                let insert_user = "INSERT INTO users (name, email) values(?1, ?2)";
+                let insert_user = "INSERT INTO users (name, email, role, locked) values(?1, ?2, ?3, ?4)";
                 let insert_creds = "INSERT INTO credentials (password, user_id) values(?1, ?2)";
-                tx.execute(insert_user, (user.name, user.email))?;
+                tx.execute(insert_user, (user.name, user.email, user.role.as_str(), user.locked))?;
 
                 user_id = tx.last_insert_rowid() as i32;
                 tx.execute(insert_creds, (hashed_password.to_string(), user_id))?;
@@ -114,30 +155,108 @@ impl UserApi for UserService {
 
         let user = self.find_by_id(user_id)
             .await
-            .map_err(|_| UserUpdateError::new("Unable to retrieve user after update"))?;
+            .map_err(|e| UserUpdateError::Database(format!("Unable to retrieve user after update: {}", e)))?;
 
 
         Ok(user)
         
     }
 
+    async fn update_password(&self, user_id: i32, password: &str) -> Result<(), UserUpdateError> {
+        if password.is_empty() {
+            return Err(UserUpdateError::Invalid("Password must not be empty".to_owned()));
+        }
+
+        let db = self.db_config.get_database().to_owned();
+        let owned_pass = password.to_owned();
+        let params = self.hash_params.clone();
+        let updated = tokio::task::spawn_blocking(move || {
+            let hashed_password = UserService::hash_password(&params, &owned_pass)?;
+            let mut conn = Connection::open(db)?;
+
+            let tx = conn.transaction()?;
+            let updated = tx.execute("UPDATE credentials SET password = ?1 WHERE user_id = ?2", (hashed_password, user_id))?;
+            tx.execute("DELETE FROM api_tokens WHERE user_id = ?1", [user_id])?;
+            tx.execute("DELETE FROM trusted_devices WHERE user_id = ?1", [user_id])?;
+            tx.commit()?;
+
+            Ok::<usize, UserUpdateError>(updated)
+        }).await??;
+
+        if updated == 0 {
+            Err(UserUpdateError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn update_email(&self, user_id: i32, email: &str) -> Result<User, UserUpdateError> {
+        let db = self.db_config.get_database().to_owned();
+        let email = email.to_owned();
+        let updated = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<usize, UserUpdateError>(conn.execute("UPDATE users SET email = ?1 WHERE id = ?2", (email, user_id))?)
+        }).await??;
+
+        if updated == 0 {
+            return Err(UserUpdateError::NotFound);
+        }
+
+        self.find_by_id(user_id)
+            .await
+            .map_err(|e| UserUpdateError::Database(format!("Unable to retrieve user after update: {}", e)))
+    }
+
+    async fn upgrade_password_hash(&self, user_id: i32, password: &str) -> Result<bool, UserUpdateError> {
+        let db = self.db_config.get_database().to_owned();
+        let owned_pass = password.to_owned();
+        let params = self.hash_params.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            let current: String = conn.query_row("SELECT password FROM credentials WHERE user_id = ?1", [user_id], |row| row.get(0))
+                .map_err(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => UserUpdateError::NotFound,
+                    _ => e.into(),
+                })?;
+            if !is_outdated_hash(&current, &params) {
+                return Ok(false);
+            }
+
+            // only replace the hash that was verified, a concurrent password change wins
+            let hashed_password = UserService::hash_password(&params, &owned_pass)?;
+            let updated = conn.execute("UPDATE credentials SET password = ?1 WHERE user_id = ?2 AND password = ?3", (hashed_password, user_id, current))?;
+            Ok(updated > 0)
+        }).await?
+    }
+
+    async fn password_hash_report(&self) -> Result<PasswordHashReport, QueryUserError> {
+        let db = self.db_config.get_database().to_owned();
+        let params = self.hash_params.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare("SELECT password FROM credentials")?;
+            let hashes = stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            let outdated = hashes.iter().filter(|hash| is_outdated_hash(hash, &params)).count();
+
+            Ok(PasswordHashReport {
+                current: hashes.len() - outdated,
+                outdated,
+            })
+        }).await?
+    }
+
     /// Expects that password is already hashed
     async fn save_credentials(&self, credentials: Credentials) -> Result<Credentials, UserUpdateError> {
         if credentials.user_id == 0 {
-            Err(UserUpdateError::new("Cannot save credentials if user_id is 0"))
+            Err(UserUpdateError::Invalid("Cannot save credentials if user_id is 0".to_owned()))
         } else {
             let db = self.db_config.get_database().to_owned();
 
             let mfa_config = match credentials.mfa_config {
-                Some(mfa_config) => {
-                    let secret = match mfa_config.secret {
-                        Some(secret) => secret,
-                        None => "null".to_owned(),
-                    };
-
-                    (mfa_config.mfa_id, secret)
-                },
-                None => todo!(),
+                Some(mfa_config) => (Some(mfa_config.mfa_id), mfa_config.secret),
+                None => (None, None),
             };
 
             let command = match credentials.id > 0 {
@@ -156,12 +275,93 @@ impl UserApi for UserService {
 
             match exec {
                 Ok(_) => self.find_credentials_by_user_id(credentials.user_id).await
-                .map_err(|e| UserUpdateError::new(&format!("Cannot load credentials after save: {}", e))),
-                Err(e) => Err(UserUpdateError::new(&format!("Cannot insert or update credentials: {}", e))),
+                .map_err(|e| UserUpdateError::Database(format!("Cannot load credentials after save: {}", e))),
+                Err(e) => Err(e.into()),
             }
         }   
     }
 
+    async fn find_overview(&self, page: &PageRequest<UserSort>) -> Result<Page<UserOverview>, QueryUserError> {
+        let sort = match page.sort {
+            UserSort::Id => "u.id",
+            UserSort::Name => "COALESCE(u.name, '') COLLATE NOCASE",
+            UserSort::Email => "COALESCE(u.email, '') COLLATE NOCASE",
+        };
+        let mut params: Vec<Value> = Vec::new();
+        let mut condition = String::new();
+        if let Some(cursor) = &page.cursor {
+            params.push(cursor.key.clone().into());
+            params.push(cursor.id.into());
+            condition = format!("WHERE {0} {1} ?1 OR ({0} = ?1 AND u.id {1} ?2)", sort, page.operator());
+        }
+        let sql = format!(
+            "SELECT u.id, u.name, u.email, u.role, u.locked, c.mfa_id IS NOT NULL, CAST({0} AS TEXT) FROM users u LEFT JOIN credentials c ON c.user_id = u.id \
+                {1} ORDER BY {0} {2}, u.id {2} LIMIT {3}",
+            sort, condition, page.direction(), page.limit + 1
+        );
+        let limit = page.limit;
+
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&sql)?;
+            let users = stmt.query_map(params_from_iter(params), |row| {
+                let overview = UserOverview {
+                    user: map_user(row)?,
+                    mfa_enabled: row.get(5)?,
+                };
+                let cursor = Cursor { key: row.get(6)?, id: overview.user.id };
+                Ok((overview, cursor))
+            })?.collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Page::new(users, limit))
+        }).await?
+    }
+
+    async fn set_locked(&self, user_id: i32, locked: bool) -> Result<User, UserUpdateError> {
+        let db = self.db_config.get_database().to_owned();
+        let changed = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<usize, UserUpdateError>(conn.execute("UPDATE users SET locked = ?1 WHERE id = ?2", (locked, user_id))?)
+        }).await??;
+
+        if changed == 0 {
+            return Err(UserUpdateError::NotFound);
+        }
+
+        self.find_by_id(user_id)
+            .await
+            .map_err(|e| UserUpdateError::Database(format!("Unable to retrieve user after update: {}", e)))
+    }
+
+    async fn find_by_external_identity(&self, issuer: &str, subject: &str) -> Result<User, QueryUserError> {
+        let db = self.db_config.get_database().to_owned();
+        let owned_issuer = issuer.to_owned();
+        let owned_subject = subject.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            Ok(conn.query_row(
+                "SELECT u.id, u.name, u.email, u.role, u.locked FROM users u JOIN external_identities e ON e.user_id = u.id WHERE e.issuer = ?1 AND e.subject = ?2",
+                [owned_issuer, owned_subject],
+                map_user
+            )?)
+        }).await?
+    }
+
+    async fn link_external_identity(&self, user_id: i32, issuer: &str, subject: &str) -> Result<(), UserUpdateError> {
+        let db = self.db_config.get_database().to_owned();
+        let owned_issuer = issuer.to_owned();
+        let owned_subject = subject.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("INSERT INTO external_identities (issuer, subject, user_id) values (?1, ?2, ?3)", (owned_issuer, owned_subject, user_id))?;
+
+            Ok(())
+        }).await?
+    }
+
     async fn find_credentials_by_user_id(&self, user_id: i32) -> Result<Credentials, QueryUserError> {
         let db = self.db_config.get_database().to_owned();
         tokio::task::spawn_blocking(move || {
@@ -195,8 +395,11 @@ impl UserApi for UserService {
 mod user_service_tests {
     use std::sync::Arc;
 
-    use crate::{config::db::DbConfig, create_db, domain::{user::{Mfa, User}, user_api::UserApi}, service::user_service::UserService};
+    use crate::{config::{config::PasswordHashConfig, db::DbConfig}, create_db, domain::{page::{Cursor, Page, PageRequest, SortOrder}, user::{Mfa, PasswordHashReport, Role, User, UserOverview, UserSort}, user_api::UserApi}, error::errors::{QueryUserError, UserUpdateError}, service::user_service::UserService};
 
+    fn page(sort: UserSort, order: SortOrder, cursor: Option<Cursor>) -> PageRequest<UserSort> {
+        PageRequest { sort, order, cursor, limit: 20 }
+    }
 
     #[tokio::test]
     async fn should_be_able_to_save_credentials() {
@@ -225,4 +428,110 @@ mod user_service_tests {
         assert_eq!(mfa_config.secret.unwrap(), "asecret");
     }
 
+    #[tokio::test]
+    async fn should_return_not_found_when_user_does_not_exist() {
+        let db_config = DbConfig::new("file:user_service_not_found_test?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::new(db_config));
+
+        let result = user_service.find_by_email("nobody@example.org").await;
+
+        assert!(matches!(result, Err(QueryUserError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_find_user_by_email_ignoring_case() {
+        let db_config = DbConfig::new("file:user_service_email_case_test?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::new(db_config));
+        let user = user_service.save_user_with_credentials(User::new(0, "Test@Example.org".to_owned(), "Test User".to_owned()), "secretpassword").await.unwrap();
+
+        assert_eq!(user_service.find_by_email(" test@example.ORG ").await.unwrap().id, user.id);
+    }
+
+    #[tokio::test]
+    async fn should_return_conflict_when_email_already_exists() {
+        let db_config = DbConfig::new("file:user_service_conflict_test?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::new(db_config));
+        let user = User::new(0, "test@example.org".to_owned(), "Test User".to_owned());
+        user_service.save_user_with_credentials(user, "secretpassword").await.unwrap();
+
+        let duplicate = User::new(0, "test@example.org".to_owned(), "Other User".to_owned());
+        let result = user_service.save_user_with_credentials(duplicate, "secretpassword").await;
+
+        assert!(matches!(result, Err(UserUpdateError::Conflict(_))));
+
+        let variant = User::new(0, "Test@Example.ORG".to_owned(), "Other User".to_owned());
+        let result = user_service.save_user_with_credentials(variant, "secretpassword").await;
+        assert!(matches!(result, Err(UserUpdateError::Conflict(message)) if message == "Email address is already in use"));
+
+        let other = user_service.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Other User".to_owned()), "secretpassword").await.unwrap();
+        let result = user_service.update_email(other.id, "TEST@example.org").await;
+        assert!(matches!(result, Err(UserUpdateError::Conflict(message)) if message == "Email address is already in use"));
+    }
+
+    #[tokio::test]
+    async fn should_lock_user_and_reset_mfa() {
+        let db_config = DbConfig::new("file:user_service_lock_test?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::new(db_config));
+        let user = User::new(0, "test@example.org".to_owned(), "Test User".to_owned()).with_role(Role::Admin);
+        let saved_user = user_service.save_user_with_credentials(user, "secretpassword").await.unwrap();
+        let mut creds = user_service.find_credentials_by_user_id(saved_user.id).await.unwrap();
+        creds.set_mfa(Mfa::with_secret("MFA_ID", "asecret"));
+        let mut creds = user_service.save_credentials(creds).await.unwrap();
+
+        let locked = user_service.set_locked(saved_user.id, true).await.unwrap();
+        creds.mfa_config = None;
+        user_service.save_credentials(creds).await.unwrap();
+
+        assert!(locked.locked);
+        assert_eq!(locked.role, Role::Admin);
+        let overview = user_service.find_overview(&page(UserSort::Id, SortOrder::Asc, None)).await.unwrap();
+        assert_eq!(overview.items.len(), 1);
+        assert!(!overview.items[0].mfa_enabled);
+        assert!(overview.next_cursor.is_none());
+        assert!(matches!(user_service.set_locked(4711, true).await, Err(UserUpdateError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn should_page_user_overview_in_sort_order() {
+        let db_config = DbConfig::new("file:user_service_page_test?mode=memory&cache=shared");
+        let _db = create_db(&db_config);
+        let user_service = UserService::new(Arc::new(db_config));
+        for (email, name) in [("c@example.org", "bert"), ("a@example.org", "Carla"), ("b@example.org", "Anna")] {
+            user_service.save_user_with_credentials(User::new(0, email.to_owned(), name.to_owned()), "secretpassword").await.unwrap();
+        }
+        let names = |page: &Page<UserOverview>| page.items.iter().map(|u| u.user.name.clone()).collect::<Vec<_>>();
+
+        let first = user_service.find_overview(&PageRequest { limit: 2, ..page(UserSort::Name, SortOrder::Asc, None) }).await.unwrap();
+        assert_eq!(names(&first), vec!["Anna", "bert"]);
+        let second = user_service.find_overview(&PageRequest { limit: 2, ..page(UserSort::Name, SortOrder::Asc, first.next_cursor) }).await.unwrap();
+        assert_eq!(names(&second), vec!["Carla"]);
+        assert!(second.next_cursor.is_none());
+
+        let first = user_service.find_overview(&PageRequest { limit: 1, ..page(UserSort::Id, SortOrder::Desc, None) }).await.unwrap();
+        assert_eq!(names(&first), vec!["Anna"]);
+        let second = user_service.find_overview(&page(UserSort::Id, SortOrder::Desc, first.next_cursor)).await.unwrap();
+        assert_eq!(names(&second), vec!["Carla", "bert"]);
+    }
+
+    #[tokio::test]
+    async fn should_upgrade_hashes_with_weaker_parameters() {
+        let db_config = Arc::new(DbConfig::new("file:user_service_rehash_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let weak = UserService::new(Arc::clone(&db_config))
+            .with_password_hash_config(&PasswordHashConfig { memory_kib: 8, iterations: 1, parallelism: 1 });
+        let strong = UserService::new(Arc::clone(&db_config));
+        let user = weak.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Test User".to_owned()), "secretpassword").await.unwrap();
+        strong.save_user_with_credentials(User::new(0, "other@example.org".to_owned(), "Other User".to_owned()), "secretpassword").await.unwrap();
+
+        assert_eq!(strong.password_hash_report().await.unwrap(), PasswordHashReport { current: 1, outdated: 1 });
+        assert!(!weak.upgrade_password_hash(user.id, "secretpassword").await.unwrap());
+        assert!(strong.upgrade_password_hash(user.id, "secretpassword").await.unwrap());
+        assert!(!strong.upgrade_password_hash(user.id, "secretpassword").await.unwrap());
+        assert_eq!(strong.password_hash_report().await.unwrap(), PasswordHashReport { current: 2, outdated: 0 });
+    }
+
 }
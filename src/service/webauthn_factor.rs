@@ -0,0 +1,78 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use authfix::multifactor::factor::{CheckCodeError, Factor, GenerateCodeError};
+
+use crate::{domain::{user::User, webauthn::{AssertionResponse, RequestOptions, MFA_ID_WEBAUTHN}, webauthn_api::WebauthnApi}, error::errors::WebauthnError, service::auth_service::AUTHFIX_SESSION_KEY_USER};
+
+/// Session key of the pending assertion options, served by `GET /api/webauthn/login-options`
+pub const SESSION_KEY_WEBAUTHN_LOGIN: &str = "webauthn_login";
+
+/// WebAuthn as authfix MFA factor.
+///
+/// `generate_code` creates the assertion challenge and keeps it in the session, the browser fetches it
+/// from the login options endpoint. The signed assertion is sent as JSON string in the `code` field
+/// of the usual MFA request.
+pub struct WebauthnFactor {
+    webauthn_api: Arc<dyn WebauthnApi>,
+}
+
+impl WebauthnFactor {
+    pub fn new(webauthn_api: Arc<dyn WebauthnApi>) -> Self {
+        Self {
+            webauthn_api,
+        }
+    }
+}
+
+impl Factor for WebauthnFactor {
+    fn generate_code(&self, req: &HttpRequest) -> Pin<Box<dyn Future<Output = Result<(), GenerateCodeError>>>> {
+        let session = req.get_session();
+        let webauthn_api = Arc::clone(&self.webauthn_api);
+
+        Box::pin(async move {
+            let user = session.get::<User>(AUTHFIX_SESSION_KEY_USER)
+                .map_err(|e| GenerateCodeError::new_with_cause("Cannot read user from session", e))?
+                .ok_or_else(|| GenerateCodeError::new("No user in session"))?;
+
+            let options = webauthn_api.start_authentication(user.id).await
+                .map_err(|e| GenerateCodeError::new_with_cause("Cannot create WebAuthn challenge", e))?;
+            if options.allow_credentials.is_empty() {
+                return Err(GenerateCodeError::new(&format!("User {} has no security key registered", user.id)));
+            }
+
+            session.insert(SESSION_KEY_WEBAUTHN_LOGIN, options)
+                .map_err(|e| GenerateCodeError::new_with_cause("Cannot store WebAuthn challenge", e))
+        })
+    }
+
+    fn unique_id(&self) -> String {
+        MFA_ID_WEBAUTHN.to_owned()
+    }
+
+    fn check_code(&self, code: &str, req: &HttpRequest) -> Pin<Box<dyn Future<Output = Result<(), CheckCodeError>>>> {
+        let session = req.get_session();
+        let webauthn_api = Arc::clone(&self.webauthn_api);
+        let response = serde_json::from_str::<AssertionResponse>(code);
+
+        Box::pin(async move {
+            let response = response.map_err(|_| CheckCodeError::InvalidCode)?;
+            let user = session.get::<User>(AUTHFIX_SESSION_KEY_USER)
+                .map_err(|e| CheckCodeError::UnknownError(e.to_string()))?
+                .ok_or_else(|| CheckCodeError::UnknownError("No user in session".to_owned()))?;
+            let options = session.get::<RequestOptions>(SESSION_KEY_WEBAUTHN_LOGIN)
+                .map_err(|e| CheckCodeError::UnknownError(e.to_string()))?
+                .ok_or_else(|| CheckCodeError::UnknownError("No WebAuthn challenge in session".to_owned()))?;
+
+            match webauthn_api.finish_authentication(user.id, &options.challenge, response).await {
+                Ok(_) => {
+                    session.remove(SESSION_KEY_WEBAUTHN_LOGIN);
+                    Ok(())
+                },
+                Err(WebauthnError::NotFound) | Err(WebauthnError::InvalidResponse(_)) => Err(CheckCodeError::InvalidCode),
+                Err(e) => Err(CheckCodeError::UnknownError(e.to_string())),
+            }
+        })
+    }
+}
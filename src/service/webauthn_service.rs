@@ -0,0 +1,510 @@
+use std::sync::Arc;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use ciborium::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rusqlite::{Connection, Row};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::{config::WebauthnConfig, db::DbConfig}, domain::{user::User, webauthn::{AssertionResponse, AuthenticatorSelection, CreationOptions, CredentialDescriptor, PublicKeyParameters, RegistrationResponse, RelyingParty, RequestOptions, UserEntity, WebauthnCredential}, webauthn_api::WebauthnApi}, error::errors::WebauthnError};
+
+const CREDENTIAL_COLUMNS: &str = "id, name, credential_id, public_key, sign_count, created_at, last_used_at";
+const CEREMONY_TIMEOUT_MS: u32 = 120_000;
+const USER_VERIFICATION: &str = "preferred";
+/// COSE algorithm identifier of ES256, the only algorithm supported
+const COSE_ALG_ES256: i32 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+pub struct WebauthnService {
+    db_config: Arc<DbConfig>,
+    config: WebauthnConfig,
+}
+
+impl WebauthnService {
+    pub fn new(db_config: Arc<DbConfig>, config: WebauthnConfig) -> Self {
+        Self {
+            db_config,
+            config,
+        }
+    }
+
+    fn verify_client_data(&self, client_data_json: &[u8], ceremony: &str, challenge: &str) -> Result<(), WebauthnError> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)
+            .map_err(|e| WebauthnError::InvalidResponse(format!("Cannot parse client data: {}", e)))?;
+
+        if client_data.ceremony != ceremony {
+            return Err(WebauthnError::InvalidResponse(format!("Expected ceremony '{}' but got '{}'", ceremony, client_data.ceremony)));
+        }
+
+        if client_data.challenge.trim_end_matches('=') != challenge {
+            return Err(WebauthnError::InvalidResponse("Challenge does not match".to_owned()));
+        }
+
+        if client_data.origin != self.config.origin {
+            return Err(WebauthnError::InvalidResponse(format!("Unexpected origin '{}'", client_data.origin)));
+        }
+
+        Ok(())
+    }
+
+    fn verify_authenticator_data<'a>(&self, data: &'a [u8]) -> Result<AuthenticatorData<'a>, WebauthnError> {
+        let auth_data = AuthenticatorData::parse(data)?;
+
+        if auth_data.rp_id_hash != Sha256::digest(self.config.rp_id.as_bytes()).as_slice() {
+            return Err(WebauthnError::InvalidResponse("Key was created for another relying party".to_owned()));
+        }
+
+        if auth_data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebauthnError::InvalidResponse("User was not present".to_owned()));
+        }
+
+        Ok(auth_data)
+    }
+}
+
+/// The client data as collected by the browser
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Parsed authenticator data, see https://www.w3.org/TR/webauthn-2/#sctn-authenticator-data
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    attested_credential: &'a [u8],
+}
+
+impl<'a> AuthenticatorData<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, WebauthnError> {
+        if data.len() < 37 {
+            return Err(WebauthnError::InvalidResponse("Authenticator data is too short".to_owned()));
+        }
+
+        Ok(Self {
+            rp_id_hash: &data[..32],
+            flags: data[32],
+            sign_count: u32::from_be_bytes([data[33], data[34], data[35], data[36]]),
+            attested_credential: &data[37..],
+        })
+    }
+
+    /// Returns the credential id and the public key as uncompressed SEC1 point
+    fn credential(&self) -> Result<(Vec<u8>, Vec<u8>), WebauthnError> {
+        if self.flags & FLAG_ATTESTED_CREDENTIAL == 0 || self.attested_credential.len() < 18 {
+            return Err(WebauthnError::InvalidResponse("Authenticator data contains no credential".to_owned()));
+        }
+
+        // 16 bytes AAGUID followed by the length of the credential id
+        let data = self.attested_credential;
+        let id_len = u16::from_be_bytes([data[16], data[17]]) as usize;
+        if data.len() < 18 + id_len {
+            return Err(WebauthnError::InvalidResponse("Credential id is truncated".to_owned()));
+        }
+
+        let credential_id = data[18..18 + id_len].to_vec();
+        let cose_key: Value = ciborium::from_reader(&data[18 + id_len..])
+            .map_err(|e| WebauthnError::InvalidResponse(format!("Cannot parse public key: {}", e)))?;
+
+        Ok((credential_id, cose_key_to_sec1(&cose_key)?))
+    }
+}
+
+fn cbor_map_get(map: &Value, key: Value) -> Option<&Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+fn cbor_integer(value: Option<&Value>) -> Option<i128> {
+    value.and_then(|v| v.as_integer()).map(i128::from)
+}
+
+/// Converts an EC2 P-256 COSE key into an uncompressed SEC1 point
+fn cose_key_to_sec1(key: &Value) -> Result<Vec<u8>, WebauthnError> {
+    let kty = cbor_integer(cbor_map_get(key, Value::from(1)));
+    let alg = cbor_integer(cbor_map_get(key, Value::from(3)));
+    let crv = cbor_integer(cbor_map_get(key, Value::from(-1)));
+    if kty != Some(2) || alg != Some(COSE_ALG_ES256.into()) || crv != Some(1) {
+        return Err(WebauthnError::InvalidResponse("Only ES256 keys are supported".to_owned()));
+    }
+
+    let x = cbor_map_get(key, Value::from(-2)).and_then(|v| v.as_bytes());
+    let y = cbor_map_get(key, Value::from(-3)).and_then(|v| v.as_bytes());
+    let (x, y) = match (x, y) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => (x, y),
+        _ => return Err(WebauthnError::InvalidResponse("Invalid EC2 key coordinates".to_owned())),
+    };
+
+    let mut sec1 = Vec::with_capacity(65);
+    sec1.push(0x04);
+    sec1.extend_from_slice(x);
+    sec1.extend_from_slice(y);
+
+    VerifyingKey::from_sec1_bytes(&sec1)
+        .map_err(|_| WebauthnError::InvalidResponse("Public key is not on the curve".to_owned()))?;
+
+    Ok(sec1)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, WebauthnError> {
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))
+        .map_err(|_| WebauthnError::InvalidResponse(format!("{} is not base64url encoded", field)))
+}
+
+/// Challenges are single use and random, 32 bytes like the other secrets of this app
+fn new_challenge() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Maps a row selected with [CREDENTIAL_COLUMNS]
+fn map_credential(row: &Row) -> rusqlite::Result<WebauthnCredential> {
+    Ok(WebauthnCredential {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        credential_id: row.get(2)?,
+        public_key: row.get(3)?,
+        sign_count: row.get(4)?,
+        created_at: row.get(5)?,
+        last_used_at: row.get(6)?,
+    })
+}
+
+#[async_trait]
+impl WebauthnApi for WebauthnService {
+    async fn start_registration(&self, user: &User) -> Result<CreationOptions, WebauthnError> {
+        let exclude_credentials = self.find_by_user_id(user.id).await?
+            .iter()
+            .map(|c| CredentialDescriptor::public_key(&c.credential_id))
+            .collect();
+
+        Ok(CreationOptions {
+            challenge: new_challenge(),
+            rp: RelyingParty {
+                id: self.config.rp_id.clone(),
+                name: self.config.rp_name.clone(),
+            },
+            user: UserEntity {
+                id: URL_SAFE_NO_PAD.encode(user.id.to_be_bytes()),
+                name: user.email.clone(),
+                display_name: user.name.clone(),
+            },
+            pub_key_cred_params: vec![PublicKeyParameters {
+                credential_type: "public-key".to_owned(),
+                alg: COSE_ALG_ES256,
+            }],
+            timeout: CEREMONY_TIMEOUT_MS,
+            attestation: "none".to_owned(),
+            exclude_credentials,
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "discouraged".to_owned(),
+                user_verification: USER_VERIFICATION.to_owned(),
+            },
+        })
+    }
+
+    async fn finish_registration(&self, user_id: i32, name: &str, challenge: &str, response: RegistrationResponse) -> Result<WebauthnCredential, WebauthnError> {
+        let client_data_json = decode("clientDataJSON", &response.response.client_data_json)?;
+        self.verify_client_data(&client_data_json, "webauthn.create", challenge)?;
+
+        // Attestation statements are not verified, "none" attestation is requested anyway
+        let attestation_object = decode("attestationObject", &response.response.attestation_object)?;
+        let attestation: Value = ciborium::from_reader(attestation_object.as_slice())
+            .map_err(|e| WebauthnError::InvalidResponse(format!("Cannot parse attestation object: {}", e)))?;
+        let auth_data = cbor_map_get(&attestation, Value::from("authData"))
+            .and_then(|v| v.as_bytes())
+            .ok_or_else(|| WebauthnError::InvalidResponse("Attestation object contains no authenticator data".to_owned()))?;
+
+        let auth_data = self.verify_authenticator_data(auth_data)?;
+        let (credential_id, public_key) = auth_data.credential()?;
+        let credential_id = URL_SAFE_NO_PAD.encode(credential_id);
+        if credential_id != response.id.trim_end_matches('=') {
+            return Err(WebauthnError::InvalidResponse("Credential id does not match the authenticator data".to_owned()));
+        }
+
+        let db = self.db_config.get_database().to_owned();
+        let owned_name = name.to_owned();
+        let sign_count = auth_data.sign_count;
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute(
+                "INSERT INTO webauthn_credentials (user_id, name, credential_id, public_key, sign_count, created_at) values (?1, ?2, ?3, ?4, ?5, ?6)",
+                (user_id, owned_name, credential_id, public_key, sign_count, Utc::now())
+            )?;
+
+            let id = conn.last_insert_rowid();
+            Ok(conn.query_row(&format!("SELECT {} FROM webauthn_credentials WHERE id = ?1", CREDENTIAL_COLUMNS), [id], map_credential)?)
+        }).await?
+    }
+
+    async fn start_authentication(&self, user_id: i32) -> Result<RequestOptions, WebauthnError> {
+        let allow_credentials = self.find_by_user_id(user_id).await?
+            .iter()
+            .map(|c| CredentialDescriptor::public_key(&c.credential_id))
+            .collect();
+
+        Ok(RequestOptions {
+            challenge: new_challenge(),
+            rp_id: self.config.rp_id.clone(),
+            allow_credentials,
+            user_verification: USER_VERIFICATION.to_owned(),
+            timeout: CEREMONY_TIMEOUT_MS,
+        })
+    }
+
+    async fn finish_authentication(&self, user_id: i32, challenge: &str, response: AssertionResponse) -> Result<WebauthnCredential, WebauthnError> {
+        let db = self.db_config.get_database().to_owned();
+        let credential_id = response.id.trim_end_matches('=').to_owned();
+        let credential = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<WebauthnCredential, WebauthnError>(conn.query_row(
+                &format!("SELECT {} FROM webauthn_credentials WHERE credential_id = ?1 AND user_id = ?2", CREDENTIAL_COLUMNS),
+                (credential_id, user_id),
+                map_credential
+            )?)
+        }).await??;
+
+        let client_data_json = decode("clientDataJSON", &response.response.client_data_json)?;
+        self.verify_client_data(&client_data_json, "webauthn.get", challenge)?;
+
+        let raw_auth_data = decode("authenticatorData", &response.response.authenticator_data)?;
+        let auth_data = self.verify_authenticator_data(&raw_auth_data)?;
+
+        let signature = decode("signature", &response.response.signature)?;
+        let signature = Signature::from_der(&signature)
+            .map_err(|_| WebauthnError::InvalidResponse("Signature is not DER encoded".to_owned()))?;
+        let key = VerifyingKey::from_sec1_bytes(&credential.public_key)
+            .map_err(|e| WebauthnError::Database(format!("Stored public key is invalid: {}", e)))?;
+
+        let mut signed_data = raw_auth_data.clone();
+        signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+        key.verify(&signed_data, &signature)
+            .map_err(|_| WebauthnError::InvalidResponse("Signature is invalid".to_owned()))?;
+
+        // A counter that does not increase indicates a cloned authenticator. Keys without a counter always send 0.
+        let sign_count = auth_data.sign_count;
+        if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+            log::warn!("Sign count of security key {} did not increase, the key may be cloned", credential.id);
+            return Err(WebauthnError::InvalidResponse("Sign count did not increase".to_owned()));
+        }
+
+        let db = self.db_config.get_database().to_owned();
+        let mut credential = credential;
+        let now = Utc::now();
+        let id = credential.id;
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            conn.execute("UPDATE webauthn_credentials SET sign_count = ?1, last_used_at = ?2 WHERE id = ?3", (sign_count, now, id))?;
+            Ok::<(), WebauthnError>(())
+        }).await??;
+
+        credential.sign_count = sign_count;
+        credential.last_used_at = Some(now);
+        Ok(credential)
+    }
+
+    async fn find_by_user_id(&self, user_id: i32) -> Result<Vec<WebauthnCredential>, WebauthnError> {
+        let db = self.db_config.get_database().to_owned();
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+
+            let mut stmt = conn.prepare(&format!("SELECT {} FROM webauthn_credentials WHERE user_id = ?1 ORDER BY id", CREDENTIAL_COLUMNS))?;
+            let credentials = stmt.query_map([user_id], map_credential)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(credentials)
+        }).await?
+    }
+
+    async fn delete(&self, user_id: i32, id: i32) -> Result<(), WebauthnError> {
+        let db = self.db_config.get_database().to_owned();
+        let deleted = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db)?;
+            Ok::<usize, WebauthnError>(conn.execute("DELETE FROM webauthn_credentials WHERE id = ?1 AND user_id = ?2", (id, user_id))?)
+        }).await??;
+
+        if deleted == 0 {
+            Err(WebauthnError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A software authenticator that creates the same responses as a browser with a security key
+#[cfg(test)]
+pub mod soft_authenticator {
+    use argon2::password_hash::rand_core::{OsRng, RngCore};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use ciborium::Value;
+    use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    pub struct SoftAuthenticator {
+        key: SigningKey,
+        credential_id: Vec<u8>,
+        sign_count: u32,
+    }
+
+    impl SoftAuthenticator {
+        pub fn new() -> Self {
+            let mut credential_id = vec![0u8; 16];
+            OsRng.fill_bytes(&mut credential_id);
+
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                credential_id,
+                sign_count: 0,
+            }
+        }
+
+        pub fn credential_id(&self) -> String {
+            URL_SAFE_NO_PAD.encode(&self.credential_id)
+        }
+
+        fn client_data(ceremony: &str, challenge: &str, origin: &str) -> Vec<u8> {
+            json!({ "type": ceremony, "challenge": challenge, "origin": origin, "crossOrigin": false })
+                .to_string()
+                .into_bytes()
+        }
+
+        fn auth_data(&mut self, rp_id: &str, flags: u8) -> Vec<u8> {
+            self.sign_count += 1;
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            data
+        }
+
+        /// Response of `navigator.credentials.create()`
+        pub fn register(&mut self, rp_id: &str, origin: &str, challenge: &str) -> serde_json::Value {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            let cose_key = Value::Map(vec![
+                (Value::from(1), Value::from(2)),
+                (Value::from(3), Value::from(-7)),
+                (Value::from(-1), Value::from(1)),
+                (Value::from(-2), Value::Bytes(point.x().unwrap().to_vec())),
+                (Value::from(-3), Value::Bytes(point.y().unwrap().to_vec())),
+            ]);
+
+            let mut auth_data = self.auth_data(rp_id, 0x41);
+            auth_data.extend_from_slice(&[0u8; 16]);
+            auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            auth_data.extend_from_slice(&self.credential_id);
+            ciborium::into_writer(&cose_key, &mut auth_data).unwrap();
+
+            let attestation = Value::Map(vec![
+                (Value::from("fmt"), Value::from("none")),
+                (Value::from("attStmt"), Value::Map(vec![])),
+                (Value::from("authData"), Value::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = Vec::new();
+            ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+
+            json!({
+                "id": self.credential_id(),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": URL_SAFE_NO_PAD.encode(Self::client_data("webauthn.create", challenge, origin)),
+                    "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object),
+                }
+            })
+        }
+
+        /// Response of `navigator.credentials.get()`
+        pub fn assert(&mut self, rp_id: &str, origin: &str, challenge: &str) -> serde_json::Value {
+            let client_data = Self::client_data("webauthn.get", challenge, origin);
+            let auth_data = self.auth_data(rp_id, 0x01);
+
+            let mut signed_data = auth_data.clone();
+            signed_data.extend_from_slice(&Sha256::digest(&client_data));
+            let signature: Signature = self.key.sign(&signed_data);
+
+            json!({
+                "id": self.credential_id(),
+                "type": "public-key",
+                "response": {
+                    "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                    "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                    "signature": URL_SAFE_NO_PAD.encode(signature.to_der()),
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{config::{config::Config, db::DbConfig}, create_db, domain::{user::User, user_api::UserApi, webauthn_api::WebauthnApi}, error::errors::WebauthnError, service::user_service::UserService};
+
+    use super::{soft_authenticator::SoftAuthenticator, WebauthnService};
+
+    #[tokio::test]
+    async fn should_register_key_and_verify_assertions() {
+        let db_config = Arc::new(DbConfig::new("file:webauthn_service_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let config = Config::default().webauthn;
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let webauthn = WebauthnService::new(Arc::clone(&db_config), config.clone());
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let mut authenticator = SoftAuthenticator::new();
+
+        let options = webauthn.start_registration(&user).await.unwrap();
+        let response = authenticator.register(&config.rp_id, &config.origin, &options.challenge);
+        let credential = webauthn.finish_registration(user.id, "YubiKey", &options.challenge, serde_json::from_value(response).unwrap()).await.unwrap();
+
+        let options = webauthn.start_authentication(user.id).await.unwrap();
+        let response = authenticator.assert(&config.rp_id, &config.origin, &options.challenge);
+        let used = webauthn.finish_authentication(user.id, &options.challenge, serde_json::from_value(response.clone()).unwrap()).await.unwrap();
+
+        assert_eq!(options.allow_credentials[0].id, credential.credential_id);
+        assert_eq!(used.sign_count, 2);
+        assert!(used.last_used_at.is_some());
+        // replaying the same assertion must fail because of the sign count
+        assert!(matches!(
+            webauthn.finish_authentication(user.id, &options.challenge, serde_json::from_value(response).unwrap()).await,
+            Err(WebauthnError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_reject_wrong_challenge_and_origin() {
+        let db_config = Arc::new(DbConfig::new("file:webauthn_service_reject_test?mode=memory&cache=shared"));
+        let _db = create_db(&db_config);
+        let config = Config::default().webauthn;
+        let user_service = UserService::new(Arc::clone(&db_config));
+        let webauthn = WebauthnService::new(Arc::clone(&db_config), config.clone());
+        let user = user_service.save_user_with_credentials(User::new(0, "test@example.org".to_owned(), "Hans".to_owned()), "test123").await.unwrap();
+        let mut authenticator = SoftAuthenticator::new();
+
+        let options = webauthn.start_registration(&user).await.unwrap();
+        let response = authenticator.register(&config.rp_id, "https://evil.example.org", &options.challenge);
+        assert!(matches!(
+            webauthn.finish_registration(user.id, "YubiKey", &options.challenge, serde_json::from_value(response).unwrap()).await,
+            Err(WebauthnError::InvalidResponse(_))
+        ));
+
+        let response = authenticator.register(&config.rp_id, &config.origin, "some-other-challenge");
+        assert!(matches!(
+            webauthn.finish_registration(user.id, "YubiKey", &options.challenge, serde_json::from_value(response).unwrap()).await,
+            Err(WebauthnError::InvalidResponse(_))
+        ));
+        assert!(webauthn.find_by_user_id(user.id).await.unwrap().is_empty());
+    }
+}